@@ -0,0 +1,136 @@
+use crate::error::{PatchError, Result};
+use crate::source::SourceWorkspacePath;
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Directory, relative to cargo's target directory, that archives are extracted into.
+///
+/// Patch entries point into it, so it has to outlive the command; living under the target
+/// directory keeps it out of version control and lets `cargo clean` remove it.
+pub const ARCHIVE_EXTRACT_DIR: &str = "patch-source";
+
+/// Supported archive formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Detect the archive format from its file name
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") || name.ends_with(".crate") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Extract an archive into cargo's target directory and return the workspace inside it
+pub fn extract_archive(archive: &Path, target_dir: &Path) -> Result<SourceWorkspacePath> {
+    extract_archive_into(archive, &target_dir.join(ARCHIVE_EXTRACT_DIR))
+}
+
+/// Extract an archive into a temporary directory, e.g. for a dry run that must not write next
+/// to the manifest. The workspace inside it is removed when the directory is dropped.
+pub fn extract_archive_to_temp(archive: &Path) -> Result<(TempDir, SourceWorkspacePath)> {
+    let dir = TempDir::new().map_err(|e| PatchError::ArchiveError {
+        path: archive.to_path_buf(),
+        message: e.to_string(),
+    })?;
+    let workspace = extract_archive_into(archive, dir.path())?;
+    Ok((dir, workspace))
+}
+
+fn extract_archive_into(archive: &Path, extract_root: &Path) -> Result<SourceWorkspacePath> {
+    let kind = ArchiveKind::from_path(archive).ok_or_else(|| PatchError::UnsupportedArchive {
+        path: archive.to_path_buf(),
+    })?;
+    let archive_error = |message: String| PatchError::ArchiveError {
+        path: archive.to_path_buf(),
+        message,
+    };
+
+    fs::create_dir_all(extract_root).map_err(|e| archive_error(e.to_string()))?;
+
+    // Unpack into a scratch directory first so a failed extraction never leaves a partial source
+    let scratch = tempfile::Builder::new()
+        .prefix(".extract-")
+        .tempdir_in(extract_root)
+        .map_err(|e| archive_error(e.to_string()))?;
+
+    let file = File::open(archive).map_err(|e| archive_error(e.to_string()))?;
+    match kind {
+        ArchiveKind::TarGz => tar::Archive::new(GzDecoder::new(file))
+            .unpack(scratch.path())
+            .map_err(|e| archive_error(e.to_string()))?,
+        ArchiveKind::Zip => zip::ZipArchive::new(file)
+            .and_then(|mut zip| zip.extract(scratch.path()))
+            .map_err(|e| archive_error(e.to_string()))?,
+    }
+
+    let destination = extract_root.join(archive_stem(archive));
+    if destination.exists() {
+        fs::remove_dir_all(&destination).map_err(|e| archive_error(e.to_string()))?;
+    }
+    fs::rename(scratch.path(), &destination).map_err(|e| archive_error(e.to_string()))?;
+
+    Ok(SourceWorkspacePath::new(workspace_root(destination)))
+}
+
+/// File name of the archive without its archive extension
+fn archive_stem(archive: &Path) -> String {
+    let name = archive
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    [".tar.gz", ".tgz", ".crate", ".zip"]
+        .iter()
+        .find_map(|ext| {
+            let split = name.len().checked_sub(ext.len())?;
+            let (stem, suffix) = (name.get(..split)?, name.get(split..)?);
+            suffix.eq_ignore_ascii_case(ext).then(|| stem.to_string())
+        })
+        .unwrap_or(name)
+}
+
+/// Archives usually wrap their contents in a single top-level directory; look inside it
+fn workspace_root(extracted: PathBuf) -> PathBuf {
+    if extracted.join("Cargo.toml").exists() {
+        return extracted;
+    }
+
+    let entries: Vec<_> = fs::read_dir(&extracted)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+        .unwrap_or_default();
+
+    match entries.as_slice() {
+        [single] if single.join("Cargo.toml").exists() => single.clone(),
+        _ => extracted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_kind_and_stem_from_file_name() {
+        let tar = Path::new("dist/rattler-1.0.0.tar.gz");
+        assert_eq!(ArchiveKind::from_path(tar), Some(ArchiveKind::TarGz));
+        assert_eq!(archive_stem(tar), "rattler-1.0.0");
+
+        let zip = Path::new("rattler.ZIP");
+        assert_eq!(ArchiveKind::from_path(zip), Some(ArchiveKind::Zip));
+        assert_eq!(archive_stem(zip), "rattler");
+
+        assert_eq!(ArchiveKind::from_path(Path::new("rattler.rar")), None);
+    }
+}
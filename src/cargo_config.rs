@@ -0,0 +1,124 @@
+use crate::error::{PatchError, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+
+/// Config file names cargo looks for inside each `.cargo` directory, in priority order
+const CONFIG_FILE_NAMES: &[&str] = &["config.toml", "config"];
+
+/// Resolve the index URL of a named registry the same way cargo does.
+///
+/// Checks the `CARGO_REGISTRIES_<NAME>_INDEX` environment variable first, then
+/// `.cargo/config.toml` files in `start_dir` and its ancestors, and finally `$CARGO_HOME`.
+pub fn resolve_registry_index(registry: &str, start_dir: &Path) -> Result<Option<String>> {
+    let env_key = format!(
+        "CARGO_REGISTRIES_{}_INDEX",
+        registry.to_uppercase().replace('-', "_")
+    );
+    if let Ok(index) = std::env::var(env_key) {
+        return Ok(Some(index));
+    }
+
+    for config_path in config_file_candidates(start_dir) {
+        if !config_path.exists() {
+            continue;
+        }
+
+        let content =
+            fs::read_to_string(&config_path).map_err(|e| PatchError::ConfigReadError {
+                path: config_path.clone(),
+                source: e,
+            })?;
+        let doc = content
+            .parse::<DocumentMut>()
+            .map_err(|e| PatchError::ConfigParseError {
+                path: config_path.clone(),
+                source: e,
+            })?;
+
+        let index = doc
+            .get("registries")
+            .and_then(|r| r.get(registry))
+            .and_then(|r| r.get("index"))
+            .and_then(|i| i.as_str());
+
+        if let Some(index) = index {
+            return Ok(Some(index.to_string()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The `.cargo/config.toml` that patches for the manifest in `manifest_dir` are written to
+pub fn local_config_path(manifest_dir: &Path) -> PathBuf {
+    manifest_dir.join(".cargo").join(CONFIG_FILE_NAMES[0])
+}
+
+/// Read a cargo config file, treating a missing file as empty
+pub fn read_config(path: &Path) -> Result<DocumentMut> {
+    if !path.exists() {
+        return Ok(DocumentMut::new());
+    }
+
+    let content = fs::read_to_string(path).map_err(|e| PatchError::ConfigReadError {
+        path: path.to_path_buf(),
+        source: e,
+    })?;
+    content
+        .parse::<DocumentMut>()
+        .map_err(|e| PatchError::ConfigParseError {
+            path: path.to_path_buf(),
+            source: e,
+        })
+}
+
+/// Write a cargo config file, deleting it instead when nothing is left in it
+pub fn write_config(path: &Path, doc: &DocumentMut) -> Result<()> {
+    let content = doc.to_string();
+    let write_error = |e| PatchError::ConfigWriteError {
+        path: path.to_path_buf(),
+        source: e,
+    };
+
+    if content.trim().is_empty() {
+        if path.exists() {
+            fs::remove_file(path).map_err(write_error)?;
+        }
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(write_error)?;
+    }
+    fs::write(path, content).map_err(write_error)
+}
+
+/// All config files cargo would consult for `start_dir`, most specific first
+fn config_file_candidates(start_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = start_dir
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .collect();
+
+    if let Some(cargo_home) = cargo_home() {
+        if !dirs.contains(&cargo_home) {
+            dirs.push(cargo_home);
+        }
+    }
+
+    dirs.iter()
+        .flat_map(|dir| CONFIG_FILE_NAMES.iter().map(move |name| dir.join(name)))
+        .collect()
+}
+
+/// Location of `$CARGO_HOME`, defaulting to `~/.cargo`
+fn cargo_home() -> Option<PathBuf> {
+    if let Some(home) = std::env::var_os("CARGO_HOME") {
+        return Some(PathBuf::from(home));
+    }
+
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".cargo"))
+}
@@ -1,7 +1,12 @@
 use crate::error::{PatchError, Result};
+use crate::toml_ops::{is_workspace, read_cargo_toml};
+use cargo_metadata::semver::{Version, VersionReq};
 use cargo_metadata::MetadataCommand;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
 use std::path::Path;
+use std::process::Command;
 
 /// Information about a crate that can be patched
 #[derive(Debug, Clone)]
@@ -9,10 +14,130 @@ pub struct CrateInfo {
     pub name: String,
     pub version: String,
     pub manifest_path: std::path::PathBuf,
+    /// SPDX license expression from the crate's manifest, if it declares one
+    pub license: Option<String>,
+}
+
+impl CrateInfo {
+    /// The package root cargo resolves `[patch]` paths to.
+    ///
+    /// This is always the directory of the manifest cargo metadata reports, wherever the
+    /// manifest puts its targets (`[lib] path`, `[[bin]] path`, `build`, ...).
+    pub fn package_root(&self) -> &Path {
+        self.manifest_path
+            .parent()
+            .expect("Crate manifest should have a parent directory")
+    }
+}
+
+/// Options forwarded to every `cargo metadata` invocation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataOptions {
+    /// Pass `--offline` so cargo never touches the network
+    pub offline: bool,
+    /// Pass `--frozen` so cargo neither touches the network nor updates `Cargo.lock`
+    pub frozen: bool,
+    /// `KEY=VALUE` overrides passed to cargo as `--config`
+    pub config: Vec<String>,
+}
+
+impl MetadataOptions {
+    /// Extra command line arguments for `cargo metadata`
+    pub fn other_options(&self) -> Vec<String> {
+        let mut options = Vec::new();
+        if self.offline {
+            options.push("--offline".to_string());
+        }
+        if self.frozen {
+            options.push("--frozen".to_string());
+        }
+        for config in &self.config {
+            options.push("--config".to_string());
+            options.push(config.clone());
+        }
+        options
+    }
+
+    /// Build a `cargo metadata` command for the given manifest
+    pub fn command(&self, manifest_path: &Path) -> MetadataCommand {
+        let mut command = MetadataCommand::new();
+        command
+            .manifest_path(manifest_path)
+            .other_options(self.other_options());
+        command
+    }
+}
+
+/// Run `cargo update -p <crate>` for the given crates so `Cargo.lock` picks up new patches.
+///
+/// Uses `$CARGO` when set, like cargo does for its subcommands.
+pub fn update_lockfile(
+    manifest_path: &Path,
+    crates: &[String],
+    metadata_options: &MetadataOptions,
+) -> Result<()> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut command = Command::new(cargo);
+    command
+        .arg("update")
+        .arg("--manifest-path")
+        .arg(manifest_path)
+        .args(metadata_options.other_options());
+    for name in crates {
+        command.arg("-p").arg(name);
+    }
+
+    let output = command.output().map_err(|e| PatchError::LockUpdateError {
+        message: e.to_string(),
+    })?;
+    if !output.status.success() {
+        return Err(PatchError::LockUpdateError {
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// The cargo feature a `[patch]` key relies on and the first cargo release supporting it
+pub fn patch_key_required_cargo(patch_key: &str) -> Option<(&'static str, Version)> {
+    patch_key
+        .starts_with("sparse+")
+        .then(|| ("sparse registry patch keys", Version::new(1, 68, 0)))
+}
+
+/// The version of the cargo used for subcommands (`$CARGO`, else `cargo` on `PATH`)
+pub fn installed_cargo_version() -> Option<Version> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = Command::new(cargo).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_cargo_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse `cargo --version` output such as `cargo 1.75.0 (1d8b05cdd 2023-11-20)`
+pub fn parse_cargo_version(output: &str) -> Option<Version> {
+    let version = output
+        .trim()
+        .strip_prefix("cargo ")?
+        .split_whitespace()
+        .next()?;
+    Version::parse(version).ok()
+}
+
+/// Whether `installed` is at least `required`.
+///
+/// Nightly and beta builds count as their release, so `1.68.0-nightly` satisfies `1.68.0`.
+pub fn cargo_version_at_least(installed: &Version, required: &Version) -> bool {
+    (installed.major, installed.minor, installed.patch)
+        >= (required.major, required.minor, required.patch)
 }
 
 /// Query metadata for a workspace at the given path
-pub fn query_workspace_crates(workspace_path: &Path) -> Result<Vec<CrateInfo>> {
+pub fn query_workspace_crates(
+    workspace_path: &Path,
+    metadata_options: &MetadataOptions,
+) -> Result<Vec<CrateInfo>> {
     let manifest_path = workspace_path.join("Cargo.toml");
 
     if !manifest_path.exists() {
@@ -21,8 +146,8 @@ pub fn query_workspace_crates(workspace_path: &Path) -> Result<Vec<CrateInfo>> {
         });
     }
 
-    let metadata = MetadataCommand::new()
-        .manifest_path(&manifest_path)
+    let metadata = metadata_options
+        .command(&manifest_path)
         .exec()
         .map_err(|e| PatchError::CargoMetadataError { source: e })?;
 
@@ -33,6 +158,7 @@ pub fn query_workspace_crates(workspace_path: &Path) -> Result<Vec<CrateInfo>> {
             name: pkg.name.clone(),
             version: pkg.version.to_string(),
             manifest_path: pkg.manifest_path.clone().into_std_path_buf(),
+            license: pkg.license.clone(),
         })
         .collect();
 
@@ -45,33 +171,532 @@ pub fn query_workspace_crates(workspace_path: &Path) -> Result<Vec<CrateInfo>> {
     Ok(workspace_members)
 }
 
-/// Filter crates by pattern (supports wildcards)
-pub fn filter_crates_by_pattern(
-    crates: Vec<CrateInfo>,
-    pattern: Option<&str>,
+/// Versions pinned in a workspace's `Cargo.lock` for its own (path) packages, by name
+pub fn read_lockfile_versions(workspace_path: &Path) -> Result<HashMap<String, String>> {
+    let lock_path = workspace_path.join("Cargo.lock");
+    if !lock_path.exists() {
+        return Err(PatchError::SourceLockNotFound { path: lock_path });
+    }
+    let content = fs::read_to_string(&lock_path).map_err(|e| PatchError::LockfileReadError {
+        path: lock_path.clone(),
+        source: e,
+    })?;
+    let doc: toml_edit::DocumentMut =
+        content
+            .parse()
+            .map_err(|e| PatchError::LockfileParseError {
+                path: lock_path.clone(),
+                source: e,
+            })?;
+
+    let mut versions = HashMap::new();
+    let packages = doc
+        .get("package")
+        .and_then(toml_edit::Item::as_array_of_tables);
+    for package in packages.into_iter().flatten() {
+        // Packages from a registry or git have a `source`; workspace members don't
+        if package.contains_key("source") {
+            continue;
+        }
+        let name = package.get("name").and_then(toml_edit::Item::as_str);
+        let version = package.get("version").and_then(toml_edit::Item::as_str);
+        if let (Some(name), Some(version)) = (name, version) {
+            versions.insert(name.to_string(), version.to_string());
+        }
+    }
+    Ok(versions)
+}
+
+/// Members of the workspace `manifest_path` belongs to, without resolving dependencies
+pub fn query_workspace_members(
+    manifest_path: &Path,
+    metadata_options: &MetadataOptions,
 ) -> Result<Vec<CrateInfo>> {
-    let Some(pattern) = pattern else {
-        return Ok(crates);
+    let mut command = metadata_options.command(manifest_path);
+    let metadata = command
+        .no_deps()
+        .exec()
+        .map_err(|e| PatchError::CargoMetadataError { source: e })?;
+
+    Ok(metadata
+        .workspace_packages()
+        .into_iter()
+        .map(|pkg| CrateInfo {
+            name: pkg.name.clone(),
+            version: pkg.version.to_string(),
+            manifest_path: pkg.manifest_path.clone().into_std_path_buf(),
+            license: pkg.license.clone(),
+        })
+        .collect())
+}
+
+/// Query metadata for a workspace and for every workspace nested below it.
+///
+/// `cargo metadata` only reports the members of the workspace it is run on, so sub-workspaces
+/// in a monorepo are queried separately. When a crate name appears more than once, the
+/// outermost workspace wins.
+pub fn query_workspace_crates_recursive(
+    workspace_path: &Path,
+    metadata_options: &MetadataOptions,
+) -> Result<Vec<CrateInfo>> {
+    let mut crates = query_workspace_crates(workspace_path, metadata_options)?;
+    let mut seen: HashSet<String> = crates.iter().map(|c| c.name.clone()).collect();
+
+    for nested in find_nested_workspaces(workspace_path) {
+        for crate_info in query_workspace_crates(&nested, metadata_options)? {
+            if seen.insert(crate_info.name.clone()) {
+                crates.push(crate_info);
+            }
+        }
+    }
+
+    Ok(crates)
+}
+
+/// Crates whose `[patch]` entries in the manifest are not part of its resolved dependency graph.
+///
+/// Runs `cargo metadata`, so the graph must resolve. A path patch counts as used when a resolved
+/// package of that name lives at the patch path, a git patch when one comes from that
+/// repository, and any other patch when a package of that name is resolved at all.
+pub fn find_unused_patches(manifest_path: &Path) -> Result<Vec<String>> {
+    let doc = read_cargo_toml(manifest_path)?;
+    let manifest_dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+    let metadata = MetadataOptions::default()
+        .command(manifest_path)
+        .exec()
+        .map_err(|e| PatchError::CargoMetadataError { source: e })?;
+
+    let resolved: HashSet<_> = metadata
+        .resolve
+        .as_ref()
+        .map(|resolve| resolve.nodes.iter().map(|node| &node.id).collect())
+        .unwrap_or_default();
+    let packages: Vec<_> = metadata
+        .packages
+        .iter()
+        .filter(|package| resolved.contains(&package.id))
+        .collect();
+
+    let mut unused = BTreeSet::new();
+    let Some(patch_table) = doc.get("patch").and_then(|p| p.as_table_like()) else {
+        return Ok(Vec::new());
     };
+    for (_, source) in patch_table.iter() {
+        let Some(source) = source.as_table_like() else {
+            continue;
+        };
+        for (name, entry) in source.iter() {
+            let package_name = entry
+                .get("package")
+                .and_then(|p| p.as_str())
+                .unwrap_or(name);
+            let path = entry
+                .get("path")
+                .and_then(|p| p.as_str())
+                .map(|path| canonical(&manifest_dir.join(path)));
+            let git = entry.get("git").and_then(|g| g.as_str());
+
+            let used = packages.iter().any(|package| {
+                if package.name != package_name {
+                    return false;
+                }
+                match (&path, git) {
+                    (Some(path), _) => package
+                        .manifest_path
+                        .parent()
+                        .is_some_and(|dir| canonical(dir.as_std_path()) == *path),
+                    (None, Some(git)) => package.source.as_ref().is_some_and(|source| {
+                        source
+                            .repr
+                            .starts_with(&format!("git+{}", git.trim_end_matches('/')))
+                    }),
+                    (None, None) => true,
+                }
+            });
+            if !used {
+                unused.insert(name.to_string());
+            }
+        }
+    }
+
+    Ok(unused.into_iter().collect())
+}
+
+/// Root of the workspace `manifest_path` belongs to, when that is not the manifest itself.
+///
+/// Cargo only reads `[patch]` from the workspace root. Returns `None` when cargo cannot load
+/// the manifest, leaving the real error to the later metadata queries.
+pub fn parent_workspace_root(
+    manifest_path: &Path,
+    metadata_options: &MetadataOptions,
+) -> Option<std::path::PathBuf> {
+    let mut command = metadata_options.command(manifest_path);
+    let metadata = command.no_deps().exec().ok()?;
+    let manifest_dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+    let workspace_root = metadata.workspace_root.into_std_path_buf();
+    (canonical(&workspace_root) != canonical(manifest_dir)).then_some(workspace_root)
+}
+
+/// The target directory cargo builds `manifest_path` into, honoring `CARGO_TARGET_DIR` and
+/// `build.target-dir`; a workspace member shares the target directory of its workspace
+pub fn target_directory(
+    manifest_path: &Path,
+    metadata_options: &MetadataOptions,
+) -> Option<std::path::PathBuf> {
+    // Cargo looks up `.cargo/config.toml` from the working directory, not the manifest
+    let manifest_path = canonical(manifest_path);
+    let mut command = metadata_options.command(&manifest_path);
+    if let Some(manifest_dir) = manifest_path.parent() {
+        command.current_dir(manifest_dir);
+    }
+    let metadata = command.no_deps().exec().ok()?;
+    Some(metadata.target_directory.into_std_path_buf())
+}
+
+/// `path` with symlinks resolved, or as given if it doesn't exist
+fn canonical(path: &Path) -> std::path::PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Directories below `root` whose `Cargo.toml` declares a `[workspace]`, outermost first
+fn find_nested_workspaces(root: &Path) -> Vec<std::path::PathBuf> {
+    let mut workspaces = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
 
-    let re = glob_pattern_regex(pattern)?;
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut subdirs: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name != "target" && !name.starts_with('.'))
+            })
+            .collect();
+        subdirs.sort();
+
+        for subdir in subdirs {
+            let is_workspace_root =
+                read_cargo_toml(&subdir.join("Cargo.toml")).is_ok_and(|doc| is_workspace(&doc));
+            if is_workspace_root {
+                workspaces.push(subdir.clone());
+            }
+            pending.push(subdir);
+        }
+    }
+
+    workspaces.sort_by_key(|path| path.components().count());
+    workspaces
+}
+
+/// Whether `version` looks like a placeholder rather than a real release (e.g. `0.0.0`)
+pub fn is_placeholder_version(version: &str) -> bool {
+    Version::parse(version).map_or(true, |v| v == Version::new(0, 0, 0))
+}
+
+/// Whether `version` is strictly newer than the lowest version `requirement` allows.
+///
+/// Returns `false` when either cannot be parsed, so there is nothing to compare against.
+pub fn is_newer_than_requirement(version: &str, requirement: &str) -> bool {
+    let (Ok(version), Ok(req)) = (Version::parse(version), VersionReq::parse(requirement)) else {
+        return false;
+    };
+    let Some(lowest) = req.comparators.first() else {
+        return false;
+    };
+
+    let mut required = Version::new(
+        lowest.major,
+        lowest.minor.unwrap_or(0),
+        lowest.patch.unwrap_or(0),
+    );
+    required.pre = lowest.pre.clone();
+    version > required
+}
+
+/// A source crate whose version does not satisfy the target's requirement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionMismatch {
+    pub name: String,
+    pub requirement: String,
+    pub version: String,
+}
+
+/// Find crates whose source version fails the dependency requirement in `requirements`.
+///
+/// Crates without a requirement, or with one that is not valid semver, are ignored.
+pub fn find_version_mismatches(
+    crates: &[CrateInfo],
+    requirements: &HashMap<String, String>,
+) -> Vec<VersionMismatch> {
+    let mut mismatches: Vec<_> = crates
+        .iter()
+        .filter_map(|crate_info| {
+            let requirement = requirements.get(&crate_info.name)?;
+            let req = VersionReq::parse(requirement).ok()?;
+            let version = Version::parse(&crate_info.version).ok()?;
+
+            (!req.matches(&version)).then(|| VersionMismatch {
+                name: crate_info.name.clone(),
+                requirement: requirement.clone(),
+                version: crate_info.version.clone(),
+            })
+        })
+        .collect();
+
+    mismatches.sort_by(|a, b| a.name.cmp(&b.name));
+    mismatches
+}
+
+/// A set of include and `!`-prefixed exclude patterns; excludes win over includes
+#[derive(Debug, Clone)]
+pub struct PatternSet {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl PatternSet {
+    /// Compile the patterns; with no include patterns every name not excluded matches
+    pub fn compile(patterns: &[String], ignore_case: bool) -> Result<Self> {
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        for pattern in patterns {
+            match pattern.strip_prefix('!') {
+                Some(negated) => exclude.push(compile_pattern(negated, ignore_case)?),
+                None => include.push(compile_pattern(pattern, ignore_case)?),
+            }
+        }
+        Ok(Self { include, exclude })
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|re| re.is_match(name));
+        included && !self.exclude.iter().any(|re| re.is_match(name))
+    }
+}
+
+/// A cargo package spec as accepted by `-p`: `name` or `name@version`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageSpec {
+    pub name: String,
+    /// Full or partial version, e.g. `1.0.0` or `1`
+    pub version: Option<String>,
+}
 
-    let filtered: Vec<_> = crates
+impl PackageSpec {
+    /// Parse `name` or `name@version`
+    pub fn parse(spec: &str) -> std::result::Result<Self, String> {
+        let (name, version) = match spec.split_once('@') {
+            Some((name, version)) => (name, Some(version)),
+            None => (spec, None),
+        };
+        if name.is_empty() || version.is_some_and(str::is_empty) {
+            return Err(format!("expected NAME or NAME@VERSION, got `{}`", spec));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            version: version.map(str::to_string),
+        })
+    }
+
+    /// Whether the crate has this name and, if given, a version starting with these components
+    pub fn matches(&self, crate_info: &CrateInfo) -> bool {
+        if crate_info.name != self.name {
+            return false;
+        }
+        let Some(version) = &self.version else {
+            return true;
+        };
+        let mut components = crate_info.version.split('.');
+        version
+            .split('.')
+            .all(|wanted| components.next() == Some(wanted))
+    }
+}
+
+impl std::fmt::Display for PackageSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}@{}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Keep the crates matching one of the package specs; it is an error if none match
+pub fn filter_crates_by_package_spec(
+    crates: Vec<CrateInfo>,
+    specs: &[PackageSpec],
+) -> Result<Vec<CrateInfo>> {
+    if specs.is_empty() {
+        return Ok(crates);
+    }
+
+    let matched: Vec<_> = crates
         .into_iter()
-        .filter(|c| re.is_match(&c.name))
+        .filter(|crate_info| specs.iter().any(|spec| spec.matches(crate_info)))
         .collect();
+    if matched.is_empty() {
+        let specs: Vec<_> = specs.iter().map(PackageSpec::to_string).collect();
+        return Err(PatchError::NoMatchingCrates {
+            pattern: specs.join(", "),
+        });
+    }
+
+    Ok(matched)
+}
+
+/// Whether the SPDX expression `license` mentions one of the ids in `wanted`.
+///
+/// `wanted` is a single id or several joined by `OR` (e.g. `MIT OR Apache-2.0`); ids are
+/// compared case-insensitively, and the old `MIT/Apache-2.0` notation is understood too.
+pub fn license_matches(license: &str, wanted: &str) -> bool {
+    let ids = |expression: &str| -> Vec<String> {
+        expression
+            .split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '/'))
+            .filter(|id| !id.is_empty() && !matches!(*id, "AND" | "OR" | "WITH"))
+            .map(str::to_ascii_lowercase)
+            .collect()
+    };
+    let available = ids(license);
+    ids(wanted).iter().any(|id| available.contains(id))
+}
 
-    if filtered.is_empty() {
+/// Filter crates by patterns (supports wildcards and `!` negation)
+pub fn filter_crates_by_pattern(
+    crates: Vec<CrateInfo>,
+    patterns: &[String],
+    ignore_case: bool,
+) -> Result<Vec<CrateInfo>> {
+    let (matched, _) = partition_crates_by_pattern(crates, patterns, ignore_case)?;
+
+    if matched.is_empty() && !patterns.is_empty() {
         return Err(PatchError::NoMatchingCrates {
-            pattern: pattern.to_string(),
+            pattern: patterns.join(", "),
         });
     }
 
-    Ok(filtered)
+    Ok(matched)
+}
+
+/// Split crates into those matching the patterns and those that don't.
+///
+/// Unlike [`filter_crates_by_pattern`] an empty match is not an error, so callers can report
+/// what the patterns skipped.
+pub fn partition_crates_by_pattern(
+    crates: Vec<CrateInfo>,
+    patterns: &[String],
+    ignore_case: bool,
+) -> Result<(Vec<CrateInfo>, Vec<CrateInfo>)> {
+    if patterns.is_empty() {
+        return Ok((crates, Vec::new()));
+    }
+
+    let set = PatternSet::compile(patterns, ignore_case)?;
+    Ok(crates.into_iter().partition(|c| set.is_match(&c.name)))
+}
+
+/// Crates under the `[workspace] exclude` paths of the source whose name matches the patterns.
+///
+/// `cargo metadata` never lists them, so they explain why a pattern matched nothing. Each
+/// excluded path is checked for a package itself and for packages directly below it.
+pub fn find_excluded_crates(
+    workspace_path: &Path,
+    patterns: &[String],
+    ignore_case: bool,
+) -> Result<Vec<String>> {
+    let doc = read_cargo_toml(&workspace_path.join("Cargo.toml"))?;
+    let Some(excluded) = doc
+        .get("workspace")
+        .and_then(|workspace| workspace.get("exclude"))
+        .and_then(|exclude| exclude.as_array())
+    else {
+        return Ok(Vec::new());
+    };
+
+    let set = PatternSet::compile(patterns, ignore_case)?;
+    let mut found = BTreeSet::new();
+    for dir in excluded.iter().filter_map(|path| path.as_str()) {
+        let dir = workspace_path.join(dir);
+        let children = fs::read_dir(&dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path());
+        for crate_dir in std::iter::once(dir.clone()).chain(children) {
+            let Ok(manifest) = read_cargo_toml(&crate_dir.join("Cargo.toml")) else {
+                continue;
+            };
+            let name = manifest
+                .get("package")
+                .and_then(|package| package.get("name"))
+                .and_then(|name| name.as_str());
+            if let Some(name) = name.filter(|name| set.is_match(name)) {
+                found.insert(name.to_string());
+            }
+        }
+    }
+    Ok(found.into_iter().collect())
+}
+
+/// Read a newline-delimited list of crate names, ignoring blank lines and `#` comments
+pub fn read_crate_list(path: &Path) -> Result<HashSet<String>> {
+    let content = fs::read_to_string(path).map_err(|e| PatchError::CrateListReadError {
+        path: path.to_path_buf(),
+        source: e,
+    })?;
+    Ok(parse_crate_list(&content))
+}
+
+fn parse_crate_list(content: &str) -> HashSet<String> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Read `crate=version` lines, ignoring blank lines and `#` comments
+pub fn read_version_map(path: &Path) -> Result<BTreeMap<String, String>> {
+    let content = fs::read_to_string(path).map_err(|e| PatchError::CrateListReadError {
+        path: path.to_path_buf(),
+        source: e,
+    })?;
+    parse_version_map(&content).map_err(|line| PatchError::InvalidVersionMapLine {
+        path: path.to_path_buf(),
+        line,
+    })
+}
+
+/// Parse `crate=version` lines; versions may be quoted. Returns the first malformed line.
+fn parse_version_map(content: &str) -> std::result::Result<BTreeMap<String, String>, String> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (name, version) = line.split_once('=').ok_or_else(|| line.to_string())?;
+            let (name, version) = (name.trim(), version.trim().trim_matches('"'));
+            if name.is_empty() || version.is_empty() {
+                return Err(line.to_string());
+            }
+            Ok((name.to_string(), version.to_string()))
+        })
+        .collect()
 }
 
 /// Compile a glob-like pattern into a Regex instance.
 pub fn glob_pattern_regex(pattern: &str) -> Result<Regex> {
+    compile_pattern(pattern, false)
+}
+
+/// Compile a glob-like pattern into a Regex instance, optionally ignoring case.
+pub fn compile_pattern(pattern: &str, ignore_case: bool) -> Result<Regex> {
     let mut escaped = String::from("^");
     for ch in pattern.chars() {
         match ch {
@@ -82,10 +707,13 @@ pub fn glob_pattern_regex(pattern: &str) -> Result<Regex> {
     }
     escaped.push('$');
 
-    Regex::new(&escaped).map_err(|e| PatchError::InvalidPattern {
-        pattern: pattern.to_string(),
-        source: e,
-    })
+    RegexBuilder::new(&escaped)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| PatchError::InvalidPattern {
+            pattern: pattern.to_string(),
+            source: e,
+        })
 }
 
 #[cfg(test)]
@@ -99,6 +727,207 @@ mod tests {
         assert!(!re.is_match("crate-name1(test)foo"));
     }
 
+    fn crate_info(name: &str) -> CrateInfo {
+        CrateInfo {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            manifest_path: std::path::PathBuf::from(name).join("Cargo.toml"),
+            license: None,
+        }
+    }
+
+    #[test]
+    fn package_spec_matches_name_and_version_prefix() {
+        let crate_info = crate_info("rattler-one");
+        let spec = |spec: &str| PackageSpec::parse(spec).unwrap();
+
+        assert!(spec("rattler-one").matches(&crate_info));
+        assert!(spec("rattler-one@1.0.0").matches(&crate_info));
+        assert!(spec("rattler-one@1").matches(&crate_info));
+        assert!(!spec("rattler-one@1.0.1").matches(&crate_info));
+        assert!(!spec("rattler-one@1.0.0.0").matches(&crate_info));
+        assert!(!spec("rattler-two@1.0.0").matches(&crate_info));
+        assert!(PackageSpec::parse("rattler-one@").is_err());
+        assert!(PackageSpec::parse("@1.0.0").is_err());
+    }
+
+    #[test]
+    fn cargo_version_comparison_ignores_pre_releases() {
+        let installed =
+            parse_cargo_version("cargo 1.68.0-nightly (f6e737b1e 2022-12-02)\n").unwrap();
+        let required = Version::new(1, 68, 0);
+        assert!(cargo_version_at_least(&installed, &required));
+        assert!(cargo_version_at_least(&Version::new(1, 75, 0), &required));
+        assert!(!cargo_version_at_least(&Version::new(1, 67, 1), &required));
+        assert_eq!(parse_cargo_version("rustc 1.75.0"), None);
+
+        assert_eq!(
+            patch_key_required_cargo("sparse+https://index.example.com/"),
+            Some(("sparse registry patch keys", required))
+        );
+        assert_eq!(patch_key_required_cargo("crates-io"), None);
+    }
+
+    #[test]
+    fn license_matches_any_id_in_the_expression() {
+        assert!(license_matches("MIT OR Apache-2.0", "mit"));
+        assert!(license_matches(
+            "(MIT OR Apache-2.0) AND Unicode-3.0",
+            "Apache-2.0"
+        ));
+        assert!(license_matches(
+            "MIT/Apache-2.0",
+            "BSD-3-Clause OR Apache-2.0"
+        ));
+        assert!(!license_matches("GPL-3.0-only", "MIT OR Apache-2.0"));
+        assert!(!license_matches("MIT", "OR"));
+    }
+
+    #[test]
+    fn filter_crates_by_pattern_ignore_case() {
+        let crates = vec![
+            crate_info("rattler-one"),
+            crate_info("rattler-two"),
+            crate_info("other-crate"),
+        ];
+
+        let pattern = ["RATTLER-*".to_string()];
+        let err = filter_crates_by_pattern(crates.clone(), &pattern, false).unwrap_err();
+        assert!(matches!(err, PatchError::NoMatchingCrates { .. }));
+
+        let matched = filter_crates_by_pattern(crates, &pattern, true).unwrap();
+        let names: Vec<_> = matched.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["rattler-one", "rattler-two"]);
+    }
+
+    #[test]
+    fn parse_crate_list_skips_blanks_and_comments() {
+        let selected = parse_crate_list("# header\nrattler-one\n\n  rattler-two  # note\n");
+        assert_eq!(
+            selected,
+            HashSet::from(["rattler-one".to_string(), "rattler-two".to_string()])
+        );
+    }
+
+    #[test]
+    fn parse_version_map_reads_crate_version_pairs() {
+        let versions =
+            parse_version_map("# saved\nrattler-one = \"1.0.0\"\nrattler-two=2.0\n").unwrap();
+        assert_eq!(
+            versions,
+            BTreeMap::from([
+                ("rattler-one".to_string(), "1.0.0".to_string()),
+                ("rattler-two".to_string(), "2.0".to_string()),
+            ])
+        );
+        assert_eq!(
+            parse_version_map("rattler-one\n").unwrap_err(),
+            "rattler-one"
+        );
+    }
+
+    #[test]
+    fn partition_crates_by_pattern_splits_matched_and_unmatched() {
+        let crates = vec![
+            crate_info("rattler-one"),
+            crate_info("other-crate"),
+            crate_info("rattler-two"),
+        ];
+
+        let patterns = ["rattler-*".to_string()];
+        let (matched, unmatched) = partition_crates_by_pattern(crates, &patterns, false).unwrap();
+        let matched: Vec<_> = matched.iter().map(|c| c.name.as_str()).collect();
+        let unmatched: Vec<_> = unmatched.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(matched, ["rattler-one", "rattler-two"]);
+        assert_eq!(unmatched, ["other-crate"]);
+
+        let patterns = ["nothing-*".to_string()];
+        let (matched, unmatched) =
+            partition_crates_by_pattern(vec![crate_info("a")], &patterns, false).unwrap();
+        assert!(matched.is_empty());
+        assert_eq!(unmatched.len(), 1);
+    }
+
+    #[test]
+    fn pattern_set_excludes_win() {
+        let patterns = ["rattler-*".to_string(), "!rattler-networking".to_string()];
+        let set = PatternSet::compile(&patterns, false).unwrap();
+        assert!(set.is_match("rattler-one"));
+        assert!(!set.is_match("rattler-networking"));
+        assert!(!set.is_match("other-crate"));
+
+        let set = PatternSet::compile(&["!rattler-*".to_string()], false).unwrap();
+        assert!(set.is_match("other-crate"));
+        assert!(!set.is_match("rattler-one"));
+    }
+
+    #[test]
+    fn find_version_mismatches_reports_failing_requirements() {
+        let mut crates = vec![crate_info("a"), crate_info("b"), crate_info("c")];
+        crates[1].version = "2.0.0".to_string();
+        let requirements = HashMap::from([
+            ("a".to_string(), "0.9".to_string()),
+            ("b".to_string(), "^1.0.0".to_string()),
+            ("c".to_string(), "1.0.0".to_string()),
+        ]);
+
+        let mismatches = find_version_mismatches(&crates, &requirements);
+        let names: Vec<_> = mismatches.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(mismatches[1].requirement, "^1.0.0");
+        assert_eq!(mismatches[1].version, "2.0.0");
+    }
+
+    #[test]
+    fn is_placeholder_version_flags_zero_and_unparsable_versions() {
+        assert!(is_placeholder_version("0.0.0"));
+        assert!(is_placeholder_version(""));
+        assert!(!is_placeholder_version("0.0.1"));
+        assert!(!is_placeholder_version("1.2.3-alpha.1"));
+    }
+
+    #[test]
+    fn is_newer_than_requirement_compares_against_the_lowest_allowed_version() {
+        assert!(is_newer_than_requirement("1.1.0", "1.0.0"));
+        assert!(is_newer_than_requirement("1.0.1", "^1.0"));
+        assert!(!is_newer_than_requirement("1.0.0", "1.0.0"));
+        assert!(!is_newer_than_requirement("0.9.0", "=1.0.0"));
+        assert!(!is_newer_than_requirement("1.1.0", ""));
+        assert!(!is_newer_than_requirement("1.1.0", "*"));
+    }
+
+    #[test]
+    fn metadata_options_forward_offline_and_frozen() {
+        assert!(MetadataOptions::default().other_options().is_empty());
+
+        let options = MetadataOptions {
+            offline: true,
+            frozen: true,
+            ..Default::default()
+        };
+        assert_eq!(options.other_options(), ["--offline", "--frozen"]);
+    }
+
+    #[test]
+    fn metadata_options_forward_config_overrides() {
+        let options = MetadataOptions {
+            config: vec![
+                "build.target-dir=\"/tmp/target\"".to_string(),
+                "net.git-fetch-with-cli=true".to_string(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            options.other_options(),
+            [
+                "--config",
+                "build.target-dir=\"/tmp/target\"",
+                "--config",
+                "net.git-fetch-with-cli=true"
+            ]
+        );
+    }
+
     #[test]
     fn glob_pattern_regex_star_matches_slashes() {
         let re = glob_pattern_regex("foo*bar").unwrap();
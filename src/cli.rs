@@ -1,3 +1,9 @@
+use crate::cargo_ops::PackageSpec;
+use crate::git::DEFAULT_GIT_DEPTH;
+use crate::patch::{MatchOn, MergeStrategy, RelativeTo};
+use crate::report::OutputFormat;
+use crate::source::GitReference;
+use crate::toml_ops::{PatchLocation, PatchStyle};
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
@@ -14,20 +20,50 @@ pub enum CargoCli {
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Also print per-crate decisions
+    #[arg(long, short = 'v', global = true, conflicts_with = "quiet")]
+    pub verbose: bool,
+
+    /// Only print warnings and errors
+    #[arg(long, short = 'q', global = true)]
+    pub quiet: bool,
+
+    /// Replace per-crate progress with a one-line summary
+    #[arg(long, global = true, conflicts_with_all = ["verbose", "quiet"])]
+    pub summary_only: bool,
+
+    /// Exit with an error after finishing if any warning was printed
+    #[arg(long, global = true)]
+    pub fail_on_warning: bool,
+
+    /// Never use colors in diagnostics and log output (also set by `NO_COLOR`)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// Run as if started in this directory (used when --manifest-path is omitted)
+    #[arg(long, global = true, value_name = "DIR")]
+    pub root: Option<PathBuf>,
 }
 
+// Parsed once at startup, so the size of `Apply` doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Commands {
     /// Apply patches from a source to the current Cargo.toml
     Apply {
-        /// Local path to a workspace
-        #[arg(long, conflicts_with = "git")]
+        /// Local path to a workspace, relative paths are relative to the manifest's directory
+        #[arg(long, conflicts_with_all = ["git", "archive"])]
         path: Option<PathBuf>,
 
         /// Git repository URL
-        #[arg(long, conflicts_with = "path")]
+        #[arg(long, conflicts_with_all = ["path", "archive"])]
         git: Option<String>,
 
+        /// Archive (.tar.gz or .zip) containing a workspace
+        #[arg(long, conflicts_with_all = ["path", "git"])]
+        archive: Option<PathBuf>,
+
         /// Git branch to use (only with --git)
         #[arg(long, requires = "git")]
         branch: Option<String>,
@@ -40,13 +76,185 @@ pub enum Commands {
         #[arg(long, requires = "git", conflicts_with_all = ["branch", "tag"])]
         rev: Option<String>,
 
-        /// Pattern to filter crates (e.g., "rattler-*")
+        /// Clone the git source to check that crates selected by --pattern exist in it
+        #[arg(long, requires = "git")]
+        verify_source_crate_exists: bool,
+
+        /// Per-crate git reference, e.g. `rattler-one=feature` (a branch) or `rattler-two=tag:v1.0`
+        #[arg(long, requires = "git", value_name = "CRATE=REF", value_parser = parse_crate_ref)]
+        crate_ref: Vec<(String, GitReference)>,
+
+        /// History depth used when cloning a git source to discover its crates
+        #[arg(long, requires = "git", default_value_t = DEFAULT_GIT_DEPTH)]
+        git_depth: u32,
+
+        /// Clone the full git history, e.g. when --rev is not reachable shallowly
+        #[arg(long, requires = "git")]
+        git_no_shallow: bool,
+
+        /// Pattern to filter crates (e.g., "rattler-*"); repeatable, a leading `!` excludes matches
         #[arg(long)]
-        pattern: Option<String>,
+        pattern: Vec<String>,
 
-        /// Path to Cargo.toml to modify (defaults to current directory)
+        /// Select crates whose name starts with this prefix; sugar for `--pattern "<PREFIX>*"`
+        #[arg(long)]
+        prefix: Vec<String>,
+
+        /// Only patch this source crate, given as `NAME` or `NAME@VERSION`; repeatable
+        #[arg(long = "package", short = 'p', conflicts_with = "git", value_name = "SPEC", value_parser = PackageSpec::parse)]
+        package: Vec<PackageSpec>,
+
+        /// Match the patterns case-insensitively
+        #[arg(long)]
+        ignore_case: bool,
+
+        /// Test the patterns against the dependency key or the crate it refers to
+        #[arg(long, value_enum, default_value_t = MatchOn::CrateName)]
+        match_on: MatchOn,
+
+        /// Patch the target dependency TARGET with the source crate SOURCE; repeatable
+        #[arg(long, conflicts_with = "git", value_name = "SOURCE=TARGET", value_parser = parse_name_map)]
+        name_map: Vec<(String, String)>,
+
+        /// File with crate names to patch, one per line (`#` starts a comment)
+        #[arg(long, value_name = "FILE")]
+        select_from: Option<PathBuf>,
+
+        /// Path to Cargo.toml to modify (defaults to current directory); repeat to patch several
+        #[arg(long)]
+        manifest_path: Vec<PathBuf>,
+
+        /// Number of manifests to patch in parallel when several are given
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+        concurrency: u16,
+
+        /// Patch alternative registry dependencies under their registry's index URL
+        #[arg(long)]
+        registry_from_dep: bool,
+
+        /// In a workspace root, only patch `[workspace.dependencies]`, never `[dependencies]`
+        #[arg(long)]
+        workspace_dependencies_only: bool,
+
+        /// Also scan this dependency table, given as a dotted path (e.g. package.metadata.tool-deps); repeatable
+        #[arg(long = "section", value_name = "TABLE")]
+        sections: Vec<String>,
+
+        /// Only patch crates whose source version is newer than the current requirement
+        #[arg(long, conflicts_with = "git")]
+        only_newer: bool,
+
+        /// Only patch crates whose license mentions one of these SPDX ids (e.g. "MIT OR Apache-2.0")
+        #[arg(long, conflicts_with = "git", value_name = "EXPR")]
+        license: Option<String>,
+
+        /// Set requirements to the versions pinned in the source's Cargo.lock
+        #[arg(long, conflicts_with = "git")]
+        from_source_lock: bool,
+
+        /// Run `cargo update -p <crate>` for the patched crates afterwards
+        #[arg(long)]
+        update_lock: bool,
+
+        /// Record checksums of the patch entries to detect hand edits on the next apply
+        #[arg(long)]
+        record_checksum: bool,
+
+        /// Fail when more than N crates would be patched
+        #[arg(long, value_name = "N")]
+        max_crates: Option<usize>,
+
+        /// Refuse to run when the target manifest has uncommitted git changes
+        #[arg(long)]
+        abort_on_dirty: bool,
+
+        /// Fail if source versions do not satisfy the current dependency requirements, or if
+        /// patch paths point outside the target project
+        #[arg(long)]
+        strict: bool,
+
+        /// Warn if a patch key needs a newer cargo than the installed one (e.g. sparse registries)
+        #[arg(long)]
+        check_cargo_version: bool,
+
+        /// Show what would be patched without writing the manifest
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Keep running and re-apply whenever a file in the --path source changes
+        #[arg(long, conflicts_with_all = ["git", "archive", "dry_run"])]
+        watch: bool,
+
+        /// Write a unified diff of the manifest change to this file
+        #[arg(long, value_name = "FILE")]
+        output_diff_file: Option<PathBuf>,
+
+        /// Also discover crates in workspaces nested inside the source
+        #[arg(long)]
+        recursive: bool,
+
+        /// Print a table of what happens to each candidate crate
+        #[arg(long)]
+        print_plan: bool,
+
+        /// Explain what was done to each candidate crate and why
+        #[arg(long)]
+        explain: bool,
+
+        /// Print the settings after applying presets and defaults, then exit without applying
+        #[arg(long)]
+        print_effective_config: bool,
+
+        /// Output format of the final report
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+
+        /// What to do with crates that already have a patch entry
+        #[arg(long, value_enum, default_value_t = MergeStrategy::Skip)]
+        merge_strategy: MergeStrategy,
+
+        /// Write patch entries inline or as `[patch.<key>.<crate>]` sub-tables
+        #[arg(long, value_enum, default_value_t = PatchStyle::Inline)]
+        patch_style: PatchStyle,
+
+        /// Write local patch paths as absolute, or relative to the manifest or its workspace root
+        #[arg(long, value_enum, default_value_t = RelativeTo::Absolute, conflicts_with = "git")]
+        relative_to: RelativeTo,
+
+        /// Write the patch entries to the manifest or to `.cargo/config.toml`
+        #[arg(long, value_enum, default_value_t = PatchLocation::Manifest)]
+        write_to: PatchLocation,
+
+        /// Store original versions as a table with one crate per line instead of inline
+        #[arg(long)]
+        expanded_metadata: bool,
+
+        /// Run cargo metadata with --offline
+        #[arg(long)]
+        offline: bool,
+
+        /// Run cargo metadata with --frozen
+        #[arg(long)]
+        frozen: bool,
+
+        /// Pass `--config KEY=VALUE` to cargo metadata; repeatable
+        #[arg(long, value_name = "KEY=VALUE")]
+        cargo_config: Vec<String>,
+
+        /// Use a preset from .cargo-patch-source.toml (command line values take precedence)
+        #[arg(long)]
+        preset: Option<String>,
+    },
+
+    /// Write a commented .cargo-patch-source.toml preset template next to the manifest
+    Init {
+        /// Path to the Cargo.toml the config belongs to (defaults to current directory)
         #[arg(long)]
         manifest_path: Option<PathBuf>,
+
+        /// Overwrite an existing config file
+        #[arg(long)]
+        force: bool,
     },
 
     /// Remove patches from the current Cargo.toml
@@ -54,5 +262,137 @@ pub enum Commands {
         /// Path to Cargo.toml to modify (defaults to current directory)
         #[arg(long)]
         manifest_path: Option<PathBuf>,
+
+        /// Show what would be restored and removed without writing the manifest
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Only remove managed patch keys matching this glob (e.g. "https://github.com/org/*")
+        #[arg(long)]
+        key_pattern: Option<String>,
+
+        /// Restore versions from a `crate=version` file instead of the manifest metadata,
+        /// removing every patch entry for those crates
+        #[arg(long, value_name = "FILE", conflicts_with = "key_pattern")]
+        versions_from: Option<PathBuf>,
+
+        /// Also remove patches from every member manifest of the workspace
+        #[arg(long)]
+        workspace: bool,
+
+        /// Only clean workspace members whose package name matches this glob
+        #[arg(long, requires = "workspace")]
+        member_pattern: Option<String>,
+
+        /// Only restore and unpatch this crate, leaving the others patched; repeatable
+        #[arg(long, value_name = "CRATE", conflicts_with = "versions_from")]
+        only: Vec<String>,
+
+        /// Leave an empty `cargo-patch-source` metadata table behind instead of deleting it
+        #[arg(long)]
+        keep_empty_metadata: bool,
+
+        /// Remove entries for every crate in `original-versions` under any patch key, even
+        /// keys missing from `managed-patches`; for recovering from damaged metadata
+        #[arg(long, conflicts_with_all = ["key_pattern", "versions_from", "only"])]
+        all_keys: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
     },
+
+    /// Check that a source workspace resolves and provides crates, without a target manifest
+    Validate {
+        /// Local path to a workspace
+        #[arg(long)]
+        path: PathBuf,
+
+        /// Pattern to filter crates (e.g., "rattler-*"); repeatable, a leading `!` excludes matches
+        #[arg(long)]
+        pattern: Vec<String>,
+
+        /// Match the patterns case-insensitively
+        #[arg(long)]
+        ignore_case: bool,
+
+        /// Also discover crates in workspaces nested inside the source
+        #[arg(long)]
+        recursive: bool,
+
+        /// Run cargo metadata with --offline
+        #[arg(long)]
+        offline: bool,
+
+        /// Run cargo metadata with --frozen
+        #[arg(long)]
+        frozen: bool,
+
+        /// Pass `--config KEY=VALUE` to cargo metadata; repeatable
+        #[arg(long, value_name = "KEY=VALUE")]
+        cargo_config: Vec<String>,
+    },
+
+    /// Drop `crates-io` patches for crates also patched under a git URL or registry index
+    Dedupe {
+        /// Path to Cargo.toml to modify (defaults to current directory)
+        #[arg(long)]
+        manifest_path: Option<PathBuf>,
+
+        /// Show what would be removed without writing the manifest
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// List the managed patches in the current Cargo.toml
+    List {
+        /// Path to Cargo.toml to read (defaults to current directory)
+        #[arg(long)]
+        manifest_path: Option<PathBuf>,
+
+        /// Group the crates under the patch key they are patched in
+        #[arg(long)]
+        group_by_source: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+    },
+
+    /// Diagnose common problems with the managed patches in the current Cargo.toml
+    Doctor {
+        /// Path to Cargo.toml to check (defaults to current directory)
+        #[arg(long)]
+        manifest_path: Option<PathBuf>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+    },
+}
+
+/// Parse `SOURCE=TARGET` crate names
+fn parse_name_map(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .filter(|(source, target)| !source.is_empty() && !target.is_empty())
+        .map(|(source, target)| (source.to_string(), target.to_string()))
+        .ok_or_else(|| format!("expected SOURCE=TARGET, got `{}`", value))
+}
+
+/// Parse `CRATE=REF`, where `REF` is a branch or a `branch:`, `tag:` or `rev:` prefixed reference
+fn parse_crate_ref(value: &str) -> Result<(String, GitReference), String> {
+    let (name, reference) = value
+        .split_once('=')
+        .filter(|(name, reference)| !name.is_empty() && !reference.is_empty())
+        .ok_or_else(|| format!("expected CRATE=REF, got `{}`", value))?;
+
+    let reference = match reference.split_once(':') {
+        Some(("branch", branch)) => GitReference::Branch(branch.to_string()),
+        Some(("tag", tag)) => GitReference::Tag(tag.to_string()),
+        Some(("rev", rev)) => GitReference::Rev(rev.to_string()),
+        _ => GitReference::Branch(reference.to_string()),
+    };
+
+    Ok((name.to_string(), reference))
 }
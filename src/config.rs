@@ -0,0 +1,107 @@
+use crate::error::{PatchError, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item};
+
+/// Name of the preset config file, stored next to the target manifest
+pub const CONFIG_FILE_NAME: &str = ".cargo-patch-source.toml";
+
+const PRESETS_KEY: &str = "presets";
+
+const CONFIG_TEMPLATE: &str = r#"# cargo-patch-source presets
+#
+# Apply a preset with `cargo patch-source apply --preset <name>`.
+# Values given on the command line take precedence over the preset.
+#
+# Each preset accepts the same settings as `apply`:
+#   path    - local path to a source workspace
+#   git     - git repository URL (optionally with branch, tag or rev)
+#   pattern - glob pattern to filter crates
+
+# [presets.local]
+# path = "../rattler"
+# pattern = "rattler-*"
+
+# [presets.upstream]
+# git = "https://github.com/conda/rattler"
+# branch = "main"
+# pattern = "rattler-*"
+"#;
+
+/// A named set of `apply` settings read from the config file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preset {
+    pub path: Option<PathBuf>,
+    pub git: Option<String>,
+    pub branch: Option<String>,
+    pub tag: Option<String>,
+    pub rev: Option<String>,
+    pub pattern: Option<String>,
+}
+
+/// Location of the config file for a target manifest
+pub fn config_path_for(manifest_path: &Path) -> PathBuf {
+    manifest_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(CONFIG_FILE_NAME)
+}
+
+/// Write the commented config template next to the manifest.
+///
+/// Refuses to overwrite an existing config file unless `force` is set.
+pub fn init_config(manifest_path: &Path, force: bool) -> Result<PathBuf> {
+    let config_path = config_path_for(manifest_path);
+
+    if config_path.exists() && !force {
+        return Err(PatchError::ConfigFileExists { path: config_path });
+    }
+
+    fs::write(&config_path, CONFIG_TEMPLATE).map_err(|e| PatchError::ConfigWriteError {
+        path: config_path.clone(),
+        source: e,
+    })?;
+
+    Ok(config_path)
+}
+
+/// Load a named preset from the config file
+pub fn load_preset(config_path: &Path, name: &str) -> Result<Preset> {
+    let content = fs::read_to_string(config_path).map_err(|e| PatchError::ConfigReadError {
+        path: config_path.to_path_buf(),
+        source: e,
+    })?;
+
+    let doc = content
+        .parse::<DocumentMut>()
+        .map_err(|e| PatchError::ConfigParseError {
+            path: config_path.to_path_buf(),
+            source: e,
+        })?;
+
+    let Some(preset) = doc.get(PRESETS_KEY).and_then(|p| p.get(name)) else {
+        return Err(PatchError::PresetNotFound {
+            name: name.to_string(),
+            path: config_path.to_path_buf(),
+        });
+    };
+
+    let get = |key: &str| -> Option<String> {
+        preset
+            .get(key)
+            .and_then(Item::as_str)
+            .map(|s| s.to_string())
+    };
+
+    // Relative paths in a preset are relative to the config file
+    let base_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+
+    Ok(Preset {
+        path: get("path").map(|path| base_dir.join(path)),
+        git: get("git"),
+        branch: get("branch"),
+        tag: get("tag"),
+        rev: get("rev"),
+        pattern: get("pattern"),
+    })
+}
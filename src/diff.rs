@@ -0,0 +1,23 @@
+use crate::error::{PatchError, Result};
+use similar::TextDiff;
+use std::fs;
+use std::path::Path;
+
+/// Unified diff between two versions of the manifest at `path`
+pub fn unified_diff(path: &Path, old: &str, new: &str) -> String {
+    let name = path.display().to_string();
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&format!("a/{}", name), &format!("b/{}", name))
+        .to_string()
+}
+
+/// Write a unified diff of the manifest change to `output`
+pub fn write_diff_file(output: &Path, manifest_path: &Path, old: &str, new: &str) -> Result<()> {
+    fs::write(output, unified_diff(manifest_path, old, new)).map_err(|e| {
+        PatchError::DiffWriteError {
+            path: output.to_path_buf(),
+            source: e,
+        }
+    })
+}
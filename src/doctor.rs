@@ -0,0 +1,217 @@
+use crate::cargo_config::{local_config_path, read_config};
+use crate::cargo_ops::{find_version_mismatches, CrateInfo};
+use crate::error::Result;
+use crate::patch::resolve_target_manifest_path;
+use crate::report::{CheckResult, DoctorReport};
+use crate::toml_ops::{
+    dependency_keys_for_package, find_duplicate_patches, get_dependencies_table,
+    get_dependency_version, get_managed_patches, get_original_versions, get_patch_location,
+    read_cargo_toml, resolve_package_name, swap_patch_tables, PatchLocation,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item};
+
+/// A crate patched by us, as found in the managed `[patch]` tables
+struct ManagedEntry {
+    patch_key: String,
+    name: String,
+    path: Option<PathBuf>,
+}
+
+/// Run every diagnostic against the target manifest.
+///
+/// Only a missing manifest is an error; everything else is reported as a failed check.
+pub fn run_doctor(
+    target_manifest_path: Option<PathBuf>,
+    root: Option<&Path>,
+) -> Result<DoctorReport> {
+    let target_manifest_path = resolve_target_manifest_path(target_manifest_path, root)?;
+    let manifest_path = target_manifest_path.as_path();
+
+    let manifest_dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+    let doc = match read_cargo_toml(manifest_path)
+        .and_then(|doc| with_config_patches(doc, manifest_dir))
+    {
+        Ok(doc) => doc,
+        Err(err) => {
+            return Ok(DoctorReport {
+                checks: vec![CheckResult::fail("manifest parses", vec![err.to_string()])],
+            });
+        }
+    };
+
+    let entries = managed_entries(&doc, manifest_dir);
+
+    let checks = vec![
+        CheckResult::pass("manifest parses"),
+        check_metadata_consistent(&doc, &entries),
+        check_sources_resolve(&entries),
+        check_versions_satisfied(&doc, &entries),
+        check_unused_patches(&doc, &entries),
+        check_duplicate_patches(&doc),
+    ];
+
+    Ok(DoctorReport { checks })
+}
+
+/// The manifest with its `[patch]` tables taken from `.cargo/config.toml` when they live there
+fn with_config_patches(mut doc: DocumentMut, manifest_dir: &Path) -> Result<DocumentMut> {
+    if get_patch_location(&doc) == PatchLocation::CargoConfig {
+        let mut config_doc = read_config(&local_config_path(manifest_dir))?;
+        swap_patch_tables(&mut doc, &mut config_doc);
+    }
+    Ok(doc)
+}
+
+/// Every entry we added to a managed patch table, with its resolved local path if any
+fn managed_entries(doc: &DocumentMut, manifest_dir: &Path) -> Vec<ManagedEntry> {
+    let patched_crates: HashSet<String> = get_original_versions(doc)
+        .unwrap_or_default()
+        .keys()
+        .map(|key| resolve_package_name(doc, key))
+        .collect();
+    let mut entries = Vec::new();
+
+    for patch_key in get_managed_patches(doc) {
+        let Some(table) = doc
+            .get("patch")
+            .and_then(|p| p.get(&patch_key))
+            .and_then(Item::as_table_like)
+        else {
+            continue;
+        };
+
+        for (name, spec) in table.iter() {
+            if !patched_crates.contains(name) {
+                continue;
+            }
+            let path = spec
+                .get("path")
+                .and_then(Item::as_str)
+                .map(|path| manifest_dir.join(path));
+            entries.push(ManagedEntry {
+                patch_key: patch_key.clone(),
+                name: name.to_string(),
+                path,
+            });
+        }
+    }
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries
+}
+
+/// The metadata lists the same crates and patch tables that exist in `[patch]`
+fn check_metadata_consistent(doc: &DocumentMut, entries: &[ManagedEntry]) -> CheckResult {
+    let mut problems = Vec::new();
+
+    for patch_key in get_managed_patches(doc) {
+        if doc.get("patch").and_then(|p| p.get(&patch_key)).is_none() {
+            problems.push(format!(
+                "managed patch table [patch.{}] is missing",
+                patch_key
+            ));
+        }
+    }
+
+    let mut recorded: Vec<_> = get_original_versions(doc)
+        .unwrap_or_default()
+        .keys()
+        .map(|key| resolve_package_name(doc, key))
+        .collect();
+    recorded.sort();
+    recorded.dedup();
+    for name in recorded {
+        if !entries.iter().any(|entry| entry.name == name) {
+            problems.push(format!("{} is recorded in metadata but not patched", name));
+        }
+    }
+
+    CheckResult::from_problems("metadata matches patch table", problems)
+}
+
+/// Local patch paths still point at a crate manifest
+fn check_sources_resolve(entries: &[ManagedEntry]) -> CheckResult {
+    let problems = entries
+        .iter()
+        .filter_map(|entry| {
+            let path = entry.path.as_ref()?;
+            (!path.join("Cargo.toml").exists())
+                .then(|| format!("{} points at missing {}", entry.name, path.display()))
+        })
+        .collect();
+
+    CheckResult::from_problems("patch sources resolve", problems)
+}
+
+/// Local patch versions satisfy the dependency requirements
+fn check_versions_satisfied(doc: &DocumentMut, entries: &[ManagedEntry]) -> CheckResult {
+    let requirements: HashMap<String, String> = get_dependencies_table(doc)
+        .map(|deps| {
+            deps.iter()
+                .filter_map(|(name, spec)| Some((name.to_string(), get_dependency_version(spec)?)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let crates: Vec<CrateInfo> = entries
+        .iter()
+        .filter_map(|entry| {
+            let manifest_path = entry.path.as_ref()?.join("Cargo.toml");
+            let version = read_cargo_toml(&manifest_path)
+                .ok()?
+                .get("package")
+                .and_then(|p| p.get("version"))
+                .and_then(Item::as_str)?
+                .to_string();
+            Some(CrateInfo {
+                name: entry.name.clone(),
+                version,
+                manifest_path,
+                license: None,
+            })
+        })
+        .collect();
+
+    let problems = find_version_mismatches(&crates, &requirements)
+        .into_iter()
+        .map(|m| {
+            format!(
+                "{} {} does not satisfy {}",
+                m.name, m.version, m.requirement
+            )
+        })
+        .collect();
+
+    CheckResult::from_problems("patched versions satisfy requirements", problems)
+}
+
+/// Patches for crates that are no longer dependencies have no effect
+fn check_unused_patches(doc: &DocumentMut, entries: &[ManagedEntry]) -> CheckResult {
+    let problems = entries
+        .iter()
+        .filter(|entry| dependency_keys_for_package(doc, &entry.name).is_empty())
+        .map(|entry| {
+            format!(
+                "[patch.{}] {} is not a dependency",
+                entry.patch_key, entry.name
+            )
+        })
+        .collect();
+
+    CheckResult::from_problems("no unused patches", problems)
+}
+
+/// Cargo only uses one patch per crate, so entries under several keys may conflict
+fn check_duplicate_patches(doc: &DocumentMut) -> CheckResult {
+    let problems = find_duplicate_patches(doc)
+        .into_iter()
+        .map(|(name, keys)| {
+            let keys: Vec<_> = keys.iter().map(|key| format!("[patch.{}]", key)).collect();
+            format!("{} is patched under {}", name, keys.join(", "))
+        })
+        .collect();
+
+    CheckResult::from_problems("no crate patched under several keys", problems)
+}
@@ -28,6 +28,81 @@ pub enum PatchError {
         source: toml_edit::TomlError,
     },
 
+    #[error("Failed to read lockfile at {path}")]
+    #[diagnostic(code(patch::io::lockfile))]
+    LockfileReadError {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse lockfile at {path}")]
+    #[diagnostic(code(patch::toml::lockfile))]
+    LockfileParseError {
+        path: PathBuf,
+        #[source]
+        source: toml_edit::TomlError,
+    },
+
+    #[error("Malformed [patch] section in {path}: `{key}` is not a table")]
+    #[diagnostic(
+        code(patch::toml::malformed_patch),
+        help("Each [patch] source must be a table of crate entries, e.g. `[patch.crates-io]`")
+    )]
+    MalformedPatchSection { path: PathBuf, key: String },
+
+    #[error("Failed to read config file at {path}")]
+    #[diagnostic(code(patch::config::read))]
+    ConfigReadError {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to write config file at {path}")]
+    #[diagnostic(code(patch::config::write))]
+    ConfigWriteError {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse config file at {path}")]
+    #[diagnostic(code(patch::config::parse))]
+    ConfigParseError {
+        path: PathBuf,
+        #[source]
+        source: toml_edit::TomlError,
+    },
+
+    #[error("Config file already exists: {path}")]
+    #[diagnostic(code(patch::config::exists), help("Pass --force to overwrite it"))]
+    ConfigFileExists { path: PathBuf },
+
+    #[error("Preset `{name}` not found in {path}")]
+    #[diagnostic(code(patch::config::preset_not_found))]
+    PresetNotFound { name: String, path: PathBuf },
+
+    #[error("Expected `crate=version` in {path}, got `{line}`")]
+    #[diagnostic(code(patch::versions::invalid_line))]
+    InvalidVersionMapLine { path: PathBuf, line: String },
+
+    #[error("Failed to read crate list at {path}")]
+    #[diagnostic(code(patch::io::crate_list))]
+    CrateListReadError {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to write diff file at {path}")]
+    #[diagnostic(code(patch::io::diff))]
+    DiffWriteError {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
     #[error("Failed to query cargo metadata")]
     #[diagnostic(code(patch::cargo::metadata))]
     CargoMetadataError {
@@ -35,6 +110,13 @@ pub enum PatchError {
         source: cargo_metadata::Error,
     },
 
+    #[error("Failed to update Cargo.lock: {message}")]
+    #[diagnostic(
+        code(patch::cargo::update),
+        help("The manifest was patched; run `cargo update` yourself once the problem is fixed")
+    )]
+    LockUpdateError { message: String },
+
     #[error("No source specified. Use --path or --git")]
     #[diagnostic(code(patch::cli::no_source))]
     NoSourceSpecified,
@@ -43,10 +125,28 @@ pub enum PatchError {
     #[diagnostic(code(patch::source::not_found))]
     SourceWorkspaceNotFound { path: PathBuf },
 
+    #[error("Unsupported archive format: {path}")]
+    #[diagnostic(
+        code(patch::archive::unsupported),
+        help("Supported formats are .tar.gz, .tgz, .crate and .zip")
+    )]
+    UnsupportedArchive { path: PathBuf },
+
+    #[error("Failed to extract archive {path}: {message}")]
+    #[diagnostic(code(patch::archive::extract))]
+    ArchiveError { path: PathBuf, message: String },
+
     #[error("Target manifest does not exist: {path}")]
     #[diagnostic(code(patch::target::not_found))]
     TargetManifestNotFound { path: PathBuf },
 
+    #[error("Source workspace has no lockfile: {path}")]
+    #[diagnostic(
+        code(patch::source::no_lockfile),
+        help("Run `cargo generate-lockfile` in the source, or drop --from-source-lock")
+    )]
+    SourceLockNotFound { path: PathBuf },
+
     #[error("Source path is not a valid cargo workspace: {path}")]
     #[diagnostic(code(patch::source::not_workspace))]
     NotAWorkspace { path: PathBuf },
@@ -58,10 +158,96 @@ pub enum PatchError {
         source: std::io::Error,
     },
 
+    #[error("Failed to clone git repository {url}: {message}")]
+    #[diagnostic(code(patch::git::clone))]
+    GitCloneError { url: String, message: String },
+
+    #[error("Failed to watch {path}: {message}")]
+    #[diagnostic(code(patch::watch))]
+    WatchError { path: PathBuf, message: String },
+
+    #[error("Failed to check the git status of {path}: {message}")]
+    #[diagnostic(code(patch::git::status))]
+    GitStatusError { path: PathBuf, message: String },
+
+    #[error("{path} has uncommitted changes")]
+    #[diagnostic(
+        code(patch::manifest::dirty),
+        help("Commit or stash your changes first, or drop --abort-on-dirty")
+    )]
+    DirtyManifest { path: PathBuf },
+
+    #[error("Revision {rev} is not reachable in a clone of depth {depth}")]
+    #[diagnostic(
+        code(patch::git::shallow_rev),
+        help("Pass --git-no-shallow to clone the full history, or increase --git-depth")
+    )]
+    GitRevNotInShallowHistory { rev: String, depth: u32 },
+
+    #[error("Crates not found in {url}: {}", crates.join(", "))]
+    #[diagnostic(
+        code(patch::git::crate_not_found),
+        help("Check the crate names and the branch, tag or rev")
+    )]
+    SourceCratesNotFound { url: String, crates: Vec<String> },
+
     #[error("No crates found matching pattern: {pattern}")]
     #[diagnostic(code(patch::pattern::no_match))]
     NoMatchingCrates { pattern: String },
 
+    #[error("No crates found matching pattern: {pattern}; excluded from the source workspace: {}", crates.join(", "))]
+    #[diagnostic(
+        code(patch::pattern::excluded),
+        help("Remove them from `[workspace] exclude` in the source's Cargo.toml to patch them")
+    )]
+    NoMatchingCratesExcluded {
+        pattern: String,
+        crates: Vec<String>,
+    },
+
+    #[error("No manifests matched glob {pattern}")]
+    #[diagnostic(code(patch::manifest::no_match))]
+    NoManifestsMatched { pattern: String },
+
+    #[error("Source versions do not satisfy the dependency requirements of: {}", crates.join(", "))]
+    #[diagnostic(
+        code(patch::version::mismatch),
+        help("Run without --strict to update the requirements to the source versions")
+    )]
+    VersionMismatches { crates: Vec<String> },
+
+    #[error("Patch paths point outside {}: {}", dir.display(), crates.join(", "))]
+    #[diagnostic(
+        code(patch::path::outside_project),
+        help("Run without --strict to allow paths that only work on this machine")
+    )]
+    PatchPathsOutsideProject { dir: PathBuf, crates: Vec<String> },
+
+    #[error("{count} crates would be patched, more than --max-crates {max}")]
+    #[diagnostic(
+        code(patch::pattern::too_many),
+        help("Use a narrower --pattern, or raise --max-crates if this is intended")
+    )]
+    TooManyCrates { count: usize, max: usize },
+
+    #[error("Patch entries already exist for: {}", crates.join(", "))]
+    #[diagnostic(
+        code(patch::merge::existing_entry),
+        help("Use --merge-strategy skip or overwrite, or remove the entries first")
+    )]
+    ExistingPatchEntries { crates: Vec<String> },
+
+    #[error("{count} warnings were emitted")]
+    #[diagnostic(
+        code(patch::warnings),
+        help("Fix the warnings above, or drop --fail-on-warning")
+    )]
+    WarningsEmitted { count: usize },
+
+    #[error("{failed} doctor checks failed")]
+    #[diagnostic(code(patch::doctor::failed))]
+    DoctorChecksFailed { failed: usize },
+
     #[error("No patches found to remove")]
     #[diagnostic(code(patch::remove::not_found))]
     NoPatchesFound,
@@ -82,4 +268,37 @@ pub enum PatchError {
     },
 }
 
+impl PatchError {
+    /// Stable process exit code for this error, so scripts can branch on the reason.
+    ///
+    /// | Code | Meaning |
+    /// | --- | --- |
+    /// | 1 | Any other failure |
+    /// | 2 | No crates matched the pattern (clap also exits with 2 on usage errors) |
+    /// | 3 | No managed patches found to remove |
+    /// | 4 | Reading or writing a file failed |
+    /// | 5 | A manifest or JSON document could not be parsed |
+    /// | 6 | Warnings were emitted under `--fail-on-warning` |
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::NoMatchingCrates { .. } | Self::NoMatchingCratesExcluded { .. } => 2,
+            Self::NoPatchesFound => 3,
+            Self::CargoTomlReadError { .. }
+            | Self::CargoTomlWriteError { .. }
+            | Self::ConfigReadError { .. }
+            | Self::LockfileReadError { .. }
+            | Self::ConfigWriteError { .. }
+            | Self::CrateListReadError { .. }
+            | Self::DiffWriteError { .. }
+            | Self::CurrentDirError { .. } => 4,
+            Self::TomlParseError { .. }
+            | Self::ConfigParseError { .. }
+            | Self::LockfileParseError { .. }
+            | Self::JsonError { .. } => 5,
+            Self::WarningsEmitted { .. } => 6,
+            _ => 1,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, PatchError>;
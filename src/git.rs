@@ -0,0 +1,258 @@
+use crate::error::{PatchError, Result};
+use crate::source::GitReference;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+use toml_edit::DocumentMut;
+use tracing::warn;
+
+/// Clone depth used when none is given
+pub const DEFAULT_GIT_DEPTH: u32 = 1;
+
+/// Options controlling how a git source is cloned for crate discovery
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitCloneOptions {
+    /// History depth to fetch, `None` clones the full history
+    pub depth: Option<u32>,
+}
+
+impl Default for GitCloneOptions {
+    fn default() -> Self {
+        Self {
+            depth: Some(DEFAULT_GIT_DEPTH),
+        }
+    }
+}
+
+impl GitCloneOptions {
+    /// Build clone options from the `--git-depth` and `--git-no-shallow` flags
+    pub fn new(depth: u32, no_shallow: bool) -> Self {
+        Self {
+            depth: (!no_shallow).then_some(depth),
+        }
+    }
+
+    /// Arguments passed to `git clone`
+    pub fn clone_args(
+        &self,
+        url: &str,
+        reference: Option<&GitReference>,
+        destination: &Path,
+    ) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["clone".into(), "--quiet".into()];
+
+        if let Some(depth) = self.depth {
+            args.push("--depth".into());
+            args.push(depth.to_string().into());
+        }
+
+        // Branches and tags can be checked out directly, revisions are checked out afterwards
+        match reference {
+            Some(GitReference::Branch(name)) | Some(GitReference::Tag(name)) => {
+                args.push("--branch".into());
+                args.push(name.into());
+            }
+            Some(GitReference::Rev(_)) | None => {}
+        }
+
+        args.push(url.into());
+        args.push(destination.into());
+        args
+    }
+}
+
+/// A git repository cloned into a temporary directory, removed on drop
+#[derive(Debug)]
+pub struct ClonedRepository {
+    dir: TempDir,
+}
+
+impl ClonedRepository {
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Whether the repository's `.gitattributes` routes any files through Git LFS
+    pub fn uses_git_lfs(&self) -> bool {
+        fs::read_to_string(self.path().join(".gitattributes"))
+            .is_ok_and(|content| gitattributes_use_lfs(&content))
+    }
+}
+
+fn gitattributes_use_lfs(content: &str) -> bool {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .any(|line| line.split_whitespace().any(|attr| attr == "filter=lfs"))
+}
+
+/// Clone `url` at the given reference into a temporary directory
+pub fn clone_repository(
+    url: &str,
+    reference: Option<&GitReference>,
+    options: &GitCloneOptions,
+) -> Result<ClonedRepository> {
+    let dir = TempDir::new().map_err(|e| PatchError::GitCloneError {
+        url: url.to_string(),
+        message: e.to_string(),
+    })?;
+
+    // git happily clones into an existing empty directory
+    let checkout = dir.path();
+    run_git(&options.clone_args(url, reference, checkout), url)?;
+
+    if let Some(GitReference::Rev(rev)) = reference {
+        let mut args: Vec<OsString> = vec!["-C".into(), checkout.into()];
+        args.extend(["checkout".into(), "--quiet".into(), rev.into()]);
+
+        if let Err(err) = run_git(&args, url) {
+            return Err(match options.depth {
+                Some(depth) => PatchError::GitRevNotInShallowHistory {
+                    rev: rev.clone(),
+                    depth,
+                },
+                None => err,
+            });
+        }
+    }
+
+    let repository = ClonedRepository { dir };
+    if repository.uses_git_lfs() {
+        warn!(
+            "{} uses Git LFS; cargo does not fetch LFS content for git dependencies, so LFS-tracked files in the patched crates may be pointer files",
+            url
+        );
+    }
+    Ok(repository)
+}
+
+/// Names of all packages in a cloned repository, read from its tracked `Cargo.toml` files.
+///
+/// Unlike `cargo metadata` this needs neither a resolvable dependency graph nor the network.
+pub fn package_names(repository: &ClonedRepository, url: &str) -> Result<HashSet<String>> {
+    let mut args: Vec<OsString> = vec!["-C".into(), repository.path().into()];
+    args.extend([
+        "ls-files".into(),
+        "Cargo.toml".into(),
+        "*/Cargo.toml".into(),
+    ]);
+    let listing = run_git(&args, url)?;
+
+    let names = listing
+        .lines()
+        .filter_map(|manifest| fs::read_to_string(repository.path().join(manifest)).ok())
+        .filter_map(|content| content.parse::<DocumentMut>().ok())
+        .filter_map(|doc| {
+            doc.get("package")
+                .and_then(|p| p.get("name"))
+                .and_then(|n| n.as_str())
+                .map(|n| n.to_string())
+        })
+        .collect();
+
+    Ok(names)
+}
+
+/// Whether git reports uncommitted changes to `path`, including it being untracked
+pub fn has_uncommitted_changes(path: &Path) -> Result<bool> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let status_error = |message: String| PatchError::GitStatusError {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["status", "--porcelain", "--"])
+        .arg(path.file_name().unwrap_or(path.as_os_str()))
+        .output()
+        .map_err(|e| status_error(e.to_string()))?;
+    if !output.status.success() {
+        return Err(status_error(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(!output.stdout.is_empty())
+}
+
+/// The commit checked out in the git repository containing `dir`, if it is one
+pub fn head_commit(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--verify", "--quiet", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!commit.is_empty()).then_some(commit)
+}
+
+fn run_git(args: &[OsString], url: &str) -> Result<String> {
+    let output =
+        Command::new("git")
+            .args(args)
+            .output()
+            .map_err(|e| PatchError::GitCloneError {
+                url: url.to_string(),
+                message: e.to_string(),
+            })?;
+
+    if !output.status.success() {
+        return Err(PatchError::GitCloneError {
+            url: url.to_string(),
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clone_options_from_flags() {
+        assert_eq!(GitCloneOptions::default().depth, Some(1));
+        assert_eq!(GitCloneOptions::new(5, false).depth, Some(5));
+        assert_eq!(GitCloneOptions::new(5, true).depth, None);
+    }
+
+    #[test]
+    fn gitattributes_lfs_filter_is_detected() {
+        assert!(gitattributes_use_lfs(
+            "*.txt text\n*.bin filter=lfs diff=lfs merge=lfs -text\n"
+        ));
+        assert!(!gitattributes_use_lfs("*.txt text eol=lf\n"));
+        assert!(!gitattributes_use_lfs("# *.bin filter=lfs\n"));
+    }
+
+    #[test]
+    fn clone_args_include_depth_and_branch() {
+        let destination = Path::new("dest");
+        let branch = GitReference::Branch("main".to_string());
+
+        let args = GitCloneOptions::new(3, false).clone_args("url", Some(&branch), destination);
+        assert_eq!(
+            args,
+            ["clone", "--quiet", "--depth", "3", "--branch", "main", "url", "dest"]
+        );
+
+        let rev = GitReference::Rev("abc123".to_string());
+        let args = GitCloneOptions::new(3, true).clone_args("url", Some(&rev), destination);
+        assert_eq!(args, ["clone", "--quiet", "url", "dest"]);
+    }
+}
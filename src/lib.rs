@@ -1,10 +1,29 @@
+pub mod archive;
+pub mod cargo_config;
 pub mod cargo_ops;
 pub mod cli;
+pub mod config;
+pub mod diff;
+pub mod doctor;
 pub mod error;
+pub mod git;
+pub mod logging;
 pub mod patch;
+pub mod report;
 pub mod source;
+pub mod state;
 pub mod toml_ops;
+pub mod watch;
 
+pub use cargo_ops::find_unused_patches;
+pub use doctor::run_doctor;
 pub use error::{PatchError, Result};
-pub use patch::{apply_patches, remove_patches};
+pub use patch::{
+    apply_patches, apply_patches_to_manifests, apply_patches_with_options, dedupe_manifest_patches,
+    expand_manifest_globs, format_effective_config, remove_patches, remove_patches_from_workspace,
+    remove_patches_with_options, validate_source, ApplyOptions, MatchOn, MergeStrategy, RelativeTo,
+    RemoveOptions,
+};
+pub use report::{CheckResult, DoctorReport, OutputFormat, PatchPlan, RemovePlan, SCHEMA_VERSION};
 pub use source::{GitReference, PatchSource, SourceWorkspacePath, TargetManifestPath};
+pub use state::{managed_state, ManagedState, PatchedCrate};
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// Number of warnings printed so far, on any thread
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether captured output may use ANSI colors, as decided by [`init`]
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Target of the one-line summary printed at the end of `apply`
+pub const SUMMARY_TARGET: &str = "cargo_patch_source::summary";
+
+/// How much progress output the binary prints
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Only warnings and errors
+    Quiet,
+    /// Warnings, errors and the final summary line
+    Summary,
+    /// Progress, warnings and errors
+    #[default]
+    Normal,
+    /// Everything, including per-crate decisions
+    Verbose,
+}
+
+impl Verbosity {
+    /// Build the verbosity from the `--verbose`, `--quiet` and `--summary-only` flags
+    pub fn from_flags(verbose: bool, quiet: bool, summary_only: bool) -> Self {
+        if quiet {
+            Self::Quiet
+        } else if summary_only {
+            Self::Summary
+        } else if verbose {
+            Self::Verbose
+        } else {
+            Self::Normal
+        }
+    }
+
+    /// Filter directive used when `RUST_LOG` is not set
+    pub fn directive(self) -> &'static str {
+        match self {
+            Self::Quiet => "cargo_patch_source=warn",
+            Self::Summary => "cargo_patch_source=warn,cargo_patch_source::summary=info",
+            Self::Normal => "cargo_patch_source=info",
+            Self::Verbose => "cargo_patch_source=debug",
+        }
+    }
+}
+
+/// Formats events like cargo's own output: plain progress lines, prefixed warnings
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CliFormatter;
+
+impl<S, N> FormatEvent<S, N> for CliFormatter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        match *event.metadata().level() {
+            Level::ERROR => write!(writer, "error: ")?,
+            Level::WARN => write!(writer, "warning: ")?,
+            Level::INFO => {}
+            Level::DEBUG | Level::TRACE => write!(writer, "debug: ")?,
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Counts the warnings that pass the filter, for `--fail-on-warning`
+#[derive(Debug, Clone, Copy, Default)]
+struct WarningCounter;
+
+impl<S: Subscriber> Layer<S> for WarningCounter {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() == Level::WARN {
+            WARNINGS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Number of warnings printed since the process started, including captured ones
+pub fn warning_count() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
+
+/// The `RUST_LOG` filter, falling back to the verbosity flags
+fn env_filter(verbosity: Verbosity) -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(verbosity.directive()))
+}
+
+/// Whether ANSI colors may be used: not with `--no-color` or a non-empty `NO_COLOR`
+pub fn use_color(no_color: bool) -> bool {
+    !no_color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// Install the global subscriber, honoring `RUST_LOG` over the verbosity flags.
+///
+/// Output goes to stderr so `--format json` keeps stdout machine readable.
+pub fn init(verbosity: Verbosity, color: bool) {
+    COLOR.store(color, Ordering::Relaxed);
+    tracing_subscriber::fmt()
+        .with_env_filter(env_filter(verbosity))
+        .with_ansi(color)
+        .with_writer(std::io::stderr)
+        .event_format(CliFormatter)
+        .finish()
+        .with(WarningCounter)
+        .init();
+}
+
+/// Shared buffer that formatted events are written into
+#[derive(Debug, Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run `f` on the current thread with its output formatted into a string instead of stderr.
+///
+/// Work running in parallel uses this so its output can be printed in a fixed order afterwards.
+pub fn capture<T>(verbosity: Verbosity, f: impl FnOnce() -> T) -> (T, String) {
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(env_filter(verbosity))
+        .with_ansi(COLOR.load(Ordering::Relaxed))
+        .with_writer(move || writer.clone())
+        .event_format(CliFormatter)
+        .finish()
+        .with(WarningCounter);
+
+    let result = tracing::subscriber::with_default(subscriber, f);
+    let output = std::mem::take(&mut *buffer.0.lock().unwrap_or_else(|e| e.into_inner()));
+    (result, String::from_utf8_lossy(&output).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbosity_from_flags() {
+        assert_eq!(
+            Verbosity::from_flags(false, false, false),
+            Verbosity::Normal
+        );
+        assert_eq!(
+            Verbosity::from_flags(true, false, false),
+            Verbosity::Verbose
+        );
+        assert_eq!(
+            Verbosity::from_flags(false, false, true),
+            Verbosity::Summary
+        );
+        // --quiet wins when both are given
+        assert_eq!(Verbosity::from_flags(true, true, false), Verbosity::Quiet);
+    }
+
+    #[test]
+    fn capture_follows_the_color_setting() {
+        let captured = || {
+            capture(Verbosity::Normal, || {
+                tracing::info!(crate_name = "rattler-one", "Patching")
+            })
+            .1
+        };
+
+        COLOR.store(true, Ordering::Relaxed);
+        assert!(captured().contains('\x1b'));
+        COLOR.store(false, Ordering::Relaxed);
+        assert_eq!(captured(), "Patching crate_name=\"rattler-one\"\n");
+    }
+}
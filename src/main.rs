@@ -1,27 +1,154 @@
-use cargo_patch_source::cli::{CargoCli, Commands};
+use cargo_patch_source::cargo_ops::MetadataOptions;
+use cargo_patch_source::cli::{CargoCli, Cli, Commands};
+use cargo_patch_source::config::{config_path_for, init_config, load_preset, Preset};
+use cargo_patch_source::git::GitCloneOptions;
+use cargo_patch_source::logging::{self, Verbosity};
+use cargo_patch_source::patch::resolve_target_manifest_path;
 use cargo_patch_source::source::{GitReference, PatchSource};
-use cargo_patch_source::{apply_patches, remove_patches};
+use cargo_patch_source::watch::{watch_source, DEFAULT_DEBOUNCE};
+use cargo_patch_source::{
+    apply_patches_to_manifests, apply_patches_with_options, dedupe_manifest_patches,
+    expand_manifest_globs, format_effective_config, managed_state, remove_patches_from_workspace,
+    remove_patches_with_options, run_doctor, validate_source, ApplyOptions, OutputFormat,
+    PatchError, RemoveOptions, Result,
+};
 use clap::Parser;
-use miette::Result;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     miette::set_panic_hook();
 
-    let CargoCli::PatchSource(cli) = CargoCli::parse();
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            let code = err.exit_code();
+            eprintln!("{:?}", miette::Report::new(err));
+            ExitCode::from(code)
+        }
+    }
+}
+
+// Mirrors the library's error type so `main` can map it to an exit code
+#[allow(clippy::result_large_err)]
+fn run() -> Result<()> {
+    let CargoCli::PatchSource(mut cli) = CargoCli::parse();
+    // Set by cargo for build scripts and `cargo run`; --root still wins
+    cli.root = cli
+        .root
+        .or_else(|| std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from));
+    let verbosity = Verbosity::from_flags(cli.verbose, cli.quiet, cli.summary_only);
+    let color = logging::use_color(cli.no_color);
+    logging::init(verbosity, color);
+    if !color {
+        miette::set_hook(Box::new(|_| {
+            Box::new(miette::MietteHandlerOpts::new().color(false).build())
+        }))
+        .ok();
+    }
+
+    let fail_on_warning = cli.fail_on_warning;
+    run_command(cli, verbosity)?;
 
+    let count = logging::warning_count();
+    if fail_on_warning && count > 0 {
+        return Err(PatchError::WarningsEmitted { count });
+    }
+    Ok(())
+}
+
+#[allow(clippy::result_large_err)]
+fn run_command(cli: Cli, verbosity: Verbosity) -> Result<()> {
     match cli.command {
         Commands::Apply {
             path,
             git,
+            archive,
             branch,
             tag,
             rev,
+            git_depth,
+            git_no_shallow,
+            verify_source_crate_exists,
+            crate_ref,
             pattern,
+            prefix,
+            package,
+            ignore_case,
+            match_on,
+            name_map,
+            select_from,
             manifest_path,
+            concurrency,
+            registry_from_dep,
+            workspace_dependencies_only,
+            sections,
+            only_newer,
+            license,
+            from_source_lock,
+            update_lock,
+            record_checksum,
+            max_crates,
+            abort_on_dirty,
+            strict,
+            check_cargo_version,
+            dry_run,
+            watch,
+            output_diff_file,
+            recursive,
+            print_plan,
+            explain,
+            print_effective_config,
+            format,
+            merge_strategy,
+            patch_style,
+            relative_to,
+            write_to,
+            expanded_metadata,
+            offline,
+            frozen,
+            cargo_config,
+            preset,
         } => {
+            let manifest_path = expand_manifest_globs(manifest_path)?;
+
+            // Fill in anything not given on the command line from the preset
+            let preset = match preset {
+                Some(name) => {
+                    let manifest = resolve_target_manifest_path(
+                        manifest_path.first().cloned(),
+                        cli.root.as_deref(),
+                    )?;
+                    load_preset(&config_path_for(manifest.as_path()), &name)?
+                }
+                None => Preset::default(),
+            };
+            let patterns = if pattern.is_empty() && prefix.is_empty() {
+                preset.pattern.into_iter().collect()
+            } else {
+                pattern
+                    .into_iter()
+                    .chain(prefix.into_iter().map(|prefix| format!("{}*", prefix)))
+                    .collect()
+            };
+            let (path, git, branch, tag, rev) =
+                if path.is_some() || git.is_some() || archive.is_some() {
+                    (path, git, branch, tag, rev)
+                } else {
+                    (
+                        preset.path,
+                        preset.git,
+                        preset.branch,
+                        preset.tag,
+                        preset.rev,
+                    )
+                };
+
             // Determine the source
             let source = if let Some(path) = path {
                 PatchSource::local_path(path)
+            } else if let Some(archive) = archive {
+                PatchSource::archive(archive)
             } else if let Some(url) = git {
                 let reference = if let Some(branch) = branch {
                     Some(GitReference::Branch(branch))
@@ -32,13 +159,196 @@ fn main() -> Result<()> {
                 };
                 PatchSource::git(url, reference)
             } else {
-                return Err(cargo_patch_source::PatchError::NoSourceSpecified.into());
+                return Err(PatchError::NoSourceSpecified);
             };
 
-            apply_patches(source, manifest_path, pattern.as_deref())?;
+            let options = ApplyOptions {
+                patterns,
+                packages: package,
+                ignore_case,
+                match_on,
+                name_map: name_map.into_iter().collect(),
+                select_from,
+                registry_from_dep,
+                workspace_dependencies_only,
+                sections,
+                verify_source_crates: verify_source_crate_exists,
+                crate_refs: crate_ref.into_iter().collect(),
+                only_newer,
+                license,
+                from_source_lock,
+                update_lock,
+                record_checksums: record_checksum,
+                max_crates,
+                abort_on_dirty,
+                strict,
+                check_cargo_version,
+                dry_run,
+                output_diff_file,
+                recursive,
+                print_plan,
+                explain,
+                format,
+                merge_strategy,
+                patch_style,
+                relative_to,
+                write_to,
+                expanded_metadata,
+                git_clone: GitCloneOptions::new(git_depth, git_no_shallow),
+                metadata: MetadataOptions {
+                    offline,
+                    frozen,
+                    config: cargo_config,
+                },
+                root: cli.root,
+            };
+            if print_effective_config {
+                let manifests = if manifest_path.is_empty() {
+                    let manifest = resolve_target_manifest_path(None, options.root.as_deref())?;
+                    vec![manifest.as_path().to_path_buf()]
+                } else {
+                    manifest_path
+                };
+                println!(
+                    "{}",
+                    format_effective_config(&source, &manifests, &options)?
+                );
+                return Ok(());
+            }
+            let apply = || -> Result<()> {
+                if manifest_path.len() > 1 {
+                    apply_patches_to_manifests(
+                        &source,
+                        &manifest_path,
+                        &options,
+                        usize::from(concurrency),
+                        verbosity,
+                    )?;
+                } else {
+                    apply_patches_with_options(
+                        source.clone(),
+                        manifest_path.first().cloned(),
+                        &options,
+                    )?;
+                }
+                Ok(())
+            };
+            apply()?;
+            // --watch conflicts with --git and --archive, so the source is a local path
+            if let (true, PatchSource::LocalPath(path)) = (watch, &source) {
+                // A relative --path is relative to the (first) manifest, as in `apply`
+                let manifest = resolve_target_manifest_path(
+                    manifest_path.first().cloned(),
+                    options.root.as_deref(),
+                )?;
+                let manifest_dir = manifest.as_path().parent().unwrap_or(Path::new("."));
+                let source_root = path.clone().relative_to(manifest_dir);
+                watch_source(source_root.as_path(), DEFAULT_DEBOUNCE, apply)?;
+            }
         }
-        Commands::Remove { manifest_path } => {
-            remove_patches(manifest_path)?;
+        Commands::Init {
+            manifest_path,
+            force,
+        } => {
+            let manifest = resolve_target_manifest_path(manifest_path, cli.root.as_deref())?;
+            let config_path = init_config(manifest.as_path(), force)?;
+            tracing::info!("Created {}", config_path.display());
+        }
+        Commands::Remove {
+            manifest_path,
+            dry_run,
+            key_pattern,
+            versions_from,
+            only,
+            keep_empty_metadata,
+            all_keys,
+            workspace,
+            member_pattern,
+            format,
+        } => {
+            let options = RemoveOptions {
+                dry_run,
+                format,
+                key_pattern,
+                versions_from,
+                only,
+                keep_empty_metadata,
+                all_keys,
+                root: cli.root,
+            };
+            if workspace {
+                remove_patches_from_workspace(manifest_path, member_pattern.as_deref(), &options)?;
+            } else {
+                remove_patches_with_options(manifest_path, &options)?;
+            }
+        }
+        Commands::Validate {
+            path,
+            pattern,
+            ignore_case,
+            recursive,
+            offline,
+            frozen,
+            cargo_config,
+        } => {
+            let options = ApplyOptions {
+                patterns: pattern,
+                ignore_case,
+                recursive,
+                metadata: MetadataOptions {
+                    offline,
+                    frozen,
+                    config: cargo_config,
+                },
+                ..Default::default()
+            };
+            let crates = validate_source(&path, &options)?;
+            for crate_info in &crates {
+                println!("  {} {}", crate_info.name, crate_info.version);
+            }
+            tracing::info!(
+                "{} provides {} matching crates",
+                path.display(),
+                crates.len()
+            );
+        }
+        Commands::Dedupe {
+            manifest_path,
+            dry_run,
+        } => {
+            dedupe_manifest_patches(manifest_path, cli.root.as_deref(), dry_run)?;
+        }
+        Commands::List {
+            manifest_path,
+            group_by_source,
+            format,
+        } => {
+            let manifest = resolve_target_manifest_path(manifest_path, cli.root.as_deref())?;
+            let state = managed_state(manifest.as_path())?;
+            match format {
+                OutputFormat::Json => println!("{}", state.to_json()?),
+                OutputFormat::JsonLines => println!("{}", state.to_json_line()?),
+                OutputFormat::Human if state.is_empty() => {
+                    tracing::info!("No managed patches in {}", manifest.as_path().display())
+                }
+                OutputFormat::Human if group_by_source => println!("{}", state.format_grouped()),
+                OutputFormat::Human => println!("{}", state.format_list()),
+            }
+        }
+        Commands::Doctor {
+            manifest_path,
+            format,
+        } => {
+            let report = run_doctor(manifest_path, cli.root.as_deref())?;
+            match format {
+                OutputFormat::Human => report.print_human(),
+                OutputFormat::Json => println!("{}", report.to_json()?),
+                OutputFormat::JsonLines => println!("{}", report.to_json_line()?),
+            }
+            let failed = report.failed_count();
+            if failed > 0 {
+                return Err(PatchError::DoctorChecksFailed { failed });
+            }
         }
     }
 
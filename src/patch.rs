@@ -1,14 +1,158 @@
-use crate::cargo_ops::{filter_crates_by_pattern, glob_pattern_regex, query_workspace_crates};
+use crate::archive::{extract_archive, extract_archive_to_temp};
+use crate::cargo_config::{local_config_path, read_config, resolve_registry_index, write_config};
+use crate::cargo_ops::{
+    cargo_version_at_least, compile_pattern, filter_crates_by_package_spec,
+    filter_crates_by_pattern, find_excluded_crates, find_version_mismatches,
+    installed_cargo_version, is_newer_than_requirement, is_placeholder_version, license_matches,
+    parent_workspace_root, patch_key_required_cargo, query_workspace_crates,
+    query_workspace_crates_recursive, query_workspace_members, read_crate_list,
+    read_lockfile_versions, read_version_map, target_directory, update_lockfile, CrateInfo,
+    MetadataOptions, PackageSpec, PatternSet, VersionMismatch,
+};
+use crate::diff::write_diff_file;
 use crate::error::{PatchError, Result};
-use crate::source::{GitReference, PatchSource, SourceWorkspacePath, TargetManifestPath};
+use crate::git::{
+    clone_repository, has_uncommitted_changes, head_commit, package_names, GitCloneOptions,
+};
+use crate::logging::{capture, Verbosity, SUMMARY_TARGET};
+use crate::report::{
+    OutputFormat, PatchPlan, PlanAction, PlannedPatch, RemovePlan, RestoredVersion,
+};
+use crate::source::{
+    file_url_to_path, GitReference, PatchSource, SourceWorkspacePath, TargetManifestPath,
+};
 use crate::toml_ops::{
-    add_managed_patch, detect_common_git_url, get_dependencies_table, get_dependency_version,
-    get_managed_patches, get_original_versions, read_cargo_toml, remove_managed_patches,
-    store_original_versions, update_dependency_version, write_cargo_toml,
+    add_dependency_sections, add_managed_patch, clear_metadata, dedupe_patches,
+    dependency_keys_for_package, dependency_package_name, detect_common_git_ref,
+    detect_common_git_url, detect_common_registry, detect_common_registry_index,
+    ensure_metadata_table, find_dependency, find_duplicate_patches, find_edited_patches,
+    find_malformed_patch_key, get_dependencies_table, get_dependency_git_ref,
+    get_dependency_git_url, get_dependency_version, get_managed_patches,
+    get_or_create_patch_source_table, get_original_versions, get_patch_location, get_table_at_path,
+    get_workspace_dependencies_table, has_patch_checksums, is_path_dependency, is_workspace,
+    plan_removal_all_keys, plan_removal_from_versions, plan_removal_matching_crates,
+    read_cargo_toml, record_patch_checksums, remove_empty_patch_tables, remove_managed_patches,
+    remove_managed_patches_matching_crates, remove_patch_entries, remove_planned_patches,
+    render_cargo_toml, section_dependency_keys_for_package, set_git_ref, set_patch_location,
+    set_source_revision, store_original_versions_with, swap_patch_tables,
+    update_dependency_version, write_cargo_toml, write_patch_entries, PatchLocation, PatchStyle,
 };
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 use toml_edit::Table;
+use tracing::{debug, error, info, warn};
+
+/// What `apply` does with crates that already have a patch entry it doesn't manage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MergeStrategy {
+    /// Leave the existing entry alone and don't patch the crate
+    #[default]
+    Skip,
+    /// Replace the existing entry with ours
+    Overwrite,
+    /// Abort without changing anything
+    Error,
+}
+
+/// What local patch paths are written relative to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RelativeTo {
+    /// Absolute paths, which only work on this machine
+    #[default]
+    Absolute,
+    /// The directory of the patched manifest, which is where cargo resolves them
+    Manifest,
+    /// The root of the workspace the patched manifest belongs to
+    Workspace,
+}
+
+/// Which name of a dependency `--pattern` is tested against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MatchOn {
+    /// The key of the dependency entry, which differs from the crate name for renamed entries
+    DepKey,
+    /// The name of the crate the dependency refers to
+    #[default]
+    CrateName,
+}
+
+/// Options controlling how `apply` behaves
+#[derive(Debug, Clone, Default)]
+pub struct ApplyOptions {
+    /// Glob patterns to filter crates (e.g. `rattler-*`), `!` excludes matches
+    pub patterns: Vec<String>,
+    /// Match the patterns case-insensitively
+    pub ignore_case: bool,
+    /// File listing the exact crate names to patch, one per line
+    pub select_from: Option<PathBuf>,
+    /// Use the index URL of a dependency's `registry` as the patch key
+    pub registry_from_dep: bool,
+    /// Clone a git source to check that crates selected by pattern exist in it
+    pub verify_source_crates: bool,
+    /// Git references for individual crates, overriding the source reference
+    pub crate_refs: HashMap<String, GitReference>,
+    /// Source crate names mapped to the target dependency they patch
+    pub name_map: HashMap<String, String>,
+    /// Which name of a dependency the patterns are tested against
+    pub match_on: MatchOn,
+    /// In a workspace root, only patch `[workspace.dependencies]`, never `[dependencies]`
+    pub workspace_dependencies_only: bool,
+    /// Extra dependency tables to scan, as dotted paths (e.g. `package.metadata.tool-deps`)
+    pub sections: Vec<String>,
+    /// Only patch crates whose source version is newer than the current requirement
+    pub only_newer: bool,
+    /// Only patch crates matching one of these `name` or `name@version` specs
+    pub packages: Vec<PackageSpec>,
+    /// Only patch crates whose license mentions one of these SPDX ids (e.g. `MIT OR Apache-2.0`)
+    pub license: Option<String>,
+    /// Refuse to run when the target manifest has uncommitted git changes
+    pub abort_on_dirty: bool,
+    /// Run `cargo update -p` for the patched crates after writing the manifest
+    pub update_lock: bool,
+    /// Use the versions pinned in the source's `Cargo.lock` instead of its manifests
+    pub from_source_lock: bool,
+    /// Record checksums of the managed entries so hand edits are detected on the next apply
+    pub record_checksums: bool,
+    /// Fail when more than this many crates would be patched
+    pub max_crates: Option<usize>,
+    /// Fail instead of warning when source versions do not satisfy requirements
+    pub strict: bool,
+    /// Warn when a patch key needs a newer cargo than the one installed
+    pub check_cargo_version: bool,
+    /// How git sources are cloned when discovering their crates
+    pub git_clone: GitCloneOptions,
+    /// Options forwarded to `cargo metadata`
+    pub metadata: MetadataOptions,
+    /// Write a unified diff of the manifest change to this file
+    pub output_diff_file: Option<PathBuf>,
+    /// Also discover crates in workspaces nested inside the source workspace
+    pub recursive: bool,
+    /// Compute and report the patches without writing the manifest or `.cargo/config.toml`
+    pub dry_run: bool,
+    /// Print a table of what happens to each candidate crate
+    pub print_plan: bool,
+    /// Describe what was done to each candidate crate and why
+    pub explain: bool,
+    /// How crates that already have a patch entry are handled
+    pub merge_strategy: MergeStrategy,
+    /// Whether patch entries are written inline or as sub-tables
+    pub patch_style: PatchStyle,
+    /// What local patch paths are written relative to
+    pub relative_to: RelativeTo,
+    /// Write the patch entries to the manifest or to `.cargo/config.toml`
+    pub write_to: PatchLocation,
+    /// Write `original-versions` as a table with one crate per line instead of inline
+    pub expanded_metadata: bool,
+    /// Directory used instead of the current directory when no manifest path is given
+    pub root: Option<PathBuf>,
+    /// Finish with a one-line summary or print the plan as JSON
+    pub format: OutputFormat,
+}
 
 /// Apply patches from a source to a target Cargo.toml
 pub fn apply_patches(
@@ -16,25 +160,89 @@ pub fn apply_patches(
     target_manifest_path: Option<PathBuf>,
     pattern: Option<&str>,
 ) -> Result<()> {
-    // Determine the target manifest path (defaults to ./Cargo.toml)
-    let default_path = match target_manifest_path {
-        Some(path) => path,
-        None => {
-            let current_dir =
-                std::env::current_dir().map_err(|e| PatchError::CurrentDirError { source: e })?;
-            current_dir.join("Cargo.toml")
-        }
+    let options = ApplyOptions {
+        patterns: pattern.map(|p| p.to_string()).into_iter().collect(),
+        ..Default::default()
     };
-    let target_manifest_path = TargetManifestPath::new(default_path);
+    apply_patches_with_options(source, target_manifest_path, &options).map(|_| ())
+}
 
-    if !target_manifest_path.as_path().exists() {
-        return Err(PatchError::TargetManifestNotFound {
+/// Apply patches from a source to a target Cargo.toml using the given options
+pub fn apply_patches_with_options(
+    source: PatchSource,
+    target_manifest_path: Option<PathBuf>,
+    options: &ApplyOptions,
+) -> Result<PatchPlan> {
+    let plan = apply_to_manifest(source, target_manifest_path, options)?;
+    print_json_report(&plan, options.format)?;
+    Ok(plan)
+}
+
+/// Print the plan to stdout when a JSON format was asked for
+fn print_json_report(plan: &PatchPlan, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Human => {}
+        OutputFormat::Json => println!("{}", plan.to_json()?),
+        OutputFormat::JsonLines => println!("{}", plan.to_json_line()?),
+    }
+    Ok(())
+}
+
+/// Apply patches to one manifest, leaving the JSON report to the caller
+fn apply_to_manifest(
+    source: PatchSource,
+    target_manifest_path: Option<PathBuf>,
+    options: &ApplyOptions,
+) -> Result<PatchPlan> {
+    let started = Instant::now();
+    let target_manifest_path =
+        resolve_target_manifest_path(target_manifest_path, options.root.as_deref())?;
+    debug!(
+        "Applying patches from {} to {}",
+        source,
+        target_manifest_path.as_path().display()
+    );
+
+    // Don't mix our edits with manual ones that haven't been committed
+    if options.abort_on_dirty && has_uncommitted_changes(target_manifest_path.as_path())? {
+        return Err(PatchError::DirtyManifest {
             path: target_manifest_path.as_path().to_path_buf(),
         });
     }
 
     // Read the target Cargo.toml (the manifest we're going to patch)
+    let original_content = match &options.output_diff_file {
+        Some(_) => read_manifest_content(target_manifest_path.as_path())?,
+        None => String::new(),
+    };
     let mut target_doc = read_cargo_toml(target_manifest_path.as_path())?;
+    if !is_workspace(&target_doc) {
+        warn_if_workspace_member(target_manifest_path.as_path(), &options.metadata);
+    }
+
+    // Patches kept in `.cargo/config.toml` are swapped into the manifest while we work on them
+    let config_path = local_config_path(
+        target_manifest_path
+            .as_path()
+            .parent()
+            .unwrap_or_else(|| Path::new(".")),
+    );
+    let previous_location = get_patch_location(&target_doc);
+    let uses_config = previous_location == PatchLocation::CargoConfig
+        || options.write_to == PatchLocation::CargoConfig;
+    let mut config_doc = if uses_config {
+        read_config(&config_path)?
+    } else {
+        toml_edit::DocumentMut::new()
+    };
+    if previous_location == PatchLocation::CargoConfig {
+        swap_patch_tables(&mut target_doc, &mut config_doc);
+    }
+    check_patch_section(&target_doc, target_manifest_path.as_path())?;
+
+    // Hand-edited entries survive the clean up below as unmanaged entries
+    let record_checksums = options.record_checksums || has_patch_checksums(&target_doc);
+    let edited_entries = take_edited_entries(&target_doc);
 
     // Clean up previously managed patches so we always operate from a fresh state
     let existing_managed = get_managed_patches(&target_doc);
@@ -46,7 +254,7 @@ pub fn apply_patches(
             .collect();
 
         if !versions_to_restore.is_empty() {
-            println!(
+            info!(
                 "Restoring original versions for {} crates",
                 versions_to_restore.len()
             );
@@ -61,131 +269,483 @@ pub fn apply_patches(
             }
         }
     }
+    for (patch_key, name, entry) in edited_entries {
+        get_or_create_patch_source_table(
+            &mut target_doc,
+            target_manifest_path.as_path(),
+            &patch_key,
+        )?
+        .insert(&name, entry);
+    }
+    if previous_location == PatchLocation::CargoConfig {
+        swap_patch_tables(&mut target_doc, &mut config_doc);
+    }
+    if options.write_to == PatchLocation::CargoConfig {
+        swap_patch_tables(&mut target_doc, &mut config_doc);
+    }
 
     // Get current dependencies from the target to know which crates to patch
     // Include all dependencies, even those without version fields (e.g., git-only deps)
-    let current_deps = get_dependencies_table(&target_doc)
-        .map(|t| {
-            t.iter()
-                .filter_map(|(k, v)| {
-                    // Extract version if it exists, otherwise use empty string
-                    match v {
-                        toml_edit::Item::Value(val) => {
-                            // Handle simple string version
-                            if let Some(version) = val.as_str() {
-                                Some((k.to_string(), version.to_string()))
-                            }
-                            // Handle inline table
-                            else if let Some(inline_tbl) = val.as_inline_table() {
-                                // Try to get version, but include the dependency even if there's no version
-                                let version = inline_tbl
-                                    .get("version")
-                                    .and_then(|v| v.as_str())
-                                    .unwrap_or("");
-                                Some((k.to_string(), version.to_string()))
-                            } else {
-                                None
-                            }
-                        }
-                        toml_edit::Item::Table(tbl) => {
-                            // Try to get version, but include the dependency even if there's no version
-                            let version = tbl.get("version").and_then(|v| v.as_str()).unwrap_or("");
-                            Some((k.to_string(), version.to_string()))
-                        }
-                        _ => None,
-                    }
-                })
-                .collect::<HashMap<_, _>>()
-        })
+    let dependencies_table = if options.workspace_dependencies_only && is_workspace(&target_doc) {
+        get_workspace_dependencies_table(&target_doc)
+    } else {
+        if options.workspace_dependencies_only {
+            warn!(
+                "{} is not a workspace root; --workspace-dependencies-only has no effect",
+                target_manifest_path.as_path().display()
+            );
+        }
+        get_dependencies_table(&target_doc)
+    };
+    let mut current_deps = dependencies_table
+        .map(dependency_requirements)
         .unwrap_or_default();
+    for section in &options.sections {
+        match get_table_at_path(&target_doc, section) {
+            Some(table) => {
+                for (name, requirement) in dependency_requirements(table) {
+                    current_deps.entry(name).or_insert(requirement);
+                }
+            }
+            None => warn!(
+                "No [{}] table in {}",
+                section,
+                target_manifest_path.as_path().display()
+            ),
+        }
+    }
+
+    // Restrict to an explicit list of crate names when one is given
+    if let Some(list_path) = &options.select_from {
+        let selected = read_crate_list(list_path)?;
+        current_deps.retain(|name, _| selected.contains(name));
+    }
 
+    let mut plan = PatchPlan {
+        dependencies_table: dependencies_table_name(&target_doc).to_string(),
+        ..Default::default()
+    };
+    // The commit checked out in a local source, recorded so reviewers can trace the patches
+    let mut source_revision = None;
     match source {
         PatchSource::LocalPath(source_workspace_path) => {
+            // A relative --path is relative to the manifest, not to where we were started
+            let source_workspace_path = source_workspace_path.relative_to(
+                target_manifest_path
+                    .as_path()
+                    .parent()
+                    .unwrap_or_else(|| Path::new(".")),
+            );
+            if source_workspace_path.is_registry_cache() {
+                warn!(
+                    "{} is in cargo's registry cache; edits there are volatile and may be overwritten",
+                    source_workspace_path.as_path().display()
+                );
+            }
+            source_revision = head_commit(source_workspace_path.as_path());
             apply_local_path_patches(
                 &mut target_doc,
+                &target_manifest_path,
                 &source_workspace_path,
                 &current_deps,
-                pattern,
+                options,
+                &mut plan,
             )?;
         }
-        PatchSource::Git { url, reference } => {
-            apply_git_patches(&mut target_doc, &url, reference, &current_deps, pattern)?;
+        PatchSource::Archive(archive_path) => {
+            // A dry run leaves nothing behind, so it extracts into a directory dropped below
+            let (_scratch, source_workspace_path) = if options.dry_run {
+                let (dir, workspace) = extract_archive_to_temp(&archive_path)?;
+                (Some(dir), workspace)
+            } else {
+                // Falls back to `target/` next to the manifest when cargo can't read it
+                let target_dir =
+                    target_directory(target_manifest_path.as_path(), &options.metadata)
+                        .unwrap_or_else(|| {
+                            target_manifest_path
+                                .as_path()
+                                .parent()
+                                .unwrap_or_else(|| Path::new("."))
+                                .join("target")
+                        });
+                (None, extract_archive(&archive_path, &target_dir)?)
+            };
+            debug!(
+                "Extracted {} to {}",
+                archive_path.display(),
+                source_workspace_path.as_path().display()
+            );
+            apply_local_path_patches(
+                &mut target_doc,
+                &target_manifest_path,
+                &source_workspace_path,
+                &current_deps,
+                options,
+                &mut plan,
+            )?;
         }
+        PatchSource::Git { url, reference } => match local_checkout(&url, &reference, options) {
+            Some(checkout) => {
+                debug!(
+                    "Using the local checkout {} for {}",
+                    checkout.as_path().display(),
+                    url
+                );
+                source_revision = head_commit(checkout.as_path());
+                apply_local_path_patches(
+                    &mut target_doc,
+                    &target_manifest_path,
+                    &checkout,
+                    &current_deps,
+                    options,
+                    &mut plan,
+                )?;
+            }
+            None => {
+                apply_git_patches(
+                    &mut target_doc,
+                    &target_manifest_path,
+                    &url,
+                    reference,
+                    &current_deps,
+                    options,
+                    &mut plan,
+                )?;
+            }
+        },
     }
 
-    // Write back the modified target Cargo.toml
-    write_cargo_toml(target_manifest_path.as_path(), &target_doc)?;
+    for (name, keys) in find_duplicate_patches(&target_doc) {
+        warn!(
+            "{} is patched under several keys ({}); the patches may conflict",
+            name,
+            keys.join(", ")
+        );
+    }
 
-    println!(
-        "Successfully applied patches to {}",
-        target_manifest_path.as_path().display()
-    );
-    Ok(())
+    if options.print_plan {
+        plan.print_human();
+    }
+    if options.explain {
+        plan.print_explanation();
+    }
+
+    // Skipping every candidate must not leave an empty `[patch.<key>]` behind
+    remove_empty_patch_tables(&mut target_doc);
+    if record_checksums && !get_managed_patches(&target_doc).is_empty() {
+        record_patch_checksums(&mut target_doc);
+    }
+    if !get_managed_patches(&target_doc).is_empty() {
+        set_patch_location(&mut target_doc, options.write_to);
+        set_source_revision(&mut target_doc, source_revision.as_deref());
+    }
+    if options.check_cargo_version {
+        warn_unsupported_patch_keys(&get_managed_patches(&target_doc));
+    }
+    if options.write_to == PatchLocation::CargoConfig {
+        swap_patch_tables(&mut target_doc, &mut config_doc);
+    }
+
+    // Write back the modified target Cargo.toml; a dry run stops short of touching any file
+    // but the diff
+    let new_content = render_cargo_toml(target_manifest_path.as_path(), &target_doc);
+    if !options.dry_run {
+        write_cargo_toml(target_manifest_path.as_path(), &target_doc)?;
+        if uses_config {
+            write_config(&config_path, &config_doc)?;
+            debug!("Wrote patches to {}", config_path.display());
+        }
+    }
+
+    if options.update_lock && !options.dry_run {
+        let patched: Vec<_> = plan
+            .patches
+            .iter()
+            .filter(|p| p.action != PlanAction::Skip)
+            .map(|p| p.name.clone())
+            .collect();
+        if !patched.is_empty() {
+            info!("Updating Cargo.lock for {} crates", patched.len());
+            update_lockfile(target_manifest_path.as_path(), &patched, &options.metadata)?;
+        }
+    }
+
+    if let Some(output) = &options.output_diff_file {
+        write_diff_file(
+            output,
+            target_manifest_path.as_path(),
+            &original_content,
+            &new_content,
+        )?;
+        debug!("Wrote diff to {}", output.display());
+    }
+
+    plan.patched = plan
+        .patches
+        .iter()
+        .filter(|p| p.action != PlanAction::Skip)
+        .count();
+    plan.skipped = plan.patches.len() - plan.patched;
+    let elapsed = started.elapsed();
+    plan.elapsed_ms = elapsed.as_millis() as u64;
+    plan.dry_run = options.dry_run;
+    if options.format == OutputFormat::Human {
+        log_summary(&plan, target_manifest_path.as_path(), elapsed);
+    }
+    Ok(plan)
 }
 
 /// Apply patches from a local source workspace to the target manifest
 fn apply_local_path_patches(
     target_doc: &mut toml_edit::DocumentMut,
+    target_manifest_path: &TargetManifestPath,
     source_workspace_path: &SourceWorkspacePath,
     current_deps: &HashMap<String, String>,
-    pattern: Option<&str>,
+    options: &ApplyOptions,
+    plan: &mut PatchPlan,
 ) -> Result<()> {
+    // Read the lockfile first, cargo metadata would create a missing one
+    let locked_versions = if options.from_source_lock {
+        Some(read_lockfile_versions(source_workspace_path.as_path())?)
+    } else {
+        None
+    };
+
     // Query the source workspace for available crates
-    let source_workspace_crates = query_workspace_crates(source_workspace_path.as_path())?;
+    let mut source_workspace_crates =
+        discover_source_crates(source_workspace_path.as_path(), options)?;
+    if let Some(locked_versions) = &locked_versions {
+        for crate_info in &mut source_workspace_crates {
+            if let Some(version) = locked_versions.get(&crate_info.name) {
+                crate_info.version = version.clone();
+            }
+        }
+    }
+
+    // Wire source crates to differently named target dependencies
+    let source_workspace_crates = apply_name_map(source_workspace_crates, &options.name_map);
 
     // Filter by pattern if provided
-    let source_workspace_crates = filter_crates_by_pattern(source_workspace_crates, pattern)?;
+    let source_workspace_crates = match options.match_on {
+        MatchOn::CrateName => filter_crates_by_pattern(
+            source_workspace_crates,
+            &options.patterns,
+            options.ignore_case,
+        )
+        .map_err(|err| explain_excluded_crates(err, source_workspace_path.as_path(), options))?,
+        MatchOn::DepKey => filter_crates_by_dep_key(target_doc, source_workspace_crates, options)?,
+    };
+
+    let source_workspace_crates =
+        filter_crates_by_package_spec(source_workspace_crates, &options.packages)?;
+
+    // Keep crates without a matching license out of the patch, e.g. for compliance
+    let source_workspace_crates = match &options.license {
+        Some(wanted) => source_workspace_crates
+            .into_iter()
+            .filter(|crate_info| {
+                let matches = crate_info
+                    .license
+                    .as_deref()
+                    .is_some_and(|license| license_matches(license, wanted));
+                if !matches {
+                    debug!(
+                        "Not patching {} because its license {:?} does not match {}",
+                        crate_info.name, crate_info.license, wanted
+                    );
+                }
+                matches
+            })
+            .collect(),
+        None => source_workspace_crates,
+    };
 
     // Filter to only crates that are in current target dependencies
-    let crates_to_patch: Vec<_> = source_workspace_crates
+    let (mut crates_to_patch, unused): (Vec<_>, Vec<_>) = source_workspace_crates
         .into_iter()
-        .filter(|c| current_deps.contains_key(&c.name))
-        .collect();
+        .partition(|c| current_deps.contains_key(&c.name));
+    crates_to_patch.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Without a selection every other source crate is "unused", which says nothing
+    if !unused.is_empty() && (!options.patterns.is_empty() || !options.packages.is_empty()) {
+        let mut names: Vec<_> = unused.iter().map(|c| c.name.as_str()).collect();
+        names.sort();
+        info!(
+            "  Matched in the source but not a dependency: {}",
+            names.join(", ")
+        );
+    }
+
+    // Leave crates the source has nothing newer for on their current release
+    if options.only_newer {
+        crates_to_patch.retain(|crate_info| {
+            let requirement = current_deps
+                .get(&crate_info.name)
+                .map(String::as_str)
+                .unwrap_or_default();
+            let newer = is_newer_than_requirement(&crate_info.version, requirement);
+            if !newer {
+                info!(
+                    "  Skipping {} because its source version {} is not newer than {:?}",
+                    crate_info.name, crate_info.version, requirement
+                );
+                plan.patches.push(skipped_patch(
+                    &crate_info.name,
+                    current_deps,
+                    &format!("its source version {} is not newer", crate_info.version),
+                ));
+            }
+            newer
+        });
+    }
 
     if crates_to_patch.is_empty() {
-        println!("No matching crates found in current dependencies");
+        warn!("No matching crates found in current dependencies");
         return Ok(());
     }
 
     let existing_patched_crates = collect_existing_patched_crates(target_doc);
+    ensure_no_existing_entries(
+        &existing_patched_crates,
+        crates_to_patch.iter().map(|c| c.name.as_str()),
+        options.merge_strategy,
+    )?;
     let mut managed_crates = Vec::new();
+    let mut overwritten = HashSet::new();
     for crate_info in crates_to_patch {
-        if existing_patched_crates.contains(&crate_info.name) {
-            println!(
-                "  Skipping {} because a patch entry already exists",
+        if is_sysroot_crate(&crate_info.name) {
+            warn_sysroot_crate(&crate_info.name);
+            plan.patches.push(skipped_patch(
+                &crate_info.name,
+                current_deps,
+                SYSROOT_SKIP_REASON,
+            ));
+            continue;
+        }
+        let has_entry = existing_patched_crates.contains(&crate_info.name);
+        if has_entry && options.merge_strategy == MergeStrategy::Skip {
+            warn!(
+                "Skipping {} because a patch entry already exists",
                 crate_info.name
             );
+            plan.patches.push(skipped_patch(
+                &crate_info.name,
+                current_deps,
+                EXISTING_ENTRY_SKIP_REASON,
+            ));
             continue;
         }
+        if has_path_dependency(target_doc, &crate_info.name) {
+            warn!(
+                "Skipping {} because it is already a path dependency",
+                crate_info.name
+            );
+            plan.patches.push(skipped_patch(
+                &crate_info.name,
+                current_deps,
+                PATH_DEPENDENCY_SKIP_REASON,
+            ));
+            continue;
+        }
+        if has_entry {
+            overwrite_existing_entry(target_doc, &crate_info.name);
+            overwritten.insert(crate_info.name.clone());
+        }
         managed_crates.push(crate_info);
     }
 
     if managed_crates.is_empty() {
-        println!("No crates to patch after skipping existing patch entries");
+        warn!("No crates to patch after skipping existing patch entries");
         return Ok(());
     }
+    ensure_within_max_crates(managed_crates.len(), options)?;
 
     // Collect crate names for git URL detection in the target
     let crate_names: Vec<String> = managed_crates.iter().map(|c| c.name.clone()).collect();
 
     // Detect if these dependencies in the target come from a common git URL
     let git_url = detect_common_git_url(target_doc, &crate_names);
+    // Remember the branch, tag or rev they share, which the path patch stands in for
+    let git_ref = git_url
+        .as_ref()
+        .and_then(|_| detect_common_git_ref(target_doc, &crate_names));
 
     // Store original versions from target dependencies table (not our stored versions)
-    // For dependencies without version fields (like git-only), store empty string
-    let mut original_versions = HashMap::new();
-    if let Some(deps_table) = get_dependencies_table(target_doc) {
-        for crate_name in &crate_names {
-            if let Some(dep_value) = deps_table.get(crate_name) {
-                let version = get_dependency_version(dep_value).unwrap_or_default();
-                original_versions.insert(crate_name.clone(), version);
+    let original_versions = collect_original_versions(target_doc, &crate_names, &options.sections);
+
+    // Every dependency entry, renamed or not, is checked against and updated to its source crate
+    let entry_crates: Vec<CrateInfo> = managed_crates
+        .iter()
+        .flat_map(|crate_info| {
+            let mut keys = dependency_keys_for_package(target_doc, &crate_info.name);
+            for key in
+                section_dependency_keys_for_package(target_doc, &options.sections, &crate_info.name)
+            {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
             }
+            keys.into_iter().map(|key| CrateInfo {
+                name: key,
+                ..crate_info.clone()
+            })
+        })
+        .collect();
+
+    // A placeholder version would turn every requirement into `0.0.0`, so keep those as they are
+    for crate_info in &managed_crates {
+        if is_placeholder_version(&crate_info.version) {
+            warn!(
+                "Not updating the requirement for {} because its source version {:?} looks like a placeholder",
+                crate_info.name, crate_info.version
+            );
+        }
+    }
+    let entry_crates: Vec<CrateInfo> = entry_crates
+        .into_iter()
+        .filter(|crate_info| !is_placeholder_version(&crate_info.version))
+        .collect();
+
+    // Report every requirement the source versions fail in one go
+    let mismatches = find_version_mismatches(&entry_crates, &original_versions);
+    if !mismatches.is_empty() {
+        print_version_mismatches(&mismatches);
+        if options.strict {
+            return Err(PatchError::VersionMismatches {
+                crates: mismatches.into_iter().map(|m| m.name).collect(),
+            });
         }
     }
 
+    // Absolute paths outside the project tree only work on this machine
+    let manifest_dir = target_manifest_path
+        .as_path()
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
+    let outside = match options.relative_to {
+        RelativeTo::Absolute => crates_outside_dir(manifest_dir, &managed_crates),
+        RelativeTo::Manifest | RelativeTo::Workspace => Vec::new(),
+    };
+    if !outside.is_empty() {
+        if options.strict {
+            return Err(PatchError::PatchPathsOutsideProject {
+                dir: manifest_dir.to_path_buf(),
+                crates: outside,
+            });
+        }
+        warn!(
+            "Patch paths for {} point outside {}; they won't work for other contributors",
+            outside.join(", "),
+            manifest_dir.display()
+        );
+    }
+
+    // Recorded first, so the `--section` tables are updated (and later restored) too
+    add_dependency_sections(target_doc, &options.sections);
+
     // Update versions in target [workspace.dependencies] to match source local versions
     // Only update if the original dependency had a version field
-    for crate_info in &managed_crates {
+    for crate_info in &entry_crates {
         if let Some(original_version) = original_versions.get(&crate_info.name) {
             if !original_version.is_empty() {
                 update_dependency_version(target_doc, &crate_info.name, &crate_info.version)?;
@@ -193,74 +753,643 @@ fn apply_local_path_patches(
         }
     }
 
+    let path_base = match options.relative_to {
+        RelativeTo::Absolute => None,
+        RelativeTo::Manifest => Some(manifest_dir.to_path_buf()),
+        RelativeTo::Workspace => Some(
+            parent_workspace_root(target_manifest_path.as_path(), &options.metadata)
+                .unwrap_or_else(|| manifest_dir.to_path_buf()),
+        ),
+    };
+
     // Create patch entries
-    let mut patch_table = Table::new();
+    let mut patch_entries = Vec::new();
     for crate_info in &managed_crates {
         let mut crate_patch = toml_edit::InlineTable::new();
 
-        // Get the path to the crate (directory containing its Cargo.toml)
-        let crate_path = crate_info
-            .manifest_path
-            .parent()
-            .expect("Crate manifest should have a parent directory");
+        let crate_path = match &path_base {
+            Some(base) => relative_path(crate_info.package_root(), base),
+            None => crate_info.package_root().to_path_buf(),
+        };
 
         // Always use forward slashes for paths in TOML (cross-platform compatibility)
         let path_str = crate_path.display().to_string().replace('\\', "/");
-        crate_patch.insert("path", path_str.into());
+        crate_patch.insert("path", path_str.as_str().into());
+        // A crate mapped with --name-map keeps its own package name in the source
+        if let Some(package) = mapped_source_name(&options.name_map, &crate_info.name) {
+            crate_patch.insert("package", package.into());
+        }
 
-        patch_table.insert(
-            &crate_info.name,
-            toml_edit::Item::Value(toml_edit::Value::InlineTable(crate_patch)),
-        );
+        patch_entries.push((crate_info.name.clone(), crate_patch));
 
-        println!(
+        info!(
             "  Patching {} {} -> {}",
             crate_info.name,
             crate_info.version,
             crate_path.display()
         );
+        plan.patches.push(PlannedPatch {
+            name: crate_info.name.clone(),
+            old_requirement: old_requirement(&crate_info.name, current_deps),
+            new_version: Some(crate_info.version.clone()),
+            action: patch_action(&overwritten, &crate_info.name),
+            target: path_str,
+            patch_key: None,
+            requirement_updated: old_requirement(&crate_info.name, current_deps).is_some()
+                && !is_placeholder_version(&crate_info.version),
+            skip_reason: None,
+        });
     }
 
-    // Determine patch key (crates-io or git URL)
+    // Determine patch key (crates-io, git URL, registry index or alternative registry)
+    let registry_index = detect_common_registry_index(target_doc, &crate_names);
+    let registry_key = if git_url.is_none() && registry_index.is_none() && options.registry_from_dep
+    {
+        registry_patch_key(target_doc, target_manifest_path, &crate_names)?
+    } else {
+        None
+    };
     let patch_key = if let Some(url) = git_url.as_ref() {
-        println!("  Detected git source: {}", url);
+        debug!("Detected git source: {}", url);
         url.as_str()
+    } else if let Some(index) = registry_index.as_ref() {
+        debug!("Detected registry index: {}", index);
+        index.as_str()
+    } else if let Some(key) = registry_key.as_ref() {
+        key.as_str()
     } else {
         "crates-io"
     };
 
     // Store original versions and track managed patch in target metadata
-    store_original_versions(target_doc, &original_versions)?;
+    store_original_versions_with(target_doc, &original_versions, options.expanded_metadata)?;
     add_managed_patch(target_doc, patch_key)?;
+    set_plan_patch_key(plan, patch_key);
+    if let Some(reference) = &git_ref {
+        debug!("Detected git reference: {}", reference);
+        set_git_ref(target_doc, reference);
+    }
 
     // Add patch section to target document, preserving any existing patches
-    let patch_section = target_doc
-        .entry("patch")
-        .or_insert(toml_edit::Item::Table(Table::new()))
-        .as_table_mut()
-        .expect("just inserted a table");
+    write_patch_entries(
+        target_doc,
+        target_manifest_path.as_path(),
+        patch_key,
+        &patch_entries,
+        options.patch_style,
+    )?;
+
+    Ok(())
+}
+
+/// Names of the crates whose directory is not inside `dir`, comparing canonical paths
+fn crates_outside_dir(dir: &Path, crates: &[CrateInfo]) -> Vec<String> {
+    let canonical =
+        |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = canonical(dir);
+    crates
+        .iter()
+        .filter(|crate_info| !canonical(crate_info.package_root()).starts_with(&dir))
+        .map(|crate_info| crate_info.name.clone())
+        .collect()
+}
 
-    // Get or create the patch source table (e.g., patch.crates-io)
-    let source_table = patch_section
-        .entry(patch_key)
-        .or_insert(toml_edit::Item::Table(Table::new()))
-        .as_table_mut()
-        .expect("just inserted a table");
+/// `path` relative to the directory `base`, e.g. `../rattler/crates/rattler-one`.
+///
+/// Falls back to `path` itself when the two share no root, like different Windows drives.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let canonical =
+        |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let (path, base) = (canonical(path), canonical(base));
 
-    // Add each crate patch, preserving existing patches
-    for (crate_name, patch_spec) in patch_table.iter() {
-        source_table.insert(crate_name, patch_spec.clone());
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    if path_components.peek() != base_components.peek() {
+        return path;
+    }
+    while let (Some(a), Some(b)) = (path_components.peek(), base_components.peek()) {
+        if a != b {
+            break;
+        }
+        path_components.next();
+        base_components.next();
     }
 
-    Ok(())
+    let relative: PathBuf = base_components
+        .map(|_| std::path::Component::ParentDir)
+        .chain(path_components)
+        .collect();
+    if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative
+    }
+}
+
+/// Check that a source workspace resolves and provides crates matching the patterns.
+///
+/// Only the source is read; the matching crates are returned sorted by name.
+pub fn validate_source(
+    source_workspace_path: &Path,
+    options: &ApplyOptions,
+) -> Result<Vec<CrateInfo>> {
+    let crates = discover_source_crates(source_workspace_path, options)?;
+    let mut crates = filter_crates_by_pattern(crates, &options.patterns, options.ignore_case)
+        .map_err(|err| explain_excluded_crates(err, source_workspace_path, options))?;
+    crates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(crates)
+}
+
+/// Turn a pattern that matched nothing into an error naming the source crates it would have
+/// matched, had `[workspace] exclude` not left them out
+fn explain_excluded_crates(
+    err: PatchError,
+    workspace_path: &Path,
+    options: &ApplyOptions,
+) -> PatchError {
+    let PatchError::NoMatchingCrates { pattern } = err else {
+        return err;
+    };
+    match find_excluded_crates(workspace_path, &options.patterns, options.ignore_case) {
+        Ok(crates) if !crates.is_empty() => {
+            PatchError::NoMatchingCratesExcluded { pattern, crates }
+        }
+        _ => PatchError::NoMatchingCrates { pattern },
+    }
+}
+
+/// Apply one source to several manifests, processing up to `concurrency` of them at a time.
+///
+/// Each manifest's output and JSON report are buffered and printed in the order given, so they
+/// do not depend on scheduling. Every manifest is attempted; the first error is returned and
+/// later ones logged.
+pub fn apply_patches_to_manifests(
+    source: &PatchSource,
+    manifests: &[PathBuf],
+    options: &ApplyOptions,
+    concurrency: usize,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let next = AtomicUsize::new(0);
+    // Each manifest's result and buffered output, by position
+    let outcomes = Mutex::new(manifests.iter().map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..concurrency.clamp(1, manifests.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(manifest) = manifests.get(index) else {
+                    break;
+                };
+                let outcome = capture(verbosity, || {
+                    apply_to_manifest(source.clone(), Some(manifest.clone()), options)
+                });
+                outcomes.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(outcome);
+            });
+        }
+    });
+
+    let mut first_error = None;
+    let outcomes = outcomes.into_inner().unwrap_or_else(|e| e.into_inner());
+    for (manifest, outcome) in manifests.iter().zip(outcomes) {
+        let (result, output) = outcome.expect("every manifest is processed");
+        eprint!("{}", output);
+        let result = result.and_then(|plan| print_json_report(&plan, options.format));
+        if let Err(err) = result {
+            if first_error.is_none() {
+                first_error = Some(err);
+            } else {
+                error!("Failed to apply patches to {}: {}", manifest.display(), err);
+            }
+        }
+    }
+
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Expand `*` and `?` in manifest paths, keeping paths without them as they are.
+///
+/// Each glob must match at least one file so a typo doesn't silently patch nothing.
+pub fn expand_manifest_globs(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut manifests = Vec::new();
+    for path in paths {
+        let pattern = path.to_string_lossy().to_string();
+        if !pattern.contains(['*', '?']) {
+            manifests.push(path);
+            continue;
+        }
+
+        let matched = glob_files(&path)?;
+        if matched.is_empty() {
+            return Err(PatchError::NoManifestsMatched { pattern });
+        }
+        manifests.extend(matched);
+    }
+    Ok(manifests)
+}
+
+/// Files matching a path whose components may contain `*` and `?`, sorted
+fn glob_files(pattern: &Path) -> Result<Vec<PathBuf>> {
+    let mut candidates = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let part = component.as_os_str().to_string_lossy();
+        if !part.contains(['*', '?']) {
+            for candidate in &mut candidates {
+                candidate.push(component);
+            }
+            continue;
+        }
+
+        // Like a shell, wildcards don't match hidden entries
+        let re = compile_pattern(&part, false)?;
+        candidates = candidates
+            .iter()
+            .flat_map(|dir| {
+                let read_from = if dir.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    dir.as_path()
+                };
+                fs::read_dir(read_from)
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .map(|entry| entry.file_name().to_string_lossy().to_string())
+                    .filter(|name| !name.starts_with('.') && re.is_match(name))
+                    .map(|name| dir.join(name))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+    }
+
+    candidates.retain(|path| path.is_file());
+    candidates.sort();
+    Ok(candidates)
+}
+
+/// Raw manifest text, used to diff the change
+fn read_manifest_content(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).map_err(|e| PatchError::CargoTomlReadError {
+        path: path.to_path_buf(),
+        source: e,
+    })
+}
+
+/// Query the crates of a source workspace, including nested workspaces with `recursive`
+fn discover_source_crates(workspace_path: &Path, options: &ApplyOptions) -> Result<Vec<CrateInfo>> {
+    if options.recursive {
+        query_workspace_crates_recursive(workspace_path, &options.metadata)
+    } else {
+        query_workspace_crates(workspace_path, &options.metadata)
+    }
+}
+
+/// The checkout behind a `file://` git source, which is patched like a local path.
+///
+/// Sources with a branch, tag or rev, per-crate refs or `--verify-source-crate-exists` are
+/// still cloned, since the working tree may not be at that reference.
+fn local_checkout(
+    url: &str,
+    reference: &Option<GitReference>,
+    options: &ApplyOptions,
+) -> Option<SourceWorkspacePath> {
+    if reference.is_some() || !options.crate_refs.is_empty() || options.verify_source_crates {
+        return None;
+    }
+    file_url_to_path(url).map(SourceWorkspacePath::new)
+}
+
+/// Requirement of every entry in a dependency table, keyed by package name; entries without
+/// a version (e.g. git-only dependencies) map to an empty string
+fn dependency_requirements(table: &Table) -> HashMap<String, String> {
+    table
+        .iter()
+        .filter_map(|(k, v)| {
+            // Key by package name so renamed entries match their source crate
+            let k = dependency_package_name(k, v);
+            // Extract version if it exists, otherwise use empty string
+            match v {
+                toml_edit::Item::Value(val) => {
+                    // Handle simple string version
+                    if let Some(version) = val.as_str() {
+                        Some((k.to_string(), version.to_string()))
+                    }
+                    // Handle inline table
+                    else if let Some(inline_tbl) = val.as_inline_table() {
+                        // Try to get version, but include the dependency even if there's no version
+                        let version = inline_tbl
+                            .get("version")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("");
+                        Some((k.to_string(), version.to_string()))
+                    } else {
+                        None
+                    }
+                }
+                toml_edit::Item::Table(tbl) => {
+                    // Try to get version, but include the dependency even if there's no version
+                    let version = tbl.get("version").and_then(|v| v.as_str()).unwrap_or("");
+                    Some((k.to_string(), version.to_string()))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// The dependency table `apply` reads, as written in a table header
+fn dependencies_table_name(doc: &toml_edit::DocumentMut) -> &'static str {
+    if get_workspace_dependencies_table(doc).is_some() {
+        "workspace.dependencies"
+    } else {
+        "dependencies"
+    }
+}
+
+/// Warn that cargo will ignore `[patch]` in a manifest that is a member of another workspace
+fn warn_if_workspace_member(manifest_path: &Path, metadata_options: &MetadataOptions) {
+    if let Some(root) = parent_workspace_root(manifest_path, metadata_options) {
+        warn!(
+            "{} is a member of the workspace at {}; cargo ignores [patch] outside the workspace root. \
+             Pass --manifest-path {} instead",
+            manifest_path.display(),
+            root.display(),
+            root.join("Cargo.toml").display()
+        );
+    }
+}
+
+/// Rename source crates listed in `name_map` to the target dependency they should patch
+fn apply_name_map(crates: Vec<CrateInfo>, name_map: &HashMap<String, String>) -> Vec<CrateInfo> {
+    for source_name in name_map.keys() {
+        if !crates.iter().any(|c| &c.name == source_name) {
+            warn!(
+                "--name-map refers to {}, which is not a crate in the source",
+                source_name
+            );
+        }
+    }
+
+    crates
+        .into_iter()
+        .map(|crate_info| match name_map.get(&crate_info.name) {
+            Some(target_name) => {
+                debug!(
+                    "Mapping source crate {} to {}",
+                    crate_info.name, target_name
+                );
+                CrateInfo {
+                    name: target_name.clone(),
+                    ..crate_info
+                }
+            }
+            None => crate_info,
+        })
+        .collect()
+}
+
+/// The source crate `--name-map` maps onto `target_name`, if its name differs
+fn mapped_source_name<'a>(
+    name_map: &'a HashMap<String, String>,
+    target_name: &str,
+) -> Option<&'a str> {
+    name_map
+        .iter()
+        .find(|(source_name, mapped)| *mapped == target_name && *source_name != target_name)
+        .map(|(source_name, _)| source_name.as_str())
+}
+
+/// Names of the target dependency on `package` that `--pattern` is tested against
+fn match_names(doc: &toml_edit::DocumentMut, package: &str, match_on: MatchOn) -> Vec<String> {
+    match match_on {
+        MatchOn::CrateName => vec![package.to_string()],
+        MatchOn::DepKey => dependency_keys_for_package(doc, package),
+    }
+}
+
+/// Keep the source crates whose dependency key in the target matches the patterns
+fn filter_crates_by_dep_key(
+    doc: &toml_edit::DocumentMut,
+    crates: Vec<CrateInfo>,
+    options: &ApplyOptions,
+) -> Result<Vec<CrateInfo>> {
+    if options.patterns.is_empty() {
+        return Ok(crates);
+    }
+
+    let set = PatternSet::compile(&options.patterns, options.ignore_case)?;
+    let matched: Vec<_> = crates
+        .into_iter()
+        .filter(|c| {
+            match_names(doc, &c.name, MatchOn::DepKey)
+                .iter()
+                .any(|key| set.is_match(key))
+        })
+        .collect();
+    if matched.is_empty() {
+        return Err(PatchError::NoMatchingCrates {
+            pattern: options.patterns.join(", "),
+        });
+    }
+    Ok(matched)
+}
+
+/// One-line result of an apply, the only progress shown with `--summary-only`
+fn log_summary(plan: &PatchPlan, manifest_path: &Path, elapsed: Duration) {
+    let crates = if plan.patched == 1 { "crate" } else { "crates" };
+    let under = plan
+        .patches
+        .iter()
+        .find_map(|p| p.patch_key.as_deref())
+        .map(|patch_key| format!(" under [patch.{}]", patch_key))
+        .unwrap_or_default();
+    if plan.dry_run {
+        info!(
+            target: SUMMARY_TARGET,
+            "Dry run: would patch {} {}{} ({} skipped), no changes written to {}",
+            plan.patched,
+            crates,
+            under,
+            plan.skipped,
+            manifest_path.display()
+        );
+    } else {
+        info!(
+            target: SUMMARY_TARGET,
+            "Patched {} {}{} ({} skipped) in {:.1}s.",
+            plan.patched,
+            crates,
+            under,
+            plan.skipped,
+            elapsed.as_secs_f64()
+        );
+    }
+}
+
+/// Print a single table of all crates whose requirement the source version fails
+fn print_version_mismatches(mismatches: &[VersionMismatch]) {
+    let name_width = mismatches
+        .iter()
+        .map(|m| m.name.len())
+        .chain(["crate".len()])
+        .max()
+        .unwrap_or_default();
+    let req_width = mismatches
+        .iter()
+        .map(|m| m.requirement.len())
+        .chain(["requirement".len()])
+        .max()
+        .unwrap_or_default();
+
+    let mut table = format!(
+        "source versions do not satisfy {} dependency requirements:\n  {:<name_width$}  {:<req_width$}  source",
+        mismatches.len(),
+        "crate",
+        "requirement"
+    );
+    for mismatch in mismatches {
+        table.push_str(&format!(
+            "\n  {:<name_width$}  {:<req_width$}  {}",
+            mismatch.name, mismatch.requirement, mismatch.version
+        ));
+    }
+    warn!("{}", table);
+}
+
+/// The current requirement of a dependency, if it has one
+fn old_requirement(name: &str, current_deps: &HashMap<String, String>) -> Option<String> {
+    current_deps
+        .get(name)
+        .filter(|version| !version.is_empty())
+        .cloned()
+}
+
+const SYSROOT_SKIP_REASON: &str = "it is a standard library crate";
+const EXISTING_ENTRY_SKIP_REASON: &str = "a patch entry already exists";
+const PATH_DEPENDENCY_SKIP_REASON: &str = "it is already a path dependency";
+
+/// A plan row for a crate `apply` leaves alone, and why
+fn skipped_patch(name: &str, current_deps: &HashMap<String, String>, reason: &str) -> PlannedPatch {
+    PlannedPatch {
+        name: name.to_string(),
+        old_requirement: old_requirement(name, current_deps),
+        new_version: None,
+        action: PlanAction::Skip,
+        target: String::new(),
+        patch_key: None,
+        requirement_updated: false,
+        skip_reason: Some(reason.to_string()),
+    }
+}
+
+/// Record the patch key on the rows patched since the key was last set
+fn set_plan_patch_key(plan: &mut PatchPlan, patch_key: &str) {
+    for patch in &mut plan.patches {
+        if patch.action != PlanAction::Skip && patch.patch_key.is_none() {
+            patch.patch_key = Some(patch_key.to_string());
+        }
+    }
+}
+
+fn patch_action(overwritten: &HashSet<String>, name: &str) -> PlanAction {
+    if overwritten.contains(name) {
+        PlanAction::Overwrite
+    } else {
+        PlanAction::Patch
+    }
+}
+
+/// Fail when more crates would be patched than `--max-crates` allows
+fn ensure_within_max_crates(count: usize, options: &ApplyOptions) -> Result<()> {
+    match options.max_crates {
+        Some(max) if count > max => Err(PatchError::TooManyCrates { count, max }),
+        _ => Ok(()),
+    }
+}
+
+/// With [`MergeStrategy::Error`], fail if any candidate already has a patch entry
+fn ensure_no_existing_entries<'a>(
+    existing_patched_crates: &HashSet<String>,
+    candidates: impl Iterator<Item = &'a str>,
+    merge_strategy: MergeStrategy,
+) -> Result<()> {
+    if merge_strategy != MergeStrategy::Error {
+        return Ok(());
+    }
+
+    let mut crates: Vec<_> = candidates
+        .filter(|name| existing_patched_crates.contains(*name))
+        .map(|name| name.to_string())
+        .collect();
+    if crates.is_empty() {
+        return Ok(());
+    }
+    crates.sort();
+    Err(PatchError::ExistingPatchEntries { crates })
+}
+
+/// Drop the entry we're about to replace with [`MergeStrategy::Overwrite`]
+fn overwrite_existing_entry(doc: &mut toml_edit::DocumentMut, crate_name: &str) {
+    remove_patch_entries(doc, crate_name);
+    info!("  Overwriting the existing patch entry for {}", crate_name);
+}
+
+/// Crates shipped with the toolchain's sysroot, which `[patch]` cannot replace
+const SYSROOT_CRATES: &[&str] = &[
+    "alloc",
+    "compiler_builtins",
+    "core",
+    "panic_abort",
+    "panic_unwind",
+    "proc_macro",
+    "std",
+    "test",
+];
+
+fn is_sysroot_crate(name: &str) -> bool {
+    SYSROOT_CRATES.contains(&name)
+}
+
+fn warn_sysroot_crate(name: &str) {
+    warn!(
+        "Skipping {} because standard library crates cannot be patched with [patch]; \
+         build them from source with -Zbuild-std instead",
+        name
+    );
+}
+
+/// Copies of the managed entries edited by hand since their checksum was recorded
+fn take_edited_entries(doc: &toml_edit::DocumentMut) -> Vec<(String, String, toml_edit::Item)> {
+    find_edited_patches(doc)
+        .into_iter()
+        .filter_map(|(patch_key, name)| {
+            let entry = doc.get("patch")?.get(&patch_key)?.get(&name)?.clone();
+            warn!(
+                "The [patch.{}] entry for {} was edited by hand; keeping it as an unmanaged entry",
+                patch_key, name
+            );
+            Some((patch_key, name, entry))
+        })
+        .collect()
+}
+
+/// Refuse to work on a `[patch]` section whose sources are not tables
+fn check_patch_section(doc: &toml_edit::DocumentMut, manifest_path: &Path) -> Result<()> {
+    match find_malformed_patch_key(doc) {
+        Some(key) => Err(PatchError::MalformedPatchSection {
+            path: manifest_path.to_path_buf(),
+            key,
+        }),
+        None => Ok(()),
+    }
 }
 
 fn collect_existing_patched_crates(doc: &toml_edit::DocumentMut) -> HashSet<String> {
     let mut result = HashSet::new();
 
-    if let Some(patch_section) = doc.get("patch").and_then(|p| p.as_table()) {
+    if let Some(patch_section) = doc.get("patch").and_then(|p| p.as_table_like()) {
         for (_, source_item) in patch_section.iter() {
-            if let Some(source_table) = source_item.as_table() {
+            if let Some(source_table) = source_item.as_table_like() {
                 for (crate_name, _) in source_table.iter() {
                     result.insert(crate_name.to_string());
                 }
@@ -271,72 +1400,246 @@ fn collect_existing_patched_crates(doc: &toml_edit::DocumentMut) -> HashSet<Stri
     result
 }
 
+/// Resolve the patch key for dependencies that come from a common alternative registry.
+///
+/// Cargo keys alternative registry patches by index URL; when the registry is not
+/// configured we fall back to the registry name, which cargo also accepts.
+fn registry_patch_key(
+    doc: &toml_edit::DocumentMut,
+    target_manifest_path: &TargetManifestPath,
+    crate_names: &[String],
+) -> Result<Option<String>> {
+    let Some(registry) = detect_common_registry(doc, crate_names) else {
+        return Ok(None);
+    };
+
+    let manifest_dir = target_manifest_path
+        .as_path()
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
+
+    match resolve_registry_index(&registry, manifest_dir)? {
+        Some(index) => {
+            debug!("Detected registry {}: {}", registry, index);
+            Ok(Some(index))
+        }
+        None => {
+            warn!(
+                "Registry {} is not configured, using its name as the patch key",
+                registry
+            );
+            Ok(Some(registry))
+        }
+    }
+}
+
+/// Warn about patch keys the installed cargo is too old to understand
+fn warn_unsupported_patch_keys(patch_keys: &[String]) {
+    let required: Vec<_> = patch_keys
+        .iter()
+        .filter_map(|key| patch_key_required_cargo(key).map(|required| (key, required)))
+        .collect();
+    if required.is_empty() {
+        return;
+    }
+
+    let Some(installed) = installed_cargo_version() else {
+        warn!("Could not determine the cargo version, skipping the cargo version check");
+        return;
+    };
+    for (key, (feature, version)) in required {
+        if !cargo_version_at_least(&installed, &version) {
+            warn!(
+                "[patch.\"{}\"] uses {}, which need cargo {} (installed: {})",
+                key, feature, version, installed
+            );
+        }
+    }
+}
+
+/// Path dependencies are used directly by cargo, so a `[patch]` entry for them has no effect
+fn has_path_dependency(doc: &toml_edit::DocumentMut, crate_name: &str) -> bool {
+    let Some(deps) = get_dependencies_table(doc) else {
+        return false;
+    };
+    dependency_keys_for_package(doc, crate_name)
+        .iter()
+        .filter_map(|key| deps.get(key))
+        .any(is_path_dependency)
+}
+
+/// Original version requirement of every dependency entry for the given packages, keyed by entry.
+///
+/// Dependencies without version fields (like git-only) get an empty string.
+fn collect_original_versions(
+    doc: &toml_edit::DocumentMut,
+    packages: &[String],
+    sections: &[String],
+) -> HashMap<String, String> {
+    let mut versions: HashMap<String, String> = get_dependencies_table(doc)
+        .map(|deps| {
+            packages
+                .iter()
+                .flat_map(|package| dependency_keys_for_package(doc, package))
+                .filter_map(|key| {
+                    let version = get_dependency_version(deps.get(&key)?).unwrap_or_default();
+                    Some((key, version))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Entries only listed in a `--section` table
+    for package in packages {
+        for key in section_dependency_keys_for_package(doc, sections, package) {
+            let version = sections
+                .iter()
+                .find_map(|section| get_table_at_path(doc, section)?.get(&key))
+                .and_then(get_dependency_version)
+                .unwrap_or_default();
+            versions.entry(key).or_insert(version);
+        }
+    }
+    versions
+}
+
 /// Apply patches from a git repository to the target manifest
 fn apply_git_patches(
     target_doc: &mut toml_edit::DocumentMut,
+    target_manifest_path: &TargetManifestPath,
     git_url: &str,
     reference: Option<GitReference>,
     current_deps: &HashMap<String, String>,
-    pattern: Option<&str>,
+    options: &ApplyOptions,
+    plan: &mut PatchPlan,
 ) -> Result<()> {
-    // For git patches, we can't easily query the remote repository
-    // So we'll patch all target dependencies that match the pattern (or all if no pattern)
-
-    let crates_to_patch: Vec<_> = if let Some(pattern) = pattern {
-        let re = glob_pattern_regex(pattern)?;
+    // With patterns we patch all target dependencies that match them without touching the network.
+    // Without any we clone the repository to discover which of its crates we depend on.
+    let mut crates_to_patch: Vec<_> = if !options.patterns.is_empty() {
+        let set = PatternSet::compile(&options.patterns, options.ignore_case)?;
         current_deps
             .keys()
-            .filter(|name| re.is_match(name))
+            .filter(|name| {
+                match_names(target_doc, name, options.match_on)
+                    .iter()
+                    .any(|candidate| set.is_match(candidate))
+            })
             .cloned()
             .collect()
     } else {
-        // If no pattern, we need user to specify which crates
-        return Err(PatchError::NoMatchingCrates {
-            pattern: "none specified (pattern required for git sources)".to_string(),
-        });
+        info!("Cloning {} to discover its crates", git_url);
+        let repository = clone_repository(git_url, reference.as_ref(), &options.git_clone)?;
+        discover_source_crates(repository.path(), options)?
+            .into_iter()
+            .map(|c| c.name)
+            .filter(|name| current_deps.contains_key(name))
+            .collect()
     };
+    crates_to_patch.sort();
 
     if crates_to_patch.is_empty() {
         return Err(PatchError::NoMatchingCrates {
-            pattern: pattern.unwrap_or("none").to_string(),
+            pattern: if options.patterns.is_empty() {
+                "none".to_string()
+            } else {
+                options.patterns.join(", ")
+            },
         });
     }
 
+    // Crates selected by pattern are trusted to exist unless asked to check
+    if options.verify_source_crates && !options.patterns.is_empty() {
+        info!("Cloning {} to verify its crates", git_url);
+        let repository = clone_repository(git_url, reference.as_ref(), &options.git_clone)?;
+        let available = package_names(&repository, git_url)?;
+        let missing: Vec<_> = crates_to_patch
+            .iter()
+            .filter(|name| !available.contains(*name))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            return Err(PatchError::SourceCratesNotFound {
+                url: git_url.to_string(),
+                crates: missing,
+            });
+        }
+    }
+
     let existing_patched_crates = collect_existing_patched_crates(target_doc);
+    ensure_no_existing_entries(
+        &existing_patched_crates,
+        crates_to_patch.iter().map(String::as_str),
+        options.merge_strategy,
+    )?;
     let mut managed_crates = Vec::new();
+    let mut overwritten = HashSet::new();
     for crate_name in crates_to_patch {
-        if existing_patched_crates.contains(&crate_name) {
-            println!(
-                "  Skipping {} because a patch entry already exists",
+        if is_sysroot_crate(&crate_name) {
+            warn_sysroot_crate(&crate_name);
+            plan.patches.push(skipped_patch(
+                &crate_name,
+                current_deps,
+                SYSROOT_SKIP_REASON,
+            ));
+            continue;
+        }
+        let has_entry = existing_patched_crates.contains(&crate_name);
+        if has_entry && options.merge_strategy == MergeStrategy::Skip {
+            warn!(
+                "Skipping {} because a patch entry already exists",
                 crate_name
             );
+            plan.patches.push(skipped_patch(
+                &crate_name,
+                current_deps,
+                EXISTING_ENTRY_SKIP_REASON,
+            ));
             continue;
         }
+        if has_path_dependency(target_doc, &crate_name) {
+            warn!(
+                "Skipping {} because it is already a path dependency",
+                crate_name
+            );
+            plan.patches.push(skipped_patch(
+                &crate_name,
+                current_deps,
+                PATH_DEPENDENCY_SKIP_REASON,
+            ));
+            continue;
+        }
+        if has_entry {
+            overwrite_existing_entry(target_doc, &crate_name);
+            overwritten.insert(crate_name.clone());
+        }
         managed_crates.push(crate_name);
     }
 
     if managed_crates.is_empty() {
-        println!("No crates to patch after skipping existing patch entries");
+        warn!("No crates to patch after skipping existing patch entries");
         return Ok(());
     }
+    ensure_within_max_crates(managed_crates.len(), options)?;
 
     // Store original versions
-    let mut original_versions = HashMap::new();
-    for crate_name in &managed_crates {
-        if let Some(version) = current_deps.get(crate_name) {
-            original_versions.insert(crate_name.clone(), version.clone());
-        }
-    }
+    let original_versions =
+        collect_original_versions(target_doc, &managed_crates, &options.sections);
 
     // Create patch entries
-    let mut patch_table = Table::new();
+    let mut patch_entries = Vec::new();
     for crate_name in &managed_crates {
         let mut crate_patch = toml_edit::InlineTable::new();
 
         crate_patch.insert("git", git_url.into());
 
-        // Add reference if specified
-        match &reference {
+        // Add reference if specified, preferring a per-crate one, else keep the dependency's
+        let inherited = inherited_git_ref(target_doc, crate_name, git_url);
+        let reference = options
+            .crate_refs
+            .get(crate_name)
+            .or(reference.as_ref())
+            .or(inherited.as_ref());
+        match reference {
             Some(GitReference::Branch(b)) => {
                 crate_patch.insert("branch", b.as_str().into());
             }
@@ -349,101 +1652,512 @@ fn apply_git_patches(
             None => {}
         }
 
-        patch_table.insert(
-            crate_name,
-            toml_edit::Item::Value(toml_edit::Value::InlineTable(crate_patch)),
-        );
+        patch_entries.push((crate_name.clone(), crate_patch));
 
-        let ref_str = match &reference {
-            Some(GitReference::Branch(b)) => format!(" (branch: {})", b),
-            Some(GitReference::Tag(t)) => format!(" (tag: {})", t),
-            Some(GitReference::Rev(r)) => format!(" (rev: {})", r),
-            None => String::new(),
-        };
+        let ref_str = reference
+            .map(|reference| format!(" ({})", reference))
+            .unwrap_or_default();
 
-        println!("  Patching {} -> {}{}", crate_name, git_url, ref_str);
+        info!("  Patching {} -> {}{}", crate_name, git_url, ref_str);
+        plan.patches.push(PlannedPatch {
+            name: crate_name.clone(),
+            old_requirement: old_requirement(crate_name, current_deps),
+            new_version: None,
+            action: patch_action(&overwritten, crate_name),
+            target: format!("{}{}", git_url, ref_str),
+            patch_key: None,
+            requirement_updated: false,
+            skip_reason: None,
+        });
     }
 
+    // Determine patch key (crates-io, registry index or alternative registry)
+    let registry_index = detect_common_registry_index(target_doc, &managed_crates);
+    let registry_key = if registry_index.is_none() && options.registry_from_dep {
+        registry_patch_key(target_doc, target_manifest_path, &managed_crates)?
+    } else {
+        None
+    };
+    let patch_key = registry_index
+        .as_deref()
+        .or(registry_key.as_deref())
+        .unwrap_or("crates-io");
+
     // Store original versions and track managed patch in target metadata
-    store_original_versions(target_doc, &original_versions)?;
-    add_managed_patch(target_doc, "crates-io")?;
+    store_original_versions_with(target_doc, &original_versions, options.expanded_metadata)?;
+    add_managed_patch(target_doc, patch_key)?;
+    add_dependency_sections(target_doc, &options.sections);
+    set_plan_patch_key(plan, patch_key);
+
+    // Add patch section to target document under [patch.<key>], preserving any existing patches
+    write_patch_entries(
+        target_doc,
+        target_manifest_path.as_path(),
+        patch_key,
+        &patch_entries,
+        options.patch_style,
+    )?;
 
-    // Add patch section to target document under [patch.crates-io], preserving any existing patches
-    let patch_section = target_doc
-        .entry("patch")
-        .or_insert(toml_edit::Item::Table(Table::new()))
-        .as_table_mut()
-        .expect("just inserted a table");
+    Ok(())
+}
+
+/// The branch, tag or rev of the target's dependency on `crate_name`, when it already comes
+/// from the same git repository as the patch
+fn inherited_git_ref(
+    doc: &toml_edit::DocumentMut,
+    crate_name: &str,
+    git_url: &str,
+) -> Option<GitReference> {
+    let same_repository = |url: &str| {
+        let normalize = |url: &str| {
+            url.trim_end_matches('/')
+                .trim_end_matches(".git")
+                .to_string()
+        };
+        normalize(url) == normalize(git_url)
+    };
+    let deps = get_dependencies_table(doc)?;
+    dependency_keys_for_package(doc, crate_name)
+        .iter()
+        .filter_map(|key| deps.get(key))
+        .filter(|dep| get_dependency_git_url(dep).is_some_and(|url| same_repository(&url)))
+        .find_map(get_dependency_git_ref)
+}
 
-    // Get or create the patch.crates-io table
-    let source_table = patch_section
-        .entry("crates-io")
-        .or_insert(toml_edit::Item::Table(Table::new()))
-        .as_table_mut()
-        .expect("just inserted a table");
+/// Options controlling how `remove` behaves
+#[derive(Debug, Clone, Default)]
+pub struct RemoveOptions {
+    /// Compute and report the plan without writing the manifest
+    pub dry_run: bool,
+    /// How to report the result
+    pub format: OutputFormat,
+    /// Only remove managed patch keys matching this glob (e.g. `https://github.com/org/*`)
+    pub key_pattern: Option<String>,
+    /// Restore versions from this `crate=version` file instead of the manifest metadata
+    pub versions_from: Option<PathBuf>,
+    /// Only restore and unpatch these crates, leaving the others patched; empty means all
+    pub only: Vec<String>,
+    /// Keep an empty `cargo-patch-source` metadata table instead of deleting it
+    pub keep_empty_metadata: bool,
+    /// Remove entries for every crate in `original-versions` under any patch key, not just
+    /// the keys listed in `managed-patches`, and drop all metadata
+    pub all_keys: bool,
+    /// Directory used instead of the current directory when no manifest path is given
+    pub root: Option<PathBuf>,
+}
+
+/// Run `remove` on the workspace root and on every member whose package name matches
+/// `member_pattern`; with a pattern, the root is only included when it is a matching package.
+///
+/// Manifests without managed patches are skipped; it is an error if none had any.
+pub fn remove_patches_from_workspace(
+    target_manifest_path: Option<PathBuf>,
+    member_pattern: Option<&str>,
+    options: &RemoveOptions,
+) -> Result<Vec<RemovePlan>> {
+    let target_manifest_path =
+        resolve_target_manifest_path(target_manifest_path, options.root.as_deref())?;
+    let member_pattern = member_pattern
+        .map(|pattern| compile_pattern(pattern, false))
+        .transpose()?;
 
-    // Add each crate patch, preserving existing patches
-    for (crate_name, patch_spec) in patch_table.iter() {
-        source_table.insert(crate_name, patch_spec.clone());
+    let members =
+        query_workspace_members(target_manifest_path.as_path(), &MetadataOptions::default())?;
+    let mut manifests: Vec<PathBuf> = members
+        .into_iter()
+        .filter(|member| {
+            member_pattern
+                .as_ref()
+                .is_none_or(|re| re.is_match(&member.name))
+        })
+        .map(|member| member.manifest_path)
+        .collect();
+    let root = target_manifest_path.as_path().to_path_buf();
+    if member_pattern.is_none() && !manifests.contains(&root) {
+        manifests.insert(0, root);
     }
 
-    Ok(())
+    let mut plans = Vec::new();
+    for manifest in manifests {
+        match remove_patches_with_options(Some(manifest.clone()), options) {
+            Ok(plan) => plans.push(plan),
+            Err(PatchError::NoPatchesFound) => {
+                debug!("No managed patches in {}", manifest.display());
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    if plans.is_empty() {
+        return Err(PatchError::NoPatchesFound);
+    }
+    Ok(plans)
+}
+
+/// Read a manifest with its `[patch]` tables swapped in from `.cargo/config.toml` when they
+/// live there, along with the config to swap them back into
+fn read_with_config_patches(
+    manifest_path: &Path,
+) -> Result<(
+    toml_edit::DocumentMut,
+    Option<(PathBuf, toml_edit::DocumentMut)>,
+)> {
+    let mut doc = read_cargo_toml(manifest_path)?;
+    if get_patch_location(&doc) != PatchLocation::CargoConfig {
+        return Ok((doc, None));
+    }
+    let config_path = local_config_path(manifest_path.parent().unwrap_or_else(|| Path::new(".")));
+    let mut config_doc = read_config(&config_path)?;
+    swap_patch_tables(&mut doc, &mut config_doc);
+    Ok((doc, Some((config_path, config_doc))))
+}
+
+/// Write back a manifest read with [`read_with_config_patches`], returning its `[patch]`
+/// tables to the config they came from
+fn write_with_config_patches(
+    manifest_path: &Path,
+    doc: &mut toml_edit::DocumentMut,
+    config: &mut Option<(PathBuf, toml_edit::DocumentMut)>,
+) -> Result<()> {
+    if let Some((config_path, config_doc)) = config {
+        swap_patch_tables(doc, config_doc);
+        write_config(config_path, config_doc)?;
+    }
+    write_cargo_toml(manifest_path, doc)
+}
+
+/// Collapse crates patched under both `crates-io` and a more specific key into the latter.
+///
+/// Returns each deduplicated crate with the key that was kept; the manifest is only written
+/// when something changed and `dry_run` is not set.
+pub fn dedupe_manifest_patches(
+    target_manifest_path: Option<PathBuf>,
+    root: Option<&Path>,
+    dry_run: bool,
+) -> Result<Vec<(String, String)>> {
+    let target_manifest_path = resolve_target_manifest_path(target_manifest_path, root)?;
+    let (mut target_doc, mut config) = read_with_config_patches(target_manifest_path.as_path())?;
+
+    let deduped = dedupe_patches(&mut target_doc);
+    if deduped.is_empty() {
+        info!("No crates are patched under both crates-io and another key");
+        return Ok(deduped);
+    }
+
+    let prefix = if dry_run { "Would remove" } else { "Removed" };
+    for (name, kept) in &deduped {
+        info!(
+            "  {} [patch.crates-io] {}, keeping [patch.{}]",
+            prefix, name, kept
+        );
+    }
+    if !dry_run {
+        write_with_config_patches(target_manifest_path.as_path(), &mut target_doc, &mut config)?;
+    }
+    Ok(deduped)
 }
 
 /// Remove patches from a target Cargo.toml
 pub fn remove_patches(target_manifest_path: Option<PathBuf>) -> Result<()> {
-    // Determine the target manifest path (defaults to ./Cargo.toml)
-    let default_path = match target_manifest_path {
-        Some(path) => path,
-        None => {
-            let current_dir =
-                std::env::current_dir().map_err(|e| PatchError::CurrentDirError { source: e })?;
-            current_dir.join("Cargo.toml")
-        }
-    };
-    let target_manifest_path = TargetManifestPath::new(default_path);
+    remove_patches_with_options(target_manifest_path, &RemoveOptions::default()).map(|_| ())
+}
 
-    if !target_manifest_path.as_path().exists() {
-        return Err(PatchError::TargetManifestNotFound {
-            path: target_manifest_path.as_path().to_path_buf(),
-        });
-    }
+/// Remove patches from a target Cargo.toml, returning the plan that was (or would be) carried out
+pub fn remove_patches_with_options(
+    target_manifest_path: Option<PathBuf>,
+    options: &RemoveOptions,
+) -> Result<RemovePlan> {
+    let target_manifest_path =
+        resolve_target_manifest_path(target_manifest_path, options.root.as_deref())?;
 
     // Read the target Cargo.toml (the manifest we're going to modify)
     let mut target_doc = read_cargo_toml(target_manifest_path.as_path())?;
 
-    // Get original versions from target metadata
-    let original_versions = get_original_versions(&target_doc)?;
+    let config_path = local_config_path(
+        target_manifest_path
+            .as_path()
+            .parent()
+            .unwrap_or_else(|| Path::new(".")),
+    );
+    let uses_config = get_patch_location(&target_doc) == PatchLocation::CargoConfig;
+    let mut config_doc = if uses_config {
+        read_config(&config_path)?
+    } else {
+        toml_edit::DocumentMut::new()
+    };
+    if uses_config {
+        swap_patch_tables(&mut target_doc, &mut config_doc);
+    }
+    check_patch_section(&target_doc, target_manifest_path.as_path())?;
+
+    let key_pattern = options
+        .key_pattern
+        .as_deref()
+        .map(|pattern| compile_pattern(pattern, false))
+        .transpose()?;
+    let key_filter = |key: &str| key_pattern.as_ref().is_none_or(|re| re.is_match(key));
+
+    // Work out what would change before mutating anything
+    let versions = options
+        .versions_from
+        .as_deref()
+        .map(read_version_map)
+        .transpose()?;
+    let mut plan = match &versions {
+        Some(versions) => {
+            let plan = plan_removal_from_versions(&target_doc, versions);
+            if plan.restored_versions.is_empty() && plan.removed_patches.is_empty() {
+                return Err(PatchError::NoPatchesFound);
+            }
+            plan
+        }
+        None if options.all_keys => plan_removal_all_keys(&target_doc)?,
+        None => plan_removal_matching_crates(&target_doc, key_filter, &options.only)?,
+    };
+    plan.dry_run = options.dry_run;
+
+    if options.dry_run {
+        match options.format {
+            OutputFormat::Human => {
+                plan.print_human();
+                info!(
+                    "Dry run: no changes written to {}",
+                    target_manifest_path.as_path().display()
+                );
+            }
+            OutputFormat::Json => println!("{}", plan.to_json()?),
+            OutputFormat::JsonLines => println!("{}", plan.to_json_line()?),
+        }
+        return Ok(plan);
+    }
 
     // Restore original versions in target before removing patches
     // Only restore if there was an actual version field (non-empty)
-    let versions_to_restore: Vec<_> = original_versions
-        .iter()
-        .filter(|(_, version)| !version.is_empty())
-        .collect();
-
-    if !versions_to_restore.is_empty() {
-        println!(
+    if !plan.restored_versions.is_empty() && options.format == OutputFormat::Human {
+        info!(
             "Restoring original versions for {} crates",
-            versions_to_restore.len()
+            plan.restored_versions.len()
         );
-        for (crate_name, version) in versions_to_restore {
-            update_dependency_version(&mut target_doc, crate_name, version)?;
-        }
     }
+    for restored in &plan.restored_versions {
+        update_dependency_version(&mut target_doc, &restored.name, &restored.version)?;
+    }
+    warn_unrestored_versions(&target_doc, &plan.restored_versions);
 
-    // Remove all managed patches from target
-    let removed = remove_managed_patches(&mut target_doc)?;
+    // Remove the selected managed patches from target
+    let removed = match versions {
+        Some(_) => {
+            remove_planned_patches(&mut target_doc, &plan.removed_patches)
+                || !plan.restored_versions.is_empty()
+        }
+        None if options.all_keys => {
+            remove_planned_patches(&mut target_doc, &plan.removed_patches);
+            clear_metadata(&mut target_doc)?;
+            true
+        }
+        None => remove_managed_patches_matching_crates(&mut target_doc, key_filter, &options.only)?,
+    };
+    if removed && options.keep_empty_metadata {
+        ensure_metadata_table(&mut target_doc);
+    }
 
     if removed {
         // Write back the modified target Cargo.toml
+        if uses_config {
+            swap_patch_tables(&mut target_doc, &mut config_doc);
+            write_config(&config_path, &config_doc)?;
+        }
         write_cargo_toml(target_manifest_path.as_path(), &target_doc)?;
-        println!(
-            "Successfully removed patches from {}",
-            target_manifest_path.as_path().display()
-        );
-        Ok(())
+        match options.format {
+            OutputFormat::Human => info!(
+                "Successfully removed patches from {}",
+                target_manifest_path.as_path().display()
+            ),
+            OutputFormat::Json => println!("{}", plan.to_json()?),
+            OutputFormat::JsonLines => println!("{}", plan.to_json_line()?),
+        }
+        Ok(plan)
     } else {
         Err(PatchError::NoPatchesFound)
     }
 }
+
+/// Warn about dependencies whose recorded original version did not land on restore,
+/// e.g. because the dependency changed shape since `apply`
+fn warn_unrestored_versions(doc: &toml_edit::DocumentMut, restored_versions: &[RestoredVersion]) {
+    for restored in restored_versions {
+        let current = find_dependency(doc, &restored.name).and_then(get_dependency_version);
+        if current.as_deref() != Some(restored.version.as_str()) {
+            warn!(
+                "Could not restore {} to {} (found {}); update it by hand",
+                restored.name,
+                restored.version,
+                current.as_deref().unwrap_or("no version")
+            );
+        }
+    }
+}
+
+/// The settings `apply` would run with, one `name: value` line each, for `--print-effective-config`
+pub fn format_effective_config(
+    source: &PatchSource,
+    manifests: &[PathBuf],
+    options: &ApplyOptions,
+) -> Result<String> {
+    fn value_name(value: &impl clap::ValueEnum) -> String {
+        value
+            .to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+    fn list(values: &[String]) -> String {
+        if values.is_empty() {
+            "-".to_string()
+        } else {
+            values.join(", ")
+        }
+    }
+
+    let mut patch_keys = Vec::new();
+    for manifest in manifests {
+        let patch_key = effective_patch_key(source, manifest, options)?;
+        if !patch_keys.contains(&patch_key) {
+            patch_keys.push(patch_key);
+        }
+    }
+    let mut name_map: Vec<_> = options
+        .name_map
+        .iter()
+        .map(|(source, target)| format!("{}={}", source, target))
+        .collect();
+    name_map.sort();
+    let manifests: Vec<_> = manifests
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    let packages: Vec<_> = options
+        .packages
+        .iter()
+        .map(PackageSpec::to_string)
+        .collect();
+
+    let settings = [
+        ("source", source.to_string()),
+        ("manifest", list(&manifests)),
+        ("patterns", list(&options.patterns)),
+        ("ignore-case", options.ignore_case.to_string()),
+        ("match-on", value_name(&options.match_on)),
+        (
+            "select-from",
+            options
+                .select_from
+                .as_ref()
+                .map_or("-".to_string(), |path| path.display().to_string()),
+        ),
+        ("packages", list(&packages)),
+        ("name-map", list(&name_map)),
+        (
+            "license",
+            options.license.clone().unwrap_or("-".to_string()),
+        ),
+        ("patch key", list(&patch_keys)),
+        ("merge-strategy", value_name(&options.merge_strategy)),
+        ("patch-style", value_name(&options.patch_style)),
+        ("relative-to", value_name(&options.relative_to)),
+        ("write-to", value_name(&options.write_to)),
+        (
+            "workspace-dependencies-only",
+            options.workspace_dependencies_only.to_string(),
+        ),
+        ("sections", list(&options.sections)),
+        ("only-newer", options.only_newer.to_string()),
+        ("from-source-lock", options.from_source_lock.to_string()),
+        ("dry-run", options.dry_run.to_string()),
+        ("strict", options.strict.to_string()),
+        (
+            "check-cargo-version",
+            options.check_cargo_version.to_string(),
+        ),
+        ("recursive", options.recursive.to_string()),
+        ("offline", options.metadata.offline.to_string()),
+        ("frozen", options.metadata.frozen.to_string()),
+        ("cargo-config", list(&options.metadata.config)),
+    ];
+    Ok(settings
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// The `[patch.<key>]` an apply of `source` would write to in `manifest`, decided the same way
+/// as the apply itself but from the target dependencies the patterns select
+fn effective_patch_key(
+    source: &PatchSource,
+    manifest: &Path,
+    options: &ApplyOptions,
+) -> Result<String> {
+    let target_manifest_path = TargetManifestPath::new(manifest.to_path_buf());
+    let doc = read_cargo_toml(manifest)?;
+    let set = PatternSet::compile(&options.patterns, options.ignore_case)?;
+    let crate_names: Vec<String> = get_dependencies_table(&doc)
+        .map(dependency_requirements)
+        .unwrap_or_default()
+        .into_keys()
+        .filter(|name| {
+            options.patterns.is_empty()
+                || match_names(&doc, name, options.match_on)
+                    .iter()
+                    .any(|candidate| set.is_match(candidate))
+        })
+        .collect();
+
+    // Only path-like sources take over the git URL their dependencies share
+    let git_url = match source {
+        PatchSource::Git { url, reference }
+            if local_checkout(url, reference, options).is_none() =>
+        {
+            None
+        }
+        _ => detect_common_git_url(&doc, &crate_names),
+    };
+    if let Some(url) = git_url {
+        return Ok(url);
+    }
+    if let Some(index) = detect_common_registry_index(&doc, &crate_names) {
+        return Ok(index);
+    }
+    if options.registry_from_dep {
+        if let Some(key) = registry_patch_key(&doc, &target_manifest_path, &crate_names)? {
+            return Ok(key);
+        }
+    }
+    Ok("crates-io".to_string())
+}
+
+/// Resolve the target manifest path and make sure it exists.
+///
+/// Defaults to `Cargo.toml` in `root`, or in the current directory when no root is given.
+pub fn resolve_target_manifest_path(
+    target_manifest_path: Option<PathBuf>,
+    root: Option<&Path>,
+) -> Result<TargetManifestPath> {
+    let default_path = match (target_manifest_path, root) {
+        (Some(path), _) => path,
+        (None, Some(root)) => root.join("Cargo.toml"),
+        (None, None) => {
+            let current_dir =
+                std::env::current_dir().map_err(|e| PatchError::CurrentDirError { source: e })?;
+            current_dir.join("Cargo.toml")
+        }
+    };
+    let target_manifest_path = TargetManifestPath::new(default_path);
+
+    if !target_manifest_path.as_path().exists() {
+        return Err(PatchError::TargetManifestNotFound {
+            path: target_manifest_path.as_path().to_path_buf(),
+        });
+    }
+
+    Ok(target_manifest_path)
+}
@@ -0,0 +1,390 @@
+use crate::error::{PatchError, Result};
+use serde::Serialize;
+use tracing::info;
+
+/// Version of the `apply` and `remove` JSON reports, bumped only on breaking changes
+/// (a field removed, renamed or changing meaning)
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A JSON report with the schema version as its first field
+#[derive(Serialize)]
+struct Versioned<'a, T> {
+    schema_version: u32,
+    #[serde(flatten)]
+    report: &'a T,
+}
+
+fn to_versioned_json<T: Serialize>(report: &T) -> Result<String> {
+    let versioned = Versioned {
+        schema_version: SCHEMA_VERSION,
+        report,
+    };
+    serde_json::to_string_pretty(&versioned).map_err(|e| PatchError::JsonError { source: e })
+}
+
+fn to_versioned_json_line<T: Serialize>(report: &T) -> Result<String> {
+    let versioned = Versioned {
+        schema_version: SCHEMA_VERSION,
+        report,
+    };
+    serde_json::to_string(&versioned).map_err(|e| PatchError::JsonError { source: e })
+}
+
+/// Output format for command results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable progress lines
+    #[default]
+    Human,
+    /// A single JSON document printed at the end
+    Json,
+    /// One compact JSON document per line, e.g. one per manifest
+    JsonLines,
+}
+
+/// A dependency whose original version is restored on remove
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RestoredVersion {
+    pub name: String,
+    pub version: String,
+}
+
+/// A patch entry that is deleted on remove
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RemovedPatch {
+    pub patch_key: String,
+    pub name: String,
+}
+
+/// Everything `remove` will do (or did) to a manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RemovePlan {
+    pub dry_run: bool,
+    pub restored_versions: Vec<RestoredVersion>,
+    pub removed_patches: Vec<RemovedPatch>,
+}
+
+impl RemovePlan {
+    /// Serialize the plan as pretty-printed JSON, including the schema version
+    pub fn to_json(&self) -> Result<String> {
+        to_versioned_json(self)
+    }
+
+    /// Serialize the plan as a single line of JSON, including the schema version
+    pub fn to_json_line(&self) -> Result<String> {
+        to_versioned_json_line(self)
+    }
+
+    /// Print the plan in human readable form
+    pub fn print_human(&self) {
+        let prefix = if self.dry_run {
+            "Would restore"
+        } else {
+            "Restored"
+        };
+        for restored in &self.restored_versions {
+            info!("  {} {} to {}", prefix, restored.name, restored.version);
+        }
+
+        let prefix = if self.dry_run {
+            "Would remove"
+        } else {
+            "Removed"
+        };
+        for removed in &self.removed_patches {
+            info!(
+                "  {} [patch.{}] {}",
+                prefix, removed.patch_key, removed.name
+            );
+        }
+    }
+}
+
+/// What `apply` does with a candidate crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PlanAction {
+    Patch,
+    Skip,
+    Overwrite,
+}
+
+impl PlanAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Patch => "patch",
+            Self::Skip => "skip",
+            Self::Overwrite => "overwrite",
+        }
+    }
+}
+
+/// A candidate crate and what `apply` does with it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedPatch {
+    pub name: String,
+    /// The dependency requirement before patching, if it had one
+    pub old_requirement: Option<String>,
+    /// The source version the requirement is updated to, for local sources
+    pub new_version: Option<String>,
+    pub action: PlanAction,
+    /// Path or git URL the patch points at; empty for skipped crates
+    pub target: String,
+    /// The `[patch.<key>]` the entry is written under; `None` for skipped crates
+    pub patch_key: Option<String>,
+    /// Whether the dependency requirement was set to the source version
+    pub requirement_updated: bool,
+    /// Why the crate was left alone, for skipped crates
+    pub skip_reason: Option<String>,
+}
+
+impl PlannedPatch {
+    /// One sentence saying what happened to the crate and why
+    pub fn explanation(&self, dependencies_table: &str) -> String {
+        let found = match &self.old_requirement {
+            Some(requirement) => format!("found in [{}] at {}", dependencies_table, requirement),
+            None => format!(
+                "found in [{}] without a version requirement",
+                dependencies_table
+            ),
+        };
+        let provides = self
+            .new_version
+            .as_ref()
+            .map(|version| format!(", source provides {}", version))
+            .unwrap_or_default();
+
+        let action = match self.action {
+            PlanAction::Skip => format!(
+                "skipped because {}",
+                self.skip_reason.as_deref().unwrap_or("it was not selected")
+            ),
+            PlanAction::Patch | PlanAction::Overwrite => {
+                let verb = if self.action == PlanAction::Overwrite {
+                    "replaced the entry in"
+                } else {
+                    "patched under"
+                };
+                let kind = if self.new_version.is_some() {
+                    "path"
+                } else {
+                    "git"
+                };
+                let mut action = format!(
+                    "{} [patch.{}] -> {} {}",
+                    verb,
+                    self.patch_key.as_deref().unwrap_or("crates-io"),
+                    kind,
+                    self.target
+                );
+                if let (true, Some(version)) = (self.requirement_updated, &self.new_version) {
+                    action.push_str(&format!("; updated requirement to {}", version));
+                }
+                action
+            }
+        };
+
+        format!("{}: {}{}, {}.", self.name, found, provides, action)
+    }
+}
+
+/// Everything `apply` will do (or did) to a manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PatchPlan {
+    pub dry_run: bool,
+    pub patches: Vec<PlannedPatch>,
+    /// Number of crates patched or overwritten
+    pub patched: usize,
+    pub skipped: usize,
+    /// Time taken by `apply`, including metadata queries and clones
+    pub elapsed_ms: u64,
+    /// The dependency table the crates were found in, e.g. `workspace.dependencies`
+    pub dependencies_table: String,
+}
+
+impl PatchPlan {
+    /// Serialize the plan as pretty-printed JSON, including the schema version
+    pub fn to_json(&self) -> Result<String> {
+        to_versioned_json(self)
+    }
+
+    /// Serialize the plan as a single line of JSON, including the schema version
+    pub fn to_json_line(&self) -> Result<String> {
+        to_versioned_json_line(self)
+    }
+
+    /// The plan as an aligned table, one row per crate sorted by name
+    pub fn format_table(&self) -> String {
+        let mut patches: Vec<_> = self.patches.iter().collect();
+        patches.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let rows: Vec<[&str; 5]> = patches
+            .iter()
+            .map(|p| {
+                [
+                    p.name.as_str(),
+                    p.old_requirement.as_deref().unwrap_or("-"),
+                    p.new_version.as_deref().unwrap_or("-"),
+                    p.action.as_str(),
+                    if p.target.is_empty() { "-" } else { &p.target },
+                ]
+            })
+            .collect();
+        let header = ["crate", "old req", "new version", "action", "patch target"];
+        let widths: Vec<usize> = (0..header.len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].len())
+                    .chain([header[column].len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        std::iter::once(header)
+            .chain(rows)
+            .map(|row| {
+                let cells: Vec<_> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:<width$}", cell))
+                    .collect();
+                format!("  {}", cells.join("  ").trim_end())
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Print the plan as a table
+    pub fn print_human(&self) {
+        info!("{}", self.format_table());
+    }
+
+    /// One explanation line per crate, sorted by name
+    pub fn format_explanation(&self) -> String {
+        let mut patches: Vec<_> = self.patches.iter().collect();
+        patches.sort_by(|a, b| a.name.cmp(&b.name));
+        let dependencies_table = if self.dependencies_table.is_empty() {
+            "dependencies"
+        } else {
+            &self.dependencies_table
+        };
+
+        patches
+            .iter()
+            .map(|patch| format!("  {}", patch.explanation(dependencies_table)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Print what happened to each crate and why
+    pub fn print_explanation(&self) {
+        info!("{}", self.format_explanation());
+    }
+}
+
+/// Outcome of a single `doctor` check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub passed: bool,
+    pub problems: Vec<String>,
+}
+
+impl CheckResult {
+    pub fn pass(name: &str) -> Self {
+        Self::from_problems(name, Vec::new())
+    }
+
+    pub fn fail(name: &str, problems: Vec<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            passed: false,
+            problems,
+        }
+    }
+
+    /// A check passes when it found no problems
+    pub fn from_problems(name: &str, problems: Vec<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            passed: problems.is_empty(),
+            problems,
+        }
+    }
+}
+
+/// Every check `doctor` ran against a manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DoctorReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    /// Number of checks that did not pass
+    pub fn failed_count(&self) -> usize {
+        self.checks.iter().filter(|check| !check.passed).count()
+    }
+
+    /// Serialize the report as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| PatchError::JsonError { source: e })
+    }
+
+    /// Serialize the report as a single line of JSON
+    pub fn to_json_line(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(|e| PatchError::JsonError { source: e })
+    }
+
+    /// Print the report in human readable form
+    pub fn print_human(&self) {
+        for check in &self.checks {
+            let status = if check.passed { "ok" } else { "FAIL" };
+            info!("  {:<4} {}", status, check.name);
+            for problem in &check.problems {
+                info!("         {}", problem);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_plan_table_is_sorted_and_aligned() {
+        let plan = PatchPlan {
+            patches: vec![
+                PlannedPatch {
+                    name: "rattler-two".to_string(),
+                    old_requirement: Some("2.0.0".to_string()),
+                    new_version: None,
+                    action: PlanAction::Skip,
+                    target: String::new(),
+                    patch_key: None,
+                    requirement_updated: false,
+                    skip_reason: Some("a patch entry already exists".to_string()),
+                },
+                PlannedPatch {
+                    name: "a".to_string(),
+                    old_requirement: None,
+                    new_version: Some("1.0.0".to_string()),
+                    action: PlanAction::Patch,
+                    target: "/ws/crates/a".to_string(),
+                    patch_key: Some("crates-io".to_string()),
+                    requirement_updated: false,
+                    skip_reason: None,
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            plan.format_table(),
+            "  crate        old req  new version  action  patch target\n  \
+             a            -        1.0.0        patch   /ws/crates/a\n  \
+             rattler-two  2.0.0    -            skip    -"
+        );
+    }
+}
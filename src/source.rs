@@ -1,7 +1,8 @@
+use std::fmt;
 use std::path::{Path, PathBuf};
 
 /// Path to a source workspace (where we read crates from)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SourceWorkspacePath(PathBuf);
 
 impl SourceWorkspacePath {
@@ -12,6 +13,23 @@ impl SourceWorkspacePath {
     pub fn as_path(&self) -> &Path {
         &self.0
     }
+
+    /// Resolve a relative path against `dir`, like cargo does for path dependencies
+    pub fn relative_to(self, dir: &Path) -> Self {
+        if self.0.is_relative() {
+            Self(dir.join(self.0))
+        } else {
+            self
+        }
+    }
+
+    /// Check if this points into cargo's unpacked registry cache (`.../registry/src/...`)
+    pub fn is_registry_cache(&self) -> bool {
+        let components: Vec<_> = self.0.components().map(|c| c.as_os_str()).collect();
+        components
+            .windows(2)
+            .any(|pair| pair[0] == "registry" && pair[1] == "src")
+    }
 }
 
 impl AsRef<Path> for SourceWorkspacePath {
@@ -41,7 +59,7 @@ impl AsRef<Path> for TargetManifestPath {
 }
 
 /// Represents the source of patches
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PatchSource {
     /// Local filesystem path to a workspace (where we read crates from)
     LocalPath(SourceWorkspacePath),
@@ -50,16 +68,37 @@ pub enum PatchSource {
         url: String,
         reference: Option<GitReference>,
     },
+    /// Archive (`.tar.gz` or `.zip`) containing a workspace, extracted before patching
+    Archive(PathBuf),
 }
 
 /// Git reference types
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum GitReference {
     Branch(String),
     Tag(String),
     Rev(String),
 }
 
+impl GitReference {
+    /// The branch, tag or revision itself
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Branch(name) | Self::Tag(name) | Self::Rev(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for GitReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Branch(branch) => write!(f, "branch: {}", branch),
+            Self::Tag(tag) => write!(f, "tag: {}", tag),
+            Self::Rev(rev) => write!(f, "rev: {}", rev),
+        }
+    }
+}
+
 impl PatchSource {
     /// Create a local path source
     pub fn local_path(path: PathBuf) -> Self {
@@ -71,6 +110,11 @@ impl PatchSource {
         Self::Git { url, reference }
     }
 
+    /// Create an archive source
+    pub fn archive(path: PathBuf) -> Self {
+        Self::Archive(path)
+    }
+
     /// Check if this is a local path source
     pub fn is_local(&self) -> bool {
         matches!(self, Self::LocalPath(_))
@@ -80,4 +124,132 @@ impl PatchSource {
     pub fn is_git(&self) -> bool {
         matches!(self, Self::Git { .. })
     }
+
+    /// Check if this is an archive source
+    pub fn is_archive(&self) -> bool {
+        matches!(self, Self::Archive(_))
+    }
+}
+
+/// The local directory behind a `file://` URL, with `%XX` escapes decoded
+pub fn file_url_to_path(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix("file://")?;
+    // `file:///C:/src` on Windows
+    let path = match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &path[1..],
+        _ => path,
+    };
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| path.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+impl fmt::Display for PatchSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LocalPath(path) => write!(f, "path: {}", path.as_path().display()),
+            Self::Git { url, reference } => match reference {
+                Some(reference) => write!(f, "git: {}@{}", url, reference.name()),
+                None => write!(f, "git: {}", url),
+            },
+            Self::Archive(path) => write!(f, "archive: {}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_source_display() {
+        assert_eq!(
+            PatchSource::local_path(PathBuf::from("../rattler")).to_string(),
+            "path: ../rattler"
+        );
+        assert_eq!(
+            PatchSource::git(
+                "https://github.com/prefix-dev/rattler".to_string(),
+                Some(GitReference::Branch("main".to_string()))
+            )
+            .to_string(),
+            "git: https://github.com/prefix-dev/rattler@main"
+        );
+        assert_eq!(
+            PatchSource::git("https://github.com/prefix-dev/rattler".to_string(), None).to_string(),
+            "git: https://github.com/prefix-dev/rattler"
+        );
+        assert_eq!(
+            PatchSource::archive(PathBuf::from("rattler.tar.gz")).to_string(),
+            "archive: rattler.tar.gz"
+        );
+    }
+
+    #[test]
+    fn git_reference_display_names_the_kind() {
+        assert_eq!(
+            GitReference::Branch("main".to_string()).to_string(),
+            "branch: main"
+        );
+        assert_eq!(
+            GitReference::Tag("v1.0".to_string()).to_string(),
+            "tag: v1.0"
+        );
+        assert_eq!(
+            GitReference::Rev("abc123".to_string()).to_string(),
+            "rev: abc123"
+        );
+    }
+
+    #[test]
+    fn file_url_to_path_strips_the_scheme() {
+        assert_eq!(
+            file_url_to_path("file:///home/me/rattler"),
+            Some(PathBuf::from("/home/me/rattler"))
+        );
+        assert_eq!(
+            file_url_to_path("file:///home/me/my%20rattler"),
+            Some(PathBuf::from("/home/me/my rattler"))
+        );
+        assert_eq!(
+            file_url_to_path("file:///C:/src/rattler"),
+            Some(PathBuf::from("C:/src/rattler"))
+        );
+        assert_eq!(
+            file_url_to_path("https://github.com/prefix-dev/rattler"),
+            None
+        );
+    }
+
+    #[test]
+    fn patch_sources_compare_by_value() {
+        let main = Some(GitReference::Branch("main".to_string()));
+        let url = "https://github.com/prefix-dev/rattler".to_string();
+        assert_eq!(
+            PatchSource::git(url.clone(), main.clone()),
+            PatchSource::git(url.clone(), main)
+        );
+        assert_ne!(
+            PatchSource::git(url.clone(), Some(GitReference::Branch("v1".to_string()))),
+            PatchSource::git(url, Some(GitReference::Tag("v1".to_string())))
+        );
+    }
 }
@@ -0,0 +1,191 @@
+use crate::cargo_config::{local_config_path, read_config};
+use crate::error::{PatchError, Result};
+use crate::toml_ops::{
+    get_managed_patches, get_original_versions, get_patch_location, read_cargo_toml,
+    resolve_package_name, swap_patch_tables, PatchLocation,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item};
+
+/// A crate we patched, as recorded in the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PatchedCrate {
+    pub name: String,
+    /// Version requirement recorded before patching
+    pub original_version: String,
+    /// The patch entry as written in the manifest, e.g. `{ path = "../crates/foo" }`
+    pub patch: String,
+}
+
+/// Every managed patch in a manifest, grouped by `[patch.<key>]`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ManagedState {
+    pub patches: BTreeMap<String, Vec<PatchedCrate>>,
+}
+
+impl ManagedState {
+    /// Whether the manifest has no managed patches
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    /// Serialize the state as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| PatchError::JsonError { source: e })
+    }
+
+    /// Serialize the state as a single line of JSON
+    pub fn to_json_line(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(|e| PatchError::JsonError { source: e })
+    }
+
+    /// One line per patched crate, sorted by name
+    pub fn format_list(&self) -> String {
+        let mut lines: Vec<(&str, String)> = self
+            .patches
+            .iter()
+            .flat_map(|(patch_key, crates)| {
+                crates.iter().map(move |patched| {
+                    (
+                        patched.name.as_str(),
+                        format!("  {} [patch.{}] {}", patched.name, patch_key, patched.patch),
+                    )
+                })
+            })
+            .collect();
+        lines.sort();
+        lines
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Each patch key followed by its crates and what they are patched with
+    pub fn format_grouped(&self) -> String {
+        self.patches
+            .iter()
+            .map(|(patch_key, crates)| {
+                let entries: Vec<_> = crates
+                    .iter()
+                    .map(|patched| format!("  {} = {}", patched.name, patched.patch))
+                    .collect();
+                format!("[patch.{}]\n{}", patch_key, entries.join("\n"))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Read the managed patch state of a manifest without modifying it
+pub fn managed_state(manifest_path: &Path) -> Result<ManagedState> {
+    let mut doc = read_cargo_toml(manifest_path)?;
+    if get_patch_location(&doc) == PatchLocation::CargoConfig {
+        let manifest_dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+        let mut config_doc = read_config(&local_config_path(manifest_dir))?;
+        swap_patch_tables(&mut doc, &mut config_doc);
+    }
+    managed_state_from_doc(&doc)
+}
+
+/// Collect the managed patch state from an already parsed manifest
+pub fn managed_state_from_doc(doc: &DocumentMut) -> Result<ManagedState> {
+    // Original versions are keyed by dependency entry, patches by package name
+    let mut original_versions: BTreeMap<String, String> = BTreeMap::new();
+    for (dep_key, version) in get_original_versions(doc)? {
+        original_versions
+            .entry(resolve_package_name(doc, &dep_key))
+            .or_insert(version);
+    }
+
+    let mut patches = BTreeMap::new();
+    for patch_key in get_managed_patches(doc) {
+        let Some(table) = doc
+            .get("patch")
+            .and_then(|p| p.get(&patch_key))
+            .and_then(Item::as_table_like)
+        else {
+            continue;
+        };
+
+        let mut crates: Vec<_> = table
+            .iter()
+            .filter_map(|(name, spec)| {
+                let original_version = original_versions.get(name)?;
+                Some(PatchedCrate {
+                    name: name.to_string(),
+                    original_version: original_version.clone(),
+                    patch: patch_value(spec),
+                })
+            })
+            .collect();
+        crates.sort_by(|a, b| a.name.cmp(&b.name));
+
+        if !crates.is_empty() {
+            patches.insert(patch_key, crates);
+        }
+    }
+
+    Ok(ManagedState { patches })
+}
+
+/// A patch entry as an inline value, whichever style it is written in
+fn patch_value(spec: &Item) -> String {
+    match spec.clone().into_value() {
+        Ok(value) => value.to_string().trim().to_string(),
+        Err(item) => item.to_string().trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn managed_state_groups_recorded_crates_by_patch_key() {
+        let doc: DocumentMut = r#"
+[package]
+name = "target"
+version = "0.1.0"
+
+[package.metadata.cargo-patch-source]
+original-versions = { rattler-one = "1.0.0", renamed = "2.0.0" }
+managed-patches = ["crates-io"]
+
+[dependencies]
+rattler-one = "1.0.0"
+renamed = { package = "rattler-two", version = "2.0.0" }
+
+[patch.crates-io]
+rattler-one = { path = "../ws/crates/rattler-one" }
+rattler-two = { path = "../ws/crates/rattler-two" }
+user-patch = { path = "../elsewhere" }
+"#
+        .parse()
+        .unwrap();
+
+        let state = managed_state_from_doc(&doc).unwrap();
+        let crates = &state.patches["crates-io"];
+        assert_eq!(state.patches.len(), 1);
+        assert_eq!(
+            crates,
+            &[
+                PatchedCrate {
+                    name: "rattler-one".to_string(),
+                    original_version: "1.0.0".to_string(),
+                    patch: r#"{ path = "../ws/crates/rattler-one" }"#.to_string(),
+                },
+                PatchedCrate {
+                    name: "rattler-two".to_string(),
+                    original_version: "2.0.0".to_string(),
+                    patch: r#"{ path = "../ws/crates/rattler-two" }"#.to_string(),
+                },
+            ]
+        );
+
+        let unpatched: DocumentMut = "[package]\nname = \"target\"\n".parse().unwrap();
+        assert!(managed_state_from_doc(&unpatched).unwrap().is_empty());
+    }
+}
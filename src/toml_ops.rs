@@ -1,5 +1,7 @@
 use crate::error::{PatchError, Result};
-use std::collections::HashMap;
+use crate::report::{RemovePlan, RemovedPatch, RestoredVersion};
+use crate::source::GitReference;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 use toml_edit::{DocumentMut, Item, Table};
@@ -7,6 +9,11 @@ use toml_edit::{DocumentMut, Item, Table};
 const METADATA_KEY: &str = "cargo-patch-source";
 const ORIGINAL_VERSIONS_KEY: &str = "original-versions";
 const MANAGED_PATCHES_KEY: &str = "managed-patches";
+const PATCH_LOCATION_KEY: &str = "patch-location";
+const CHECKSUMS_KEY: &str = "checksums";
+const SOURCE_REVISION_KEY: &str = "source-revision";
+const GIT_REF_KEY: &str = "git-ref";
+const DEPENDENCY_SECTIONS_KEY: &str = "dependency-sections";
 
 /// Read and parse a Cargo.toml file
 pub fn read_cargo_toml(path: &Path) -> Result<DocumentMut> {
@@ -23,14 +30,45 @@ pub fn read_cargo_toml(path: &Path) -> Result<DocumentMut> {
         })
 }
 
-/// Write a Cargo.toml document to file
+/// Write a Cargo.toml document to file.
+///
+/// Keeps the dominant line ending and the number of trailing newlines of the file being
+/// replaced, so CRLF manifests stay CRLF and a no-op write leaves the file unchanged.
 pub fn write_cargo_toml(path: &Path, doc: &DocumentMut) -> Result<()> {
-    fs::write(path, doc.to_string()).map_err(|e| PatchError::CargoTomlWriteError {
+    fs::write(path, render_cargo_toml(path, doc)).map_err(|e| PatchError::CargoTomlWriteError {
         path: path.to_path_buf(),
         source: e,
     })
 }
 
+/// The content `write_cargo_toml` would write to `path`
+pub fn render_cargo_toml(path: &Path, doc: &DocumentMut) -> String {
+    let mut content = doc.to_string();
+    if let Ok(existing) = fs::read_to_string(path) {
+        let line_ending = if uses_crlf(&existing) { "\r\n" } else { "\n" };
+        if line_ending == "\r\n" {
+            content = content.replace("\r\n", "\n").replace('\n', "\r\n");
+        }
+        let trailing = trailing_newlines(&existing);
+        content.truncate(content.trim_end_matches(['\r', '\n']).len());
+        content.push_str(&line_ending.repeat(trailing));
+    }
+    content
+}
+
+/// Whether most lines in `content` end with `\r\n`
+fn uses_crlf(content: &str) -> bool {
+    let crlf = content.matches("\r\n").count();
+    let lf = content.matches('\n').count() - crlf;
+    crlf > lf
+}
+
+/// Number of line endings at the end of `content`
+fn trailing_newlines(content: &str) -> usize {
+    let trimmed = content.trim_end_matches(['\r', '\n']);
+    content[trimmed.len()..].matches('\n').count()
+}
+
 /// Check if the document is a workspace (has `[workspace]` or `[workspace.dependencies]`)
 pub fn is_workspace(doc: &DocumentMut) -> bool {
     doc.get("workspace").is_some()
@@ -74,6 +112,51 @@ pub fn get_dependencies_table(doc: &DocumentMut) -> Option<&Table> {
     None
 }
 
+/// The `[workspace.dependencies]` table, without falling back to `[dependencies]`
+pub fn get_workspace_dependencies_table(doc: &DocumentMut) -> Option<&Table> {
+    doc.get("workspace")?.get("dependencies")?.as_table()
+}
+
+/// Name of the package a dependency entry refers to, honoring `package = "..."` renames
+pub fn dependency_package_name(dep_key: &str, dep_value: &Item) -> String {
+    get_dependency_str(dep_value, "package").unwrap_or_else(|| dep_key.to_string())
+}
+
+/// Keys of all dependency entries that refer to `package`, including renamed ones
+pub fn dependency_keys_for_package(doc: &DocumentMut, package: &str) -> Vec<String> {
+    get_dependencies_table(doc)
+        .map(|deps| {
+            deps.iter()
+                .filter(|(key, value)| dependency_package_name(key, value) == package)
+                .map(|(key, _)| key.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Keys of the entries in the given `--section` tables that refer to `package`
+pub fn section_dependency_keys_for_package(
+    doc: &DocumentMut,
+    sections: &[String],
+    package: &str,
+) -> Vec<String> {
+    sections
+        .iter()
+        .filter_map(|section| get_table_at_path(doc, section))
+        .flat_map(|deps| deps.iter())
+        .filter(|(key, value)| dependency_package_name(key, value) == package)
+        .map(|(key, _)| key.to_string())
+        .collect()
+}
+
+/// Package name of the dependency entry `dep_key`, or the key itself if there is no such entry
+pub fn resolve_package_name(doc: &DocumentMut, dep_key: &str) -> String {
+    get_dependencies_table(doc)
+        .and_then(|deps| deps.get(dep_key))
+        .map(|value| dependency_package_name(dep_key, value))
+        .unwrap_or_else(|| dep_key.to_string())
+}
+
 /// Extract git URL from a dependency specification
 pub fn get_dependency_git_url(dep_value: &Item) -> Option<String> {
     match dep_value {
@@ -99,26 +182,102 @@ pub fn get_dependency_git_url(dep_value: &Item) -> Option<String> {
     }
 }
 
+/// Extract the `branch`, `tag` or `rev` of a git dependency specification
+pub fn get_dependency_git_ref(dep_value: &Item) -> Option<GitReference> {
+    get_dependency_git_url(dep_value)?;
+    if let Some(branch) = get_dependency_str(dep_value, "branch") {
+        Some(GitReference::Branch(branch))
+    } else if let Some(tag) = get_dependency_str(dep_value, "tag") {
+        Some(GitReference::Tag(tag))
+    } else {
+        get_dependency_str(dep_value, "rev").map(GitReference::Rev)
+    }
+}
+
+/// Check whether a dependency specification points at a local `path`
+pub fn is_path_dependency(dep_value: &Item) -> bool {
+    match dep_value {
+        Item::Value(val) => val
+            .as_inline_table()
+            .is_some_and(|inline_tbl| inline_tbl.contains_key("path")),
+        Item::Table(table) => table.contains_key("path"),
+        _ => false,
+    }
+}
+
+/// Extract the alternative registry name from a dependency specification
+pub fn get_dependency_registry(dep_value: &Item) -> Option<String> {
+    get_dependency_str(dep_value, "registry")
+}
+
+/// Extract the `registry-index` URL from a dependency specification
+pub fn get_dependency_registry_index(dep_value: &Item) -> Option<String> {
+    get_dependency_str(dep_value, "registry-index")
+}
+
+/// Read a string field from an inline or regular dependency table
+fn get_dependency_str(dep_value: &Item, key: &str) -> Option<String> {
+    match dep_value {
+        Item::Value(val) => val
+            .as_inline_table()
+            .and_then(|inline_tbl| inline_tbl.get(key))
+            .and_then(|r| r.as_str())
+            .map(|s| s.to_string()),
+        Item::Table(table) => table
+            .get(key)
+            .and_then(|r| r.as_str())
+            .map(|s| s.to_string()),
+        _ => None,
+    }
+}
+
 /// Detect if dependencies use a common git URL (returns most common git URL if any)
 pub fn detect_common_git_url(doc: &DocumentMut, crate_names: &[String]) -> Option<String> {
+    detect_common_value(doc, crate_names, get_dependency_git_url)
+}
+
+/// Detect if dependencies use a common alternative registry (returns the registry name if any)
+pub fn detect_common_registry(doc: &DocumentMut, crate_names: &[String]) -> Option<String> {
+    detect_common_value(doc, crate_names, get_dependency_registry)
+}
+
+/// Detect if dependencies use a common `registry-index` URL (returns the index URL if any)
+pub fn detect_common_registry_index(doc: &DocumentMut, crate_names: &[String]) -> Option<String> {
+    detect_common_value(doc, crate_names, get_dependency_registry_index)
+}
+
+/// Detect if git dependencies share a branch, tag or rev (returns the most common one if any)
+pub fn detect_common_git_ref(doc: &DocumentMut, crate_names: &[String]) -> Option<GitReference> {
+    detect_common_value(doc, crate_names, get_dependency_git_ref)
+}
+
+/// Find the value shared by the majority of the given dependencies
+fn detect_common_value<T: Eq + std::hash::Hash>(
+    doc: &DocumentMut,
+    crate_names: &[String],
+    extract: impl Fn(&Item) -> Option<T>,
+) -> Option<T> {
     let deps_table = get_dependencies_table(doc)?;
 
-    let mut git_url_counts: HashMap<String, usize> = HashMap::new();
+    let mut value_counts: HashMap<T, usize> = HashMap::new();
 
-    for crate_name in crate_names {
-        if let Some(dep_value) = deps_table.get(crate_name) {
-            if let Some(git_url) = get_dependency_git_url(dep_value) {
-                *git_url_counts.entry(git_url).or_insert(0) += 1;
-            }
+    // Renamed entries of the same package each count
+    let entries: Vec<_> = deps_table
+        .iter()
+        .filter(|(key, value)| crate_names.contains(&dependency_package_name(key, value)))
+        .collect();
+    for (_, dep_value) in &entries {
+        if let Some(value) = extract(dep_value) {
+            *value_counts.entry(value).or_insert(0) += 1;
         }
     }
 
-    // Return the most common git URL if it accounts for majority of dependencies
-    git_url_counts
+    // Return the most common value if it accounts for majority of dependencies
+    value_counts
         .into_iter()
         .max_by_key(|(_, count)| *count)
-        .filter(|(_, count)| *count > crate_names.len() / 2) // Majority rule
-        .map(|(url, _)| url)
+        .filter(|(_, count)| *count > entries.len() / 2) // Majority rule
+        .map(|(value, _)| value)
 }
 
 /// Get current version of a dependency
@@ -149,41 +308,69 @@ pub fn get_dependency_version(dep_value: &Item) -> Option<String> {
     }
 }
 
-/// Update dependency version in the dependencies table
+/// Update dependency version in the dependencies table and in any `--section` tables
 pub fn update_dependency_version(
     doc: &mut DocumentMut,
     crate_name: &str,
     new_version: &str,
 ) -> Result<()> {
-    let deps_table = get_dependencies_table_mut(doc);
-
-    if let Some(deps_table) = deps_table {
-        if let Some(dep_value) = deps_table.get_mut(crate_name) {
-            match dep_value {
-                Item::Value(val) => {
-                    // Simple string version - replace the entire item
-                    if val.is_str() {
-                        *dep_value = toml_edit::value(new_version);
-                    }
-                    // Inline table - update the version field
-                    else if let Some(inline_tbl) = val.as_inline_table_mut() {
-                        if inline_tbl.contains_key("version") {
-                            inline_tbl.insert("version", new_version.into());
-                        }
-                    }
+    if let Some(deps_table) = get_dependencies_table_mut(doc) {
+        update_version_in_table(deps_table, crate_name, new_version);
+    }
+    for section in get_dependency_sections(doc) {
+        if let Some(deps_table) = get_table_at_path_mut(doc, &section) {
+            update_version_in_table(deps_table, crate_name, new_version);
+        }
+    }
+
+    Ok(())
+}
+
+fn update_version_in_table(deps_table: &mut Table, crate_name: &str, new_version: &str) {
+    if let Some(dep_value) = deps_table.get_mut(crate_name) {
+        match dep_value {
+            Item::Value(val) => {
+                // Simple string version - replace the entire item
+                if val.is_str() {
+                    *dep_value = toml_edit::value(new_version);
                 }
-                Item::Table(table) => {
-                    // Table with version field - update it
-                    if table.contains_key("version") {
-                        table.insert("version", toml_edit::value(new_version));
+                // Inline table - update the version field
+                else if let Some(inline_tbl) = val.as_inline_table_mut() {
+                    if inline_tbl.contains_key("version") {
+                        inline_tbl.insert("version", new_version.into());
                     }
                 }
-                _ => {}
             }
+            // Table with version field - update it
+            Item::Table(table) if table.contains_key("version") => {
+                table.insert("version", toml_edit::value(new_version));
+            }
+            _ => {}
         }
     }
+}
 
-    Ok(())
+/// The table at a dotted path such as `package.metadata.tool-deps`
+pub fn get_table_at_path<'a>(doc: &'a DocumentMut, path: &str) -> Option<&'a Table> {
+    path.split('.')
+        .try_fold(doc.as_table(), |table, key| table.get(key)?.as_table())
+}
+
+fn get_table_at_path_mut<'a>(doc: &'a mut DocumentMut, path: &str) -> Option<&'a mut Table> {
+    path.split('.').try_fold(doc.as_table_mut(), |table, key| {
+        table.get_mut(key)?.as_table_mut()
+    })
+}
+
+/// A dependency entry from the dependencies table or, failing that, a `--section` table
+pub fn find_dependency<'a>(doc: &'a DocumentMut, crate_name: &str) -> Option<&'a Item> {
+    get_dependencies_table(doc)
+        .and_then(|deps| deps.get(crate_name))
+        .or_else(|| {
+            get_dependency_sections(doc).iter().find_map(|section| {
+                get_table_at_path(doc, section).and_then(|deps| deps.get(crate_name))
+            })
+        })
 }
 
 /// Get or create the metadata table for cargo-patch-source
@@ -210,6 +397,11 @@ fn get_or_create_metadata_table(doc: &mut DocumentMut) -> &mut Table {
     current
 }
 
+/// Make sure the `cargo-patch-source` metadata table exists, leaving its contents alone
+pub fn ensure_metadata_table(doc: &mut DocumentMut) {
+    get_or_create_metadata_table(doc);
+}
+
 /// Get the metadata table for reading (returns None if doesn't exist)
 fn get_metadata_table(doc: &DocumentMut) -> Option<&Table> {
     // Try workspace first
@@ -238,27 +430,48 @@ pub fn store_original_versions(
     doc: &mut DocumentMut,
     versions: &HashMap<String, String>,
 ) -> Result<()> {
-    let metadata = get_or_create_metadata_table(doc);
+    store_original_versions_with(doc, versions, false)
+}
 
-    // Create a table for versions with sorted keys for deterministic output
-    let mut versions_table = toml_edit::InlineTable::new();
+/// Store original versions in metadata, either inline or as an expanded
+/// `[...cargo-patch-source.original-versions]` table with one crate per line
+pub fn store_original_versions_with(
+    doc: &mut DocumentMut,
+    versions: &HashMap<String, String>,
+    expanded: bool,
+) -> Result<()> {
+    let metadata = get_or_create_metadata_table(doc);
 
     // Sort keys for deterministic ordering
     let mut sorted_versions: Vec<_> = versions.iter().collect();
     sorted_versions.sort_by_key(|(name, _)| *name);
 
-    for (name, version) in sorted_versions {
-        versions_table.insert(name, version.as_str().into());
-    }
+    let versions_item = if expanded {
+        let mut versions_table = Table::new();
+        for (name, version) in sorted_versions {
+            versions_table.insert(name, toml_edit::value(version.as_str()));
+        }
+        Item::Table(versions_table)
+    } else {
+        let mut versions_table = toml_edit::InlineTable::new();
+        for (name, version) in sorted_versions {
+            versions_table.insert(name, version.as_str().into());
+        }
+        Item::Value(toml_edit::Value::InlineTable(versions_table))
+    };
 
-    metadata.insert(
-        ORIGINAL_VERSIONS_KEY,
-        Item::Value(toml_edit::Value::InlineTable(versions_table)),
-    );
+    metadata.insert(ORIGINAL_VERSIONS_KEY, versions_item);
 
     Ok(())
 }
 
+/// Whether original versions are currently stored as an expanded table
+fn has_expanded_original_versions(doc: &DocumentMut) -> bool {
+    get_metadata_table(doc)
+        .and_then(|metadata| metadata.get(ORIGINAL_VERSIONS_KEY))
+        .is_some_and(Item::is_table)
+}
+
 /// Get original versions from metadata
 pub fn get_original_versions(doc: &DocumentMut) -> Result<HashMap<String, String>> {
     let Some(metadata) = get_metadata_table(doc) else {
@@ -297,6 +510,15 @@ pub fn get_original_versions(doc: &DocumentMut) -> Result<HashMap<String, String
 
 /// Add a patch source to the managed list
 pub fn add_managed_patch(doc: &mut DocumentMut, patch_key: &str) -> Result<()> {
+    // Turn a plain string into the array form before appending to it
+    let is_string = get_metadata_table(doc)
+        .and_then(|metadata| metadata.get(MANAGED_PATCHES_KEY))
+        .is_some_and(Item::is_str);
+    if is_string {
+        let existing = get_managed_patches(doc);
+        set_managed_patches(doc, &existing);
+    }
+
     let metadata = get_or_create_metadata_table(doc);
 
     // Get existing managed patches or create new array
@@ -319,6 +541,16 @@ pub fn add_managed_patch(doc: &mut DocumentMut, patch_key: &str) -> Result<()> {
     Ok(())
 }
 
+/// Replace the list of managed patch sources
+fn set_managed_patches(doc: &mut DocumentMut, patch_keys: &[String]) {
+    let metadata = get_or_create_metadata_table(doc);
+    let array: toml_edit::Array = patch_keys.iter().map(|key| key.as_str()).collect();
+    metadata.insert(
+        MANAGED_PATCHES_KEY,
+        Item::Value(toml_edit::Value::Array(array)),
+    );
+}
+
 /// Get list of managed patch sources
 pub fn get_managed_patches(doc: &DocumentMut) -> Vec<String> {
     let Some(metadata) = get_metadata_table(doc) else {
@@ -329,6 +561,11 @@ pub fn get_managed_patches(doc: &DocumentMut) -> Vec<String> {
         return Vec::new();
     };
 
+    // A single key may have been written as a plain string
+    if let Some(key) = managed_item.as_str() {
+        return vec![key.to_string()];
+    }
+
     let Some(array) = managed_item.as_array() else {
         return Vec::new();
     };
@@ -339,6 +576,137 @@ pub fn get_managed_patches(doc: &DocumentMut) -> Vec<String> {
         .collect()
 }
 
+/// Which file the managed `[patch]` entries are written to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PatchLocation {
+    /// The target `Cargo.toml`
+    #[default]
+    Manifest,
+    /// `.cargo/config.toml` next to the target manifest
+    CargoConfig,
+}
+
+/// Record where the managed patch entries live; only non-default locations are stored
+pub fn set_patch_location(doc: &mut DocumentMut, location: PatchLocation) {
+    let metadata = get_or_create_metadata_table(doc);
+    match location {
+        PatchLocation::Manifest => {
+            metadata.remove(PATCH_LOCATION_KEY);
+        }
+        PatchLocation::CargoConfig => {
+            metadata.insert(PATCH_LOCATION_KEY, toml_edit::value("cargo-config"));
+        }
+    }
+}
+
+/// Record the git reference the replaced dependencies pointed at, as `{ tag = "v1.0" }`
+pub fn set_git_ref(doc: &mut DocumentMut, reference: &GitReference) {
+    let (kind, name) = match reference {
+        GitReference::Branch(name) => ("branch", name),
+        GitReference::Tag(name) => ("tag", name),
+        GitReference::Rev(name) => ("rev", name),
+    };
+    let mut table = toml_edit::InlineTable::new();
+    table.insert(kind, name.as_str().into());
+    get_or_create_metadata_table(doc).insert(
+        GIT_REF_KEY,
+        Item::Value(toml_edit::Value::InlineTable(table)),
+    );
+}
+
+/// Extra dependency tables recorded by `apply --section`
+pub fn get_dependency_sections(doc: &DocumentMut) -> Vec<String> {
+    get_metadata_table(doc)
+        .and_then(|metadata| metadata.get(DEPENDENCY_SECTIONS_KEY))
+        .and_then(Item::as_array)
+        .map(|array| {
+            array
+                .iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Record extra dependency tables, so versions in them are restored on `remove`
+pub fn add_dependency_sections(doc: &mut DocumentMut, sections: &[String]) {
+    let mut recorded = get_dependency_sections(doc);
+    let added: Vec<_> = sections
+        .iter()
+        .filter(|section| !recorded.contains(section))
+        .cloned()
+        .collect();
+    if added.is_empty() {
+        return;
+    }
+    recorded.extend(added);
+
+    let mut array = toml_edit::Array::new();
+    for section in &recorded {
+        array.push(section.as_str());
+    }
+    get_or_create_metadata_table(doc).insert(DEPENDENCY_SECTIONS_KEY, toml_edit::value(array));
+}
+
+/// Record the source commit the managed patches were applied from, or drop a stale one
+pub fn set_source_revision(doc: &mut DocumentMut, revision: Option<&str>) {
+    let metadata = get_or_create_metadata_table(doc);
+    match revision {
+        Some(revision) => {
+            metadata.insert(SOURCE_REVISION_KEY, toml_edit::value(revision));
+        }
+        None => {
+            metadata.remove(SOURCE_REVISION_KEY);
+        }
+    }
+}
+
+/// Where the managed patch entries of a manifest live
+pub fn get_patch_location(doc: &DocumentMut) -> PatchLocation {
+    let location = get_metadata_table(doc)
+        .and_then(|metadata| metadata.get(PATCH_LOCATION_KEY))
+        .and_then(Item::as_str);
+    match location {
+        Some("cargo-config") => PatchLocation::CargoConfig,
+        _ => PatchLocation::Manifest,
+    }
+}
+
+/// Exchange the `[patch]` tables of two documents.
+///
+/// Lets the patch logic work on a manifest while the entries live in `.cargo/config.toml`;
+/// swapping a second time puts both tables back.
+pub fn swap_patch_tables(a: &mut DocumentMut, b: &mut DocumentMut) {
+    let a_patch = a.remove("patch");
+    let b_patch = b.remove("patch");
+    if let Some(patch) = b_patch {
+        a.insert("patch", patch);
+    }
+    if let Some(patch) = a_patch {
+        b.insert("patch", patch);
+    }
+}
+
+/// How each crate's entry under `[patch.<key>]` is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PatchStyle {
+    /// `foo = { path = "..." }`
+    #[default]
+    Inline,
+    /// A `[patch.<key>.foo]` sub-table
+    Table,
+}
+
+impl PatchStyle {
+    /// Serialize a patch entry in this style
+    pub fn entry_item(self, entry: toml_edit::InlineTable) -> Item {
+        match self {
+            Self::Inline => Item::Value(toml_edit::Value::InlineTable(entry)),
+            Self::Table => Item::Table(entry.into_table()),
+        }
+    }
+}
+
 /// Add or update a patch section
 pub fn add_patch_section(
     doc: &mut DocumentMut,
@@ -364,32 +732,250 @@ pub fn add_patch_section(
     source_table.insert(crate_name, Item::Table(patch_spec));
 }
 
+/// Get or create `[patch.<patch_key>]` as a regular table.
+///
+/// Inline forms such as `crates-io = { foo = { ... } }` under `[patch]` are converted in place,
+/// keeping the entries they already contain. Any other non-table `[patch]` item is reported as
+/// malformed in `manifest_path`.
+pub fn get_or_create_patch_source_table<'a>(
+    doc: &'a mut DocumentMut,
+    manifest_path: &Path,
+    patch_key: &str,
+) -> Result<&'a mut Table> {
+    let malformed = |key: String| PatchError::MalformedPatchSection {
+        path: manifest_path.to_path_buf(),
+        key,
+    };
+
+    let patch_section = doc.entry("patch").or_insert(Item::Table(Table::new()));
+    convert_inline_table(patch_section);
+    let patch_section = patch_section
+        .as_table_mut()
+        .ok_or_else(|| malformed("patch".to_string()))?;
+
+    let was_inline = patch_section
+        .get(patch_key)
+        .is_some_and(|item| item.is_inline_table());
+    if was_inline {
+        // The key's `crates-io = ` spacing would otherwise end up in the table header
+        if let Some(mut key) = patch_section.key_mut(patch_key) {
+            key.leaf_decor_mut().clear();
+        }
+    }
+
+    let source_table = patch_section
+        .entry(patch_key)
+        .or_insert(Item::Table(Table::new()));
+    convert_inline_table(source_table);
+    source_table
+        .as_table_mut()
+        .ok_or_else(|| malformed(format!("patch.{}", patch_key)))
+}
+
+/// Write `entries` under `[patch.<patch_key>]` in `style`.
+///
+/// Entries are appended in the given order after any entries already there, so manually grouped
+/// patches keep their position; an entry for a crate that is already patched replaces it in place.
+pub fn write_patch_entries(
+    doc: &mut DocumentMut,
+    manifest_path: &Path,
+    patch_key: &str,
+    entries: &[(String, toml_edit::InlineTable)],
+    style: PatchStyle,
+) -> Result<()> {
+    let source_table = get_or_create_patch_source_table(doc, manifest_path, patch_key)?;
+    for (crate_name, entry) in entries {
+        source_table.insert(crate_name, style.entry_item(entry.clone()));
+    }
+    Ok(())
+}
+
+/// The first `[patch]` item that is not a table, as `patch` or `patch.<key>`.
+///
+/// Cargo rejects such manifests, and the patch logic would otherwise skip or replace them.
+pub fn find_malformed_patch_key(doc: &DocumentMut) -> Option<String> {
+    let patch_section = doc.get("patch")?;
+    let Some(patch_table) = patch_section.as_table_like() else {
+        return Some("patch".to_string());
+    };
+
+    patch_table
+        .iter()
+        .find(|(_, source)| !source.is_table_like())
+        .map(|(patch_key, _)| format!("patch.{}", patch_key))
+}
+
+/// Drop `[patch.<key>]` tables without entries, and `[patch]` itself when nothing is left
+pub fn remove_empty_patch_tables(doc: &mut DocumentMut) {
+    let Some(patch_table) = doc.get_mut("patch").and_then(Item::as_table_like_mut) else {
+        return;
+    };
+    let empty: Vec<_> = patch_table
+        .iter()
+        .filter(|(_, source)| source.as_table_like().is_some_and(|s| s.is_empty()))
+        .map(|(patch_key, _)| patch_key.to_string())
+        .collect();
+    for patch_key in empty {
+        patch_table.remove(&patch_key);
+    }
+    if patch_table.is_empty() {
+        doc.remove("patch");
+    }
+}
+
+/// Crates with an entry under more than one `[patch.<key>]`, each with the keys it appears under
+pub fn find_duplicate_patches(doc: &DocumentMut) -> Vec<(String, Vec<String>)> {
+    let mut keys_by_crate: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    if let Some(patch_table) = doc.get("patch").and_then(Item::as_table_like) {
+        for (patch_key, source) in patch_table.iter() {
+            let Some(source) = source.as_table_like() else {
+                continue;
+            };
+            for (name, _) in source.iter() {
+                keys_by_crate
+                    .entry(name.to_string())
+                    .or_default()
+                    .push(patch_key.to_string());
+            }
+        }
+    }
+
+    keys_by_crate
+        .into_iter()
+        .filter(|(_, keys)| keys.len() > 1)
+        .collect()
+}
+
+/// Drop the `crates-io` entry of crates also patched under a more specific key (a git URL or
+/// registry index), returning each deduplicated crate with the key that was kept.
+///
+/// Crates patched under several specific keys are ambiguous and left alone. A managed
+/// `crates-io` key is forgotten once its table is empty.
+pub fn dedupe_patches(doc: &mut DocumentMut) -> Vec<(String, String)> {
+    let deduped: Vec<(String, String)> = find_duplicate_patches(doc)
+        .into_iter()
+        .filter_map(|(name, keys)| {
+            let specific: Vec<_> = keys.iter().filter(|key| *key != "crates-io").collect();
+            let has_crates_io = specific.len() < keys.len();
+            (has_crates_io && specific.len() == 1).then(|| (name, specific[0].clone()))
+        })
+        .collect();
+    if deduped.is_empty() {
+        return deduped;
+    }
+
+    let removed: Vec<_> = deduped
+        .iter()
+        .map(|(name, _)| RemovedPatch {
+            patch_key: "crates-io".to_string(),
+            name: name.clone(),
+        })
+        .collect();
+    remove_planned_patches(doc, &removed);
+
+    let crates_io_left = doc.get("patch").and_then(|p| p.get("crates-io")).is_some();
+    let managed = get_managed_patches(doc);
+    if !crates_io_left && managed.iter().any(|key| key == "crates-io") {
+        let remaining: Vec<_> = managed
+            .into_iter()
+            .filter(|key| key != "crates-io")
+            .collect();
+        set_managed_patches(doc, &remaining);
+    }
+
+    deduped
+}
+
+/// Remove every `[patch.<key>]` entry for `crate_name`, dropping source tables left empty
+pub fn remove_patch_entries(doc: &mut DocumentMut, crate_name: &str) {
+    let Some(patch_table) = doc.get_mut("patch").and_then(Item::as_table_like_mut) else {
+        return;
+    };
+
+    let mut emptied = Vec::new();
+    for (patch_key, source) in patch_table.iter_mut() {
+        if let Some(source) = source.as_table_like_mut() {
+            if source.remove(crate_name).is_some() && source.is_empty() {
+                emptied.push(patch_key.get().to_string());
+            }
+        }
+    }
+    for patch_key in emptied {
+        patch_table.remove(&patch_key);
+    }
+}
+
+/// Turn an inline table item into a regular table, leaving other items untouched
+fn convert_inline_table(item: &mut Item) {
+    if item.is_inline_table() {
+        let inline = std::mem::take(item);
+        *item = inline
+            .into_table()
+            .map(Item::Table)
+            .unwrap_or_else(|item| item);
+    }
+}
+
 /// Remove all managed patch sections (using metadata tracking)
 pub fn remove_managed_patches(doc: &mut DocumentMut) -> Result<bool> {
+    remove_managed_patches_matching(doc, |_| true)
+}
+
+/// Remove the crates we added under managed patch keys accepted by `key_filter`.
+///
+/// Metadata for the remaining keys is kept so they can be removed later.
+pub fn remove_managed_patches_matching(
+    doc: &mut DocumentMut,
+    key_filter: impl Fn(&str) -> bool,
+) -> Result<bool> {
+    remove_managed_patches_matching_crates(doc, key_filter, &[])
+}
+
+/// Like [`remove_managed_patches_matching`], but only for the crates named in `only`
+/// (dependency keys or package names); an empty `only` selects every managed crate.
+///
+/// Keys that still hold other managed crates stay tracked in the metadata.
+pub fn remove_managed_patches_matching_crates(
+    doc: &mut DocumentMut,
+    key_filter: impl Fn(&str) -> bool,
+    only: &[String],
+) -> Result<bool> {
     // Get list of managed patches from metadata
     let managed_patches = get_managed_patches(doc);
+    let selected: Vec<_> = managed_patches
+        .iter()
+        .filter(|key| key_filter(key))
+        .cloned()
+        .collect();
 
-    if managed_patches.is_empty() {
+    if selected.is_empty() {
         return Err(PatchError::NoPatchesFound);
     }
 
-    // Get the crates we patched from original-versions
-    let original_versions = get_original_versions(doc)?;
-    let patched_crates: Vec<String> = original_versions.keys().cloned().collect();
+    // Get the crates we patched from original-versions, keyed by dependency entry
+    let mut original_versions = get_original_versions(doc)?;
+    let patched_entries: Vec<(String, String)> = original_versions
+        .keys()
+        .map(|key| (key.clone(), resolve_package_name(doc, key)))
+        .filter(|(key, package)| is_selected_crate(only, key, package))
+        .collect();
 
-    let Some(patch_table) = doc.get_mut("patch").and_then(|p| p.as_table_mut()) else {
+    let Some(patch_table) = doc.get_mut("patch").and_then(Item::as_table_like_mut) else {
         return Err(PatchError::NoPatchesFound);
     };
 
-    // For each managed patch key, remove only the specific crates we added
-    for patch_key in &managed_patches {
+    // For each selected patch key, remove only the specific crates we added
+    let mut removed_crates = Vec::new();
+    for patch_key in &selected {
         if let Some(source_table) = patch_table
             .get_mut(patch_key)
-            .and_then(|t| t.as_table_mut())
+            .and_then(Item::as_table_like_mut)
         {
             // Remove each crate patch we added
-            for crate_name in &patched_crates {
-                source_table.remove(crate_name);
+            for (_, package) in &patched_entries {
+                if source_table.remove(package).is_some() {
+                    removed_crates.push(package.clone());
+                }
             }
 
             // If the source table is now empty, remove it entirely
@@ -404,14 +990,356 @@ pub fn remove_managed_patches(doc: &mut DocumentMut) -> Result<bool> {
         doc.remove("patch");
     }
 
-    // Clear metadata
-    clear_metadata(doc)?;
+    // Keep tracking the crates and keys that are still patched
+    for (key, package) in &patched_entries {
+        if removed_crates.contains(package) {
+            original_versions.remove(key);
+        }
+    }
+    let remaining_packages: Vec<_> = original_versions
+        .keys()
+        .map(|key| resolve_package_name(doc, key))
+        .collect();
+    let remaining: Vec<_> = managed_patches
+        .into_iter()
+        .filter(|key| {
+            !selected.contains(key)
+                || doc
+                    .get("patch")
+                    .and_then(|patch| patch.get(key))
+                    .and_then(Item::as_table_like)
+                    .is_some_and(|source| {
+                        remaining_packages
+                            .iter()
+                            .any(|package| source.contains_key(package))
+                    })
+        })
+        .collect();
+
+    if remaining.is_empty() {
+        // Clear metadata
+        clear_metadata(doc)?;
+    } else {
+        let expanded = has_expanded_original_versions(doc);
+        store_original_versions_with(doc, &original_versions, expanded)?;
+        set_managed_patches(doc, &remaining);
+    }
 
     Ok(true)
 }
 
+/// Compute what `remove_managed_patches` would do without touching the document
+pub fn plan_removal(doc: &DocumentMut) -> Result<RemovePlan> {
+    plan_removal_matching(doc, |_| true)
+}
+
+/// Plan a `remove` from an explicit `dependency = version` map instead of our metadata.
+///
+/// Dependencies missing from the manifest are ignored; every patch entry for the remaining
+/// ones is removed, whichever key it is under.
+pub fn plan_removal_from_versions(
+    doc: &DocumentMut,
+    versions: &BTreeMap<String, String>,
+) -> RemovePlan {
+    let deps = get_dependencies_table(doc);
+    let restored_versions: Vec<_> = versions
+        .iter()
+        .filter(|(name, _)| deps.is_some_and(|deps| deps.contains_key(name)))
+        .map(|(name, version)| RestoredVersion {
+            name: name.clone(),
+            version: version.clone(),
+        })
+        .collect();
+
+    let mut removed_patches = Vec::new();
+    if let Some(patch_table) = doc.get("patch").and_then(Item::as_table_like) {
+        for (patch_key, source) in patch_table.iter() {
+            let Some(source) = source.as_table_like() else {
+                continue;
+            };
+            for restored in &restored_versions {
+                let package = resolve_package_name(doc, &restored.name);
+                let already_listed = removed_patches
+                    .iter()
+                    .any(|p: &RemovedPatch| p.patch_key == patch_key && p.name == package);
+                if source.contains_key(&package) && !already_listed {
+                    removed_patches.push(RemovedPatch {
+                        patch_key: patch_key.to_string(),
+                        name: package,
+                    });
+                }
+            }
+        }
+    }
+
+    RemovePlan {
+        dry_run: false,
+        restored_versions,
+        removed_patches,
+    }
+}
+
+/// Plan a `remove --all-keys`: every entry for a crate in `original-versions` is removed,
+/// under any patch key, whether or not `managed-patches` lists that key
+pub fn plan_removal_all_keys(doc: &DocumentMut) -> Result<RemovePlan> {
+    let original_versions = get_original_versions(doc)?;
+    let mut packages: Vec<_> = original_versions
+        .keys()
+        .map(|key| resolve_package_name(doc, key))
+        .collect();
+    packages.sort();
+    packages.dedup();
+
+    let mut removed_patches = Vec::new();
+    if let Some(patch_table) = doc.get("patch").and_then(Item::as_table_like) {
+        for (patch_key, source) in patch_table.iter() {
+            let Some(source) = source.as_table_like() else {
+                continue;
+            };
+            for package in &packages {
+                if source.contains_key(package) {
+                    removed_patches.push(RemovedPatch {
+                        patch_key: patch_key.to_string(),
+                        name: package.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    let restored_versions: Vec<_> = original_versions
+        .into_iter()
+        .filter(|(_, version)| !version.is_empty())
+        .map(|(name, version)| RestoredVersion { name, version })
+        .collect();
+    if restored_versions.is_empty() && removed_patches.is_empty() {
+        return Err(PatchError::NoPatchesFound);
+    }
+
+    Ok(RemovePlan {
+        dry_run: false,
+        restored_versions,
+        removed_patches,
+    })
+}
+
+/// Delete the given patch entries, dropping tables they leave empty.
+///
+/// Returns whether anything was removed.
+pub fn remove_planned_patches(doc: &mut DocumentMut, removed_patches: &[RemovedPatch]) -> bool {
+    let Some(patch_table) = doc.get_mut("patch").and_then(Item::as_table_like_mut) else {
+        return false;
+    };
+
+    let mut removed = false;
+    for planned in removed_patches {
+        let Some(source) = patch_table
+            .get_mut(&planned.patch_key)
+            .and_then(Item::as_table_like_mut)
+        else {
+            continue;
+        };
+        removed |= source.remove(&planned.name).is_some();
+        if source.is_empty() {
+            patch_table.remove(&planned.patch_key);
+        }
+    }
+
+    if patch_table.is_empty() {
+        doc.remove("patch");
+    }
+    removed
+}
+
+/// Compute what `remove_managed_patches_matching` would do without touching the document
+pub fn plan_removal_matching(
+    doc: &DocumentMut,
+    key_filter: impl Fn(&str) -> bool,
+) -> Result<RemovePlan> {
+    plan_removal_matching_crates(doc, key_filter, &[])
+}
+
+/// Compute what `remove_managed_patches_matching_crates` would do without touching the document
+pub fn plan_removal_matching_crates(
+    doc: &DocumentMut,
+    key_filter: impl Fn(&str) -> bool,
+    only: &[String],
+) -> Result<RemovePlan> {
+    let managed_patches = get_managed_patches(doc);
+    let selected: Vec<_> = managed_patches
+        .iter()
+        .filter(|key| key_filter(key))
+        .collect();
+
+    if selected.is_empty() {
+        return Err(PatchError::NoPatchesFound);
+    }
+
+    let Some(patch_table) = doc.get("patch").and_then(Item::as_table_like) else {
+        return Err(PatchError::NoPatchesFound);
+    };
+
+    let original_versions = get_original_versions(doc)?;
+    let mut patched_crates: Vec<_> = original_versions
+        .iter()
+        .filter(|(key, _)| is_selected_crate(only, key, &resolve_package_name(doc, key)))
+        .collect();
+    if patched_crates.is_empty() {
+        return Err(PatchError::NoPatchesFound);
+    }
+    patched_crates.sort_by_key(|(name, _)| *name);
+
+    // Several renamed entries can share one patch, keyed by the package name
+    let mut packages: Vec<_> = patched_crates
+        .iter()
+        .map(|(key, _)| resolve_package_name(doc, key))
+        .collect();
+    packages.sort();
+    packages.dedup();
+
+    let mut removed_patches = Vec::new();
+    for patch_key in &selected {
+        if let Some(source_table) = patch_table.get(patch_key).and_then(Item::as_table_like) {
+            for package in &packages {
+                if source_table.contains_key(package) {
+                    removed_patches.push(RemovedPatch {
+                        patch_key: patch_key.to_string(),
+                        name: package.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    // When only some keys are removed, only their crates get their versions back
+    if selected.len() < managed_patches.len() {
+        patched_crates.retain(|(key, _)| {
+            let package = resolve_package_name(doc, key);
+            removed_patches.iter().any(|p| p.name == package)
+        });
+    }
+
+    let restored_versions = patched_crates
+        .iter()
+        .filter(|(_, version)| !version.is_empty())
+        .map(|(name, version)| RestoredVersion {
+            name: name.to_string(),
+            version: version.to_string(),
+        })
+        .collect();
+
+    Ok(RemovePlan {
+        dry_run: false,
+        restored_versions,
+        removed_patches,
+    })
+}
+
+/// Whether a managed dependency is named by `only`; an empty list selects everything
+fn is_selected_crate(only: &[String], dep_key: &str, package: &str) -> bool {
+    only.is_empty() || only.iter().any(|name| name == dep_key || name == package)
+}
+
+/// Fingerprint of a patch entry that ignores formatting, key order and inline vs table style
+pub fn patch_entry_checksum(entry: &Item) -> String {
+    let mut fields: Vec<String> = entry
+        .as_table_like()
+        .map(|table| {
+            table
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value.to_string().trim()))
+                .collect()
+        })
+        .unwrap_or_else(|| vec![entry.to_string().trim().to_string()]);
+    fields.sort();
+
+    // FNV-1a, so the value stays the same across Rust releases
+    let hash = fields
+        .join(",")
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+    format!("{:016x}", hash)
+}
+
+/// The managed patch entries we added, as `(patch key, crate, entry)`
+fn managed_patch_entries(doc: &DocumentMut) -> Vec<(String, String, &Item)> {
+    let packages: HashSet<String> = get_original_versions(doc)
+        .unwrap_or_default()
+        .keys()
+        .map(|key| resolve_package_name(doc, key))
+        .collect();
+
+    let mut entries = Vec::new();
+    for patch_key in get_managed_patches(doc) {
+        let Some(table) = doc
+            .get("patch")
+            .and_then(|patch| patch.get(&patch_key))
+            .and_then(Item::as_table_like)
+        else {
+            continue;
+        };
+        for (name, entry) in table.iter() {
+            if packages.contains(name) {
+                entries.push((patch_key.clone(), name.to_string(), entry));
+            }
+        }
+    }
+    entries
+}
+
+/// Store a checksum of every managed patch entry, so later hand edits can be spotted
+pub fn record_patch_checksums(doc: &mut DocumentMut) {
+    let mut checksums = toml_edit::InlineTable::new();
+    for (patch_key, name, entry) in managed_patch_entries(doc) {
+        let checksum = patch_entry_checksum(entry);
+        let key_checksums = checksums
+            .entry(&patch_key)
+            .or_insert_with(|| toml_edit::InlineTable::new().into());
+        if let Some(key_checksums) = key_checksums.as_inline_table_mut() {
+            key_checksums.insert(&name, checksum.into());
+        }
+    }
+    checksums.sort_values();
+
+    get_or_create_metadata_table(doc).insert(
+        CHECKSUMS_KEY,
+        Item::Value(toml_edit::Value::InlineTable(checksums)),
+    );
+}
+
+/// Whether checksums of the managed entries are being recorded
+pub fn has_patch_checksums(doc: &DocumentMut) -> bool {
+    get_metadata_table(doc).is_some_and(|metadata| metadata.contains_key(CHECKSUMS_KEY))
+}
+
+/// Managed entries whose checksum no longer matches the recorded one, as `(patch key, crate)`.
+///
+/// Entries without a recorded checksum, and checksums without an entry, are ignored.
+pub fn find_edited_patches(doc: &DocumentMut) -> Vec<(String, String)> {
+    let Some(checksums) = get_metadata_table(doc).and_then(|metadata| metadata.get(CHECKSUMS_KEY))
+    else {
+        return Vec::new();
+    };
+
+    let mut edited: Vec<_> = managed_patch_entries(doc)
+        .into_iter()
+        .filter(|(patch_key, name, entry)| {
+            checksums
+                .get(patch_key)
+                .and_then(|key_checksums| key_checksums.get(name))
+                .and_then(Item::as_str)
+                .is_some_and(|recorded| recorded != patch_entry_checksum(entry))
+        })
+        .map(|(patch_key, name, _)| (patch_key, name))
+        .collect();
+    edited.sort();
+    edited
+}
+
 /// Clear all cargo-patch-source metadata
-fn clear_metadata(doc: &mut DocumentMut) -> Result<()> {
+pub fn clear_metadata(doc: &mut DocumentMut) -> Result<()> {
     // Try workspace first
     if let Some(workspace) = doc.get_mut("workspace") {
         if let Some(metadata) = workspace.get_mut("metadata") {
@@ -446,3 +1374,166 @@ fn clear_metadata(doc: &mut DocumentMut) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_common_git_ref_uses_the_majority() {
+        let doc: DocumentMut = r#"
+[dependencies]
+a = { git = "https://example.com/repo", tag = "v1.0" }
+b = { git = "https://example.com/repo", tag = "v1.0" }
+c = { git = "https://example.com/repo", branch = "main" }
+d = { version = "1.0", tag = "ignored" }
+"#
+        .parse()
+        .unwrap();
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            detect_common_git_ref(&doc, &names(&["a", "b", "c"])),
+            Some(GitReference::Tag("v1.0".to_string()))
+        );
+        assert_eq!(detect_common_git_ref(&doc, &names(&["a", "c"])), None);
+        assert_eq!(detect_common_git_ref(&doc, &names(&["d"])), None);
+    }
+
+    #[test]
+    fn patch_entry_checksum_ignores_style_and_key_order() {
+        let doc: DocumentMut = r#"
+[patch.crates-io]
+a = { path = "../a", package = "b" }
+
+[patch.crates-io.c]
+package = "b"
+path = "../a"
+"#
+        .parse()
+        .unwrap();
+        let patches = &doc["patch"]["crates-io"];
+        assert_eq!(
+            patch_entry_checksum(&patches["a"]),
+            patch_entry_checksum(&patches["c"])
+        );
+
+        let other: DocumentMut = "a = { path = \"../other\" }".parse().unwrap();
+        assert_ne!(
+            patch_entry_checksum(&patches["a"]),
+            patch_entry_checksum(&other["a"])
+        );
+    }
+
+    #[test]
+    fn string_valued_managed_patches_is_a_single_key() {
+        let mut doc: DocumentMut = r#"
+[package]
+name = "demo"
+
+[package.metadata.cargo-patch-source]
+original-versions = { foo = "1.0.0" }
+managed-patches = "crates-io"
+
+[dependencies]
+foo = "1.1.0"
+
+[patch.crates-io]
+foo = { path = "../foo" }
+"#
+        .parse()
+        .unwrap();
+
+        assert_eq!(get_managed_patches(&doc), ["crates-io"]);
+
+        add_managed_patch(&mut doc, "https://github.com/org/repo").unwrap();
+        assert_eq!(
+            get_managed_patches(&doc),
+            ["crates-io", "https://github.com/org/repo"]
+        );
+
+        assert!(remove_managed_patches_matching(&mut doc, |key| key == "crates-io").unwrap());
+        assert!(doc.get("patch").is_none());
+        assert_eq!(get_managed_patches(&doc), ["https://github.com/org/repo"]);
+    }
+
+    #[test]
+    fn write_patch_entries_appends_and_rejects_malformed_sections() {
+        let path = Path::new("Cargo.toml");
+        let entry = |dir: &str| {
+            let mut entry = toml_edit::InlineTable::new();
+            entry.insert("path", dir.into());
+            entry
+        };
+        let mut doc: DocumentMut = r#"
+[patch]
+crates-io = { manual = { path = "../manual" } }
+"#
+        .parse()
+        .unwrap();
+
+        let entries = [
+            ("b".to_string(), entry("../b")),
+            ("a".to_string(), entry("../a")),
+        ];
+        write_patch_entries(&mut doc, path, "crates-io", &entries, PatchStyle::Inline).unwrap();
+        write_patch_entries(
+            &mut doc,
+            path,
+            "https://github.com/org/repo",
+            &entries[..1],
+            PatchStyle::Table,
+        )
+        .unwrap();
+        let crates_io = doc["patch"]["crates-io"].as_table().unwrap();
+        let names: Vec<_> = crates_io.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["manual", "b", "a"]);
+        assert!(crates_io["b"].is_inline_table());
+        let git = &doc["patch"]["https://github.com/org/repo"]["b"];
+        assert_eq!(git.as_table().unwrap()["path"].as_str(), Some("../b"));
+
+        let mut doc: DocumentMut = "[patch]\ncrates-io = \"oops\"\n".parse().unwrap();
+        let err = write_patch_entries(&mut doc, path, "crates-io", &entries, PatchStyle::Inline)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PatchError::MalformedPatchSection { key, .. } if key == "patch.crates-io"
+        ));
+    }
+
+    #[test]
+    fn remove_managed_patches_from_an_inline_patch_table() {
+        let original: DocumentMut = r#"
+[package]
+name = "demo"
+
+[package.metadata.cargo-patch-source]
+original-versions = { foo = "1.0.0" }
+managed-patches = ["crates-io"]
+
+[dependencies]
+foo = "1.1.0"
+
+[patch]
+crates-io = { foo = { path = "../foo" }, manual = { path = "../manual" } }
+"#
+        .parse()
+        .unwrap();
+
+        let plan = plan_removal_matching_crates(&original, |_| true, &[]).unwrap();
+        assert_eq!(
+            plan.removed_patches,
+            [RemovedPatch {
+                patch_key: "crates-io".to_string(),
+                name: "foo".to_string(),
+            }]
+        );
+
+        let mut doc = original.clone();
+        assert!(remove_managed_patches_matching_crates(&mut doc, |_| true, &[]).unwrap());
+        let crates_io = doc["patch"]["crates-io"].as_table_like().unwrap();
+        assert!(crates_io.get("foo").is_none());
+        assert!(crates_io.get("manual").is_some());
+        assert!(get_managed_patches(&doc).is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::error::{PatchError, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// How long the source has to be quiet before a change is re-applied
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Whether a filesystem event below `source_root` should trigger a re-apply.
+///
+/// Reads are ignored, and so is anything below `target/` or `.git/` and every `Cargo.lock`,
+/// which `cargo metadata` and builds touch on their own.
+pub fn is_relevant_event(event: &Event, source_root: &Path) -> bool {
+    if matches!(event.kind, EventKind::Access(_)) {
+        return false;
+    }
+    event.paths.iter().any(|path| {
+        let relative = path.strip_prefix(source_root).unwrap_or(path);
+        let ignored = relative
+            .file_name()
+            .is_some_and(|name| name == "Cargo.lock")
+            || relative.components().any(|component| {
+                component.as_os_str() == "target" || component.as_os_str() == ".git"
+            });
+        !ignored
+    })
+}
+
+/// Watch `source_root` and call `apply` after every burst of relevant changes.
+///
+/// Runs until the process is interrupted; manifests are only ever written whole, so
+/// stopping with Ctrl-C between re-applies leaves them consistent.
+pub fn watch_source(
+    source_root: &Path,
+    debounce: Duration,
+    apply: impl FnMut() -> Result<()>,
+) -> Result<()> {
+    let watch_error = |e: notify::Error| PatchError::WatchError {
+        path: source_root.to_path_buf(),
+        message: e.to_string(),
+    };
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+    watcher
+        .watch(source_root, RecursiveMode::Recursive)
+        .map_err(watch_error)?;
+
+    info!(
+        "Watching {} for changes, press Ctrl-C to stop",
+        source_root.display()
+    );
+    run_watch_loop(&events, source_root, debounce, apply);
+    Ok(())
+}
+
+/// Re-apply once per burst of relevant events, until the event channel closes.
+///
+/// A failed re-apply is reported and the loop keeps watching, so a half-saved source file
+/// doesn't end the session.
+pub fn run_watch_loop(
+    events: &Receiver<notify::Result<Event>>,
+    source_root: &Path,
+    debounce: Duration,
+    mut apply: impl FnMut() -> Result<()>,
+) {
+    loop {
+        // Block until something relevant changes
+        match events.recv() {
+            Ok(Ok(event)) if is_relevant_event(&event, source_root) => {}
+            Ok(_) => continue,
+            Err(_) => return,
+        }
+
+        // Let the burst settle, e.g. an editor writing several files
+        let mut closed = false;
+        loop {
+            match events.recv_timeout(debounce) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    closed = true;
+                    break;
+                }
+            }
+        }
+
+        info!("Source changed, re-applying patches");
+        if let Err(err) = apply() {
+            error!("{:?}", miette::Report::new(err));
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind, ModifyKind};
+    use std::path::PathBuf;
+
+    fn event(kind: EventKind, path: &str) -> notify::Result<Event> {
+        Ok(Event::new(kind).add_path(PathBuf::from(path)))
+    }
+
+    #[test]
+    fn watch_loop_reapplies_once_per_burst_of_changes() {
+        let (sender, events) = mpsc::channel();
+        sender
+            .send(event(
+                EventKind::Access(AccessKind::Any),
+                "/ws/crates/a/src/lib.rs",
+            ))
+            .unwrap();
+        sender
+            .send(event(
+                EventKind::Create(CreateKind::File),
+                "/ws/target/debug/a",
+            ))
+            .unwrap();
+        sender
+            .send(event(EventKind::Modify(ModifyKind::Any), "/ws/Cargo.lock"))
+            .unwrap();
+        sender
+            .send(event(
+                EventKind::Modify(ModifyKind::Any),
+                "/ws/crates/a/Cargo.toml",
+            ))
+            .unwrap();
+        sender
+            .send(event(
+                EventKind::Modify(ModifyKind::Any),
+                "/ws/crates/a/src/lib.rs",
+            ))
+            .unwrap();
+        drop(sender);
+
+        let mut applied = 0;
+        run_watch_loop(&events, Path::new("/ws"), Duration::from_millis(10), || {
+            applied += 1;
+            Ok(())
+        });
+        assert_eq!(applied, 1);
+    }
+}
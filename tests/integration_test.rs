@@ -1,5 +1,14 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use cargo_patch_source::cargo_ops::MetadataOptions;
+use cargo_patch_source::config::{config_path_for, init_config};
+use cargo_patch_source::git::GitCloneOptions;
 use cargo_patch_source::source::{GitReference, PatchSource};
-use cargo_patch_source::{apply_patches, remove_patches};
+use cargo_patch_source::toml_ops::{PatchLocation, PatchStyle};
+use cargo_patch_source::{
+    apply_patches, apply_patches_with_options, find_unused_patches, format_effective_config,
+    remove_patches, remove_patches_with_options, ApplyOptions, MatchOn, MergeStrategy,
+    OutputFormat, RemoveOptions,
+};
 use insta::assert_snapshot;
 use toml_edit::DocumentMut;
 
@@ -25,6 +34,22 @@ fn rattler_project(fixture: &TestFixture) -> Project {
         .build()
 }
 
+/// Replace the timing of the final `apply` line, which varies between runs
+fn normalize_elapsed(output: &str) -> String {
+    regex::Regex::new(r" in \d+\.\ds")
+        .unwrap()
+        .replace_all(output, " in <elapsed>")
+        .into_owned()
+}
+
+fn patch_source_cmd() -> assert_cmd::Command {
+    let mut cmd = cargo_bin_cmd!("cargo-patch-source");
+    // cargo sets this for the test process, which would change the default manifest
+    cmd.env_remove("CARGO_MANIFEST_DIR");
+    cmd.arg("patch-source");
+    cmd
+}
+
 fn normalize_manifest(content: &str, workspace: Option<&Workspace>) -> String {
     let mut normalized = content.to_string();
     if let Some(ws) = workspace {
@@ -231,6 +256,100 @@ rattler-two = "2.0.0"
     );
 }
 
+#[test]
+fn test_remove_warns_when_restore_does_not_land() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+
+    // The dependency lost its version field since apply, so there is nothing to restore into
+    let changed = project.read_manifest().replace(
+        "\nrattler-one = \"1.0.0\"\n",
+        "\nrattler-one = { git = \"https://github.com/org/rattler\" }\n",
+    );
+    project.write_manifest(&changed);
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .arg("-q")
+        .arg("remove")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    assert_snapshot!(
+        String::from_utf8(output.stderr).unwrap(),
+        @"warning: Could not restore rattler-one to 1.0.0 (found no version); update it by hand"
+    );
+    assert!(!project.read_manifest().contains("[patch"));
+}
+
+#[test]
+fn test_apply_remove_with_quoted_dependency_keys() {
+    let fixture = TestFixture::new();
+    let workspace = fixture
+        .workspace("mock-workspace")
+        .member("rattler-one", "1.1.0")
+        .member("rattler-two", "2.0.0")
+        .build();
+    let project = rattler_project(&fixture);
+    let original = r#"[package]
+name = "target-project"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+"rattler-one" = "1.0.0"
+'rattler-two' = { version = "2.0.0" }
+"#;
+    project.write_manifest(original);
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+
+    let content = normalize_manifest(&project.read_manifest(), Some(&workspace));
+    // Keys are matched unquoted, while the quoting in the manifest is kept as written
+    assert_snapshot!(content, @r###"
+    [package]
+    name = "target-project"
+    version = "0.1.0"
+    edition = "2021"
+
+    [package.metadata]
+
+    [package.metadata.cargo-patch-source]
+    original-versions = { rattler-one = "1.0.0", rattler-two = "2.0.0" }
+    managed-patches = ["crates-io"]
+
+    [dependencies]
+    "rattler-one" = "1.1.0"
+    'rattler-two' = { version = "2.0.0" }
+
+    [patch]
+
+    [patch.crates-io]
+    rattler-one = { path = "<workspace>/crates/rattler-one" }
+    rattler-two = { path = "<workspace>/crates/rattler-two" }
+    "###);
+
+    remove_patches(Some(project.manifest_path().to_path_buf())).unwrap();
+    assert_eq!(project.read_manifest(), original);
+}
+
 #[test]
 fn test_apply_remove_roundtrip() {
     let fixture = TestFixture::new();
@@ -267,6 +386,82 @@ rattler-two = "2.0.0"
     );
 }
 
+#[test]
+fn test_apply_expanded_metadata_roundtrip() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let manifest_path = project.manifest_path().to_path_buf();
+    let original_content = project.read_manifest();
+
+    let options = ApplyOptions {
+        expanded_metadata: true,
+        ..Default::default()
+    };
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(manifest_path.clone()),
+        &options,
+    )
+    .unwrap();
+
+    let content = project.read_manifest();
+    let metadata = &content
+        [content.find("[package.metadata.").unwrap()..content.find("[dependencies]").unwrap()];
+    assert_snapshot!(metadata, @r###"
+    [package.metadata.cargo-patch-source]
+    managed-patches = ["crates-io"]
+
+    [package.metadata.cargo-patch-source.original-versions]
+    other-crate = "3.0.0"
+    rattler-one = "1.0.0"
+    rattler-two = "2.0.0"
+    "###);
+
+    remove_patches(Some(manifest_path)).unwrap();
+    assert_eq!(project.read_manifest(), original_content);
+}
+
+#[test]
+fn test_apply_table_patch_style_roundtrip() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let manifest_path = project.manifest_path().to_path_buf();
+    let original_content = project.read_manifest();
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(manifest_path.clone()),
+        &ApplyOptions {
+            patterns: vec!["rattler-*".to_string()],
+            patch_style: PatchStyle::Table,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let content = normalize_manifest(&project.read_manifest(), Some(&workspace));
+    assert_snapshot!(&content[content.find("[patch").unwrap()..], @r###"
+    [patch]
+
+    [patch.crates-io]
+
+    [patch.crates-io.rattler-one]
+    path = "<workspace>/crates/rattler-one"
+
+    [patch.crates-io.rattler-two]
+    path = "<workspace>/crates/rattler-two"
+    "###);
+
+    let state = cargo_patch_source::managed_state(&manifest_path).unwrap();
+    let patch = normalize_manifest(&state.patches["crates-io"][0].patch, Some(&workspace));
+    assert_eq!(patch, r#"{ path = "<workspace>/crates/rattler-one" }"#);
+
+    remove_patches(Some(manifest_path)).unwrap();
+    assert_eq!(project.read_manifest(), original_content);
+}
+
 #[test]
 fn test_apply_git_patches() {
     let fixture = TestFixture::new();
@@ -318,6 +513,204 @@ rattler-two = { git = "https://github.com/prefix-dev/rattler", branch = "main" }
     );
 }
 
+#[test]
+fn test_apply_appends_new_patches_after_existing_entries() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+
+    project.append_manifest(
+        r#"
+[patch.crates-io]
+zeta-crate = { path = "/custom/zeta" }
+alpha-crate = { path = "/custom/alpha" }
+"#,
+    );
+
+    let source = PatchSource::git("https://github.com/prefix-dev/rattler".to_string(), None);
+    apply_patches(
+        source,
+        Some(project.manifest_path().to_path_buf()),
+        Some("*"),
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let order: Vec<_> = doc["patch"]["crates-io"]
+        .as_table()
+        .unwrap()
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    assert_snapshot!(
+        format!("{:?}", order),
+        @r###"["zeta-crate", "alpha-crate", "other-crate", "rattler-one", "rattler-two"]"###
+    );
+}
+
+#[test]
+fn test_apply_git_inherits_the_dependency_ref() {
+    let fixture = TestFixture::new();
+    let project = fixture
+        .project("git-ref-project")
+        .dep(
+            "rattler-one",
+            DependencySpec::git("https://github.com/prefix-dev/rattler").tag("v1"),
+        )
+        .dep_version("rattler-two", "2.0.0")
+        .build();
+
+    apply_patches(
+        PatchSource::git(
+            "https://github.com/prefix-dev/rattler.git".to_string(),
+            None,
+        ),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let entry = |name: &str| {
+        doc["patch"]["crates-io"][name]
+            .to_string()
+            .trim()
+            .to_string()
+    };
+    assert_eq!(
+        entry("rattler-one"),
+        r#"{ git = "https://github.com/prefix-dev/rattler.git", tag = "v1" }"#
+    );
+    // Only dependencies on the same repository have a ref to inherit
+    assert_eq!(
+        entry("rattler-two"),
+        r#"{ git = "https://github.com/prefix-dev/rattler.git" }"#
+    );
+}
+
+#[test]
+fn test_apply_preserves_crlf_line_endings() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    let crlf = project.read_manifest().replace('\n', "\r\n");
+    project.write_manifest(&crlf);
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+
+    let content = project.read_manifest();
+    assert!(content.contains("[patch.crates-io]\r\n"));
+    assert_eq!(
+        content.matches('\n').count(),
+        content.matches("\r\n").count()
+    );
+
+    remove_patches(Some(project.manifest_path().to_path_buf())).unwrap();
+    assert_eq!(project.read_manifest(), crlf);
+}
+
+#[test]
+fn test_apply_keeps_missing_trailing_newline() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let original = "[package]\nname = \"target-project\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\nunrelated = \"1.0\"";
+    project.write_manifest(original);
+
+    // Nothing in the source is a dependency, so apply is a no-op
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+    assert_eq!(project.read_manifest(), original);
+
+    // Extra trailing newlines are kept as well
+    let padded = format!("{}\n\n\n", original);
+    project.write_manifest(&padded);
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+    assert_eq!(project.read_manifest(), padded);
+}
+
+#[test]
+fn test_apply_git_with_per_crate_refs() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+
+    patch_source_cmd()
+        .arg("apply")
+        .arg("--git")
+        .arg("https://github.com/prefix-dev/rattler")
+        .arg("--branch")
+        .arg("main")
+        .arg("--pattern")
+        .arg("*")
+        .arg("--crate-ref")
+        .arg("rattler-one=branchA")
+        .arg("--crate-ref")
+        .arg("rattler-two=tag:v2.0.0")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let entries: Vec<_> = doc["patch"]["crates-io"]
+        .as_table()
+        .unwrap()
+        .iter()
+        .map(|(name, value)| format!("{} = {}", name, value.to_string().trim_start()))
+        .collect();
+    assert_snapshot!(entries.join("\n"), @r###"
+other-crate = { git = "https://github.com/prefix-dev/rattler", branch = "main" }
+rattler-one = { git = "https://github.com/prefix-dev/rattler", branch = "branchA" }
+rattler-two = { git = "https://github.com/prefix-dev/rattler", tag = "v2.0.0" }
+"###);
+}
+
+#[test]
+fn test_apply_into_inline_patch_table() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+
+    project.append_manifest(
+        r#"
+[patch]
+crates-io = { custom-crate = { path = "/custom/path" }, rattler-two = { path = "/custom/two" } }
+"#,
+    );
+
+    let source = PatchSource::git("https://github.com/prefix-dev/rattler".to_string(), None);
+    apply_patches(
+        source,
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+
+    let content = project.read_manifest();
+    let patch_section = &content[content.find("[patch").unwrap()..];
+    assert_snapshot!(patch_section, @r###"
+    [patch]
+
+    [patch.crates-io]
+    custom-crate = { path = "/custom/path" }
+    rattler-two = { path = "/custom/two" }
+    rattler-one = { git = "https://github.com/prefix-dev/rattler" }
+    "###);
+}
+
 #[test]
 fn test_workspace_detection() {
     let fixture = TestFixture::new();
@@ -564,59 +957,3578 @@ rattler-one = { path = "/custom/user/path" }
 }
 
 #[test]
-fn test_patch_git_dependencies_without_version() {
+fn test_apply_merge_strategies() {
     let fixture = TestFixture::new();
     let workspace = rattler_workspace(&fixture);
-    let project = fixture
-        .project("git-deps-project")
-        .dep(
-            "rattler-one",
-            DependencySpec::git("https://github.com/prefix-dev/rattler").tag("v1.0.0"),
-        )
-        .dep(
-            "rattler-two",
-            DependencySpec::git("https://github.com/prefix-dev/rattler").tag("v1.0.0"),
-        )
-        .dep(
-            "other-crate",
-            DependencySpec::git("https://github.com/prefix-dev/rattler").tag("v1.0.0"),
-        )
-        .build();
-
-    apply_patches(
-        PatchSource::local_path(workspace.path().to_path_buf()),
-        Some(project.manifest_path().to_path_buf()),
-        None,
-    )
-    .unwrap();
+    let existing = r#"
+[patch.crates-io]
+rattler-one = { path = "/custom/user/path" }
+"#;
+    let apply_with = |name: &str, merge_strategy: MergeStrategy| {
+        let project = fixture
+            .project(name)
+            .dep_version("rattler-one", "1.0.0")
+            .dep_version("rattler-two", "2.0.0")
+            .build();
+        project.append_manifest(existing);
+        let result = apply_patches_with_options(
+            PatchSource::local_path(workspace.path().to_path_buf()),
+            Some(project.manifest_path().to_path_buf()),
+            &ApplyOptions {
+                merge_strategy,
+                ..Default::default()
+            },
+        );
+        (project, result)
+    };
+    let rattler_one_patch = |project: &Project| {
+        let doc: DocumentMut = project.read_manifest().parse().unwrap();
+        let entry = doc["patch"]["crates-io"]["rattler-one"].to_string();
+        normalize_manifest(entry.trim(), Some(&workspace))
+    };
 
-    let content = project.read_manifest();
-    let normalized = normalize_manifest(&content, Some(&workspace));
-    assert_snapshot!(
-        normalized.as_str(),
-        @r###"
-[package]
-name = "git-deps-project"
-version = "0.1.0"
-edition = "2021"
+    let (project, result) = apply_with("skip", MergeStrategy::Skip);
+    result.unwrap();
+    assert_eq!(
+        rattler_one_patch(&project),
+        r#"{ path = "/custom/user/path" }"#
+    );
 
-[package.metadata]
+    let (project, result) = apply_with("overwrite", MergeStrategy::Overwrite);
+    result.unwrap();
+    assert_eq!(
+        rattler_one_patch(&project),
+        r#"{ path = "<workspace>/crates/rattler-one" }"#
+    );
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert!(
+        doc["package"]["metadata"]["cargo-patch-source"]["original-versions"]
+            .get("rattler-one")
+            .is_some()
+    );
 
-[package.metadata.cargo-patch-source]
-original-versions = { other-crate = "", rattler-one = "", rattler-two = "" }
-managed-patches = ["https://github.com/prefix-dev/rattler"]
+    let (project, result) = apply_with("error", MergeStrategy::Error);
+    let before = project.read_manifest();
+    match result.unwrap_err() {
+        cargo_patch_source::PatchError::ExistingPatchEntries { crates } => {
+            assert_eq!(crates, ["rattler-one"])
+        }
+        err => panic!("unexpected error: {err}"),
+    }
+    assert_eq!(project.read_manifest(), before);
+    assert!(before.ends_with(existing));
+}
 
-[dependencies]
-other-crate = { git = "https://github.com/prefix-dev/rattler", tag = "v1.0.0" }
-rattler-one = { git = "https://github.com/prefix-dev/rattler", tag = "v1.0.0" }
-rattler-two = { git = "https://github.com/prefix-dev/rattler", tag = "v1.0.0" }
+#[test]
+fn test_apply_print_plan_table() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    project.append_manifest(
+        r#"
+[patch.crates-io]
+rattler-two = { path = "/custom/user/path" }
+"#,
+    );
 
-[patch]
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .arg("apply")
+        .arg("--print-plan")
+        .arg("--pattern")
+        .arg("rattler-*")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = normalize_manifest(&String::from_utf8(output.stderr).unwrap(), Some(&workspace));
+    let table = stderr
+        .lines()
+        .skip_while(|line| !line.starts_with("  crate"))
+        .take(3)
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert_snapshot!(table, @r###"
+      crate        old req  new version  action  patch target
+      rattler-one  1.0.0    1.0.0        patch   <workspace>/crates/rattler-one
+      rattler-two  2.0.0    -            skip    -
+    "###);
+}
 
-[patch."https://github.com/prefix-dev/rattler"]
-other-crate = { path = "<workspace>/crates/other-crate" }
-rattler-one = { path = "<workspace>/crates/rattler-one" }
-rattler-two = { path = "<workspace>/crates/rattler-two" }
+#[test]
+fn test_apply_explain() {
+    let fixture = TestFixture::new();
+    let workspace = fixture
+        .workspace("mock-workspace")
+        .member("rattler-one", "1.2.0")
+        .member("rattler-two", "2.0.0")
+        .build();
+    let project = rattler_project(&fixture);
+    project.append_manifest(
+        r#"
+[patch.crates-io]
+rattler-two = { path = "/custom/user/path" }
+"#,
+    );
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .arg("apply")
+        .arg("--explain")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = normalize_manifest(&String::from_utf8_lossy(&output.stderr), Some(&workspace));
+    let explanations: Vec<_> = stderr
+        .lines()
+        .filter(|line| line.starts_with("  rattler-"))
+        .collect();
+
+    assert_eq!(
+        explanations,
+        [
+            "  rattler-one: found in [dependencies] at 1.0.0, source provides 1.2.0, patched under \
+             [patch.crates-io] -> path <workspace>/crates/rattler-one; updated requirement to 1.2.0.",
+            "  rattler-two: found in [dependencies] at 2.0.0, skipped because a patch entry already exists.",
+        ]
+    );
+}
+
+#[test]
+fn test_apply_match_on_dep_key_or_crate_name() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let git_url = workspace.file_url();
+    let sources = [
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        PatchSource::git(git_url.clone(), None),
+    ];
+    for (index, source) in sources.into_iter().enumerate() {
+        let apply_with = |name: String, match_on: MatchOn| {
+            let project = fixture
+                .project(name)
+                .dep(
+                    "legacy-one",
+                    DependencySpec::version("1.0.0").package("rattler-one"),
+                )
+                .dep_version("rattler-two", "2.0.0")
+                .build();
+            let result = apply_patches_with_options(
+                source.clone(),
+                Some(project.manifest_path().to_path_buf()),
+                &ApplyOptions {
+                    patterns: vec!["legacy-*".to_string()],
+                    match_on,
+                    ..Default::default()
+                },
+            );
+            (project, result)
+        };
+
+        let (project, result) = apply_with(format!("dep-key-{index}"), MatchOn::DepKey);
+        result.unwrap();
+        let doc: DocumentMut = project.read_manifest().parse().unwrap();
+        let patched: Vec<_> = doc["patch"]["crates-io"]
+            .as_table()
+            .unwrap()
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        assert_eq!(patched, ["rattler-one"]);
+
+        let (_, result) = apply_with(format!("crate-name-{index}"), MatchOn::CrateName);
+        assert!(matches!(
+            result.unwrap_err(),
+            cargo_patch_source::PatchError::NoMatchingCrates { .. }
+        ));
+    }
+}
+
+#[test]
+fn test_apply_writes_patches_to_cargo_config() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let original = project.read_manifest();
+    project.write_file(".cargo/config.toml", "[alias]\nb = \"build\"\n");
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &ApplyOptions {
+            patterns: vec!["rattler-*".to_string()],
+            write_to: PatchLocation::CargoConfig,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let config_path = project.dir().join(".cargo/config.toml");
+    let config = std::fs::read_to_string(&config_path).unwrap();
+    assert_snapshot!(
+        normalize_manifest(&config, Some(&workspace)),
+        @r###"
+[alias]
+b = "build"
+
+[patch]
+
+[patch.crates-io]
+rattler-one = { path = "<workspace>/crates/rattler-one" }
+rattler-two = { path = "<workspace>/crates/rattler-two" }
 "###
     );
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert!(doc.get("patch").is_none());
+    assert_snapshot!(
+        doc["package"]["metadata"]["cargo-patch-source"].to_string(),
+        @r###"
+        original-versions = { rattler-one = "1.0.0", rattler-two = "2.0.0" }
+        managed-patches = ["crates-io"]
+        patch-location = "cargo-config"
+        "###
+    );
+
+    remove_patches(Some(project.manifest_path().to_path_buf())).unwrap();
+    assert_eq!(project.read_manifest(), original);
+    assert_eq!(
+        std::fs::read_to_string(&config_path).unwrap(),
+        "[alias]\nb = \"build\"\n"
+    );
+}
+
+#[test]
+fn test_doctor_reads_patches_from_cargo_config() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &ApplyOptions {
+            patterns: vec!["rattler-*".to_string()],
+            write_to: PatchLocation::CargoConfig,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let output = patch_source_cmd()
+        .arg("doctor")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .arg("--format")
+        .arg("json")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let report: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    for check in report["checks"].as_array().unwrap() {
+        assert_eq!(check["passed"], true, "{}", check);
+    }
+}
+
+#[test]
+fn test_dedupe_patches_in_cargo_config() {
+    let fixture = TestFixture::new();
+    let project = fixture
+        .project("dedupe-config-project")
+        .dep_version("rattler-one", "1.0.0")
+        .build();
+    project.append_manifest(
+        r#"
+[package.metadata.cargo-patch-source]
+original-versions = { rattler-one = "1.0.0" }
+managed-patches = ["crates-io", "https://github.com/org/rattler"]
+patch-location = "cargo-config"
+"#,
+    );
+    project.write_file(
+        ".cargo/config.toml",
+        r#"[patch.crates-io]
+rattler-one = { path = "../rattler/crates/rattler-one" }
+
+[patch."https://github.com/org/rattler"]
+rattler-one = { git = "https://github.com/org/rattler", branch = "fix" }
+"#,
+    );
+
+    patch_source_cmd()
+        .arg("dedupe")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+
+    let config: DocumentMut = std::fs::read_to_string(project.dir().join(".cargo/config.toml"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(config["patch"].get("crates-io").is_none());
+    assert!(config["patch"]["https://github.com/org/rattler"]
+        .get("rattler-one")
+        .is_some());
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert!(doc.get("patch").is_none());
+}
+
+#[test]
+fn test_apply_only_newer_skips_crates_without_a_newer_source() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture
+        .project("only-newer-project")
+        .dep_version("rattler-one", "0.9.0")
+        .dep_version("rattler-two", "2.0.0")
+        .dep_version("other-crate", "3.1.0")
+        .build();
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &ApplyOptions {
+            only_newer: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let patched: Vec<_> = doc["patch"]["crates-io"]
+        .as_table()
+        .unwrap()
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    assert_eq!(patched, ["rattler-one"]);
+    assert_eq!(doc["dependencies"]["rattler-one"].as_str(), Some("1.0.0"));
+    assert_eq!(doc["dependencies"]["rattler-two"].as_str(), Some("2.0.0"));
+    assert_eq!(doc["dependencies"]["other-crate"].as_str(), Some("3.1.0"));
+}
+
+#[test]
+fn test_apply_patches_optional_dependencies() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture.project("optional-project").build();
+    project.write_manifest(
+        r#"[package]
+name = "optional-project"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+rattler-one = { version = "1.0.0", optional = true }
+
+[features]
+one = ["dep:rattler-one"]
+"#,
+    );
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let entry = doc["patch"]["crates-io"]["rattler-one"].to_string();
+    assert_eq!(
+        normalize_manifest(entry.trim(), Some(&workspace)),
+        r#"{ path = "<workspace>/crates/rattler-one" }"#
+    );
+    assert_eq!(
+        doc["dependencies"]["rattler-one"].to_string().trim(),
+        r#"{ version = "1.0.0", optional = true }"#
+    );
+}
+
+#[test]
+fn test_apply_abort_on_dirty() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    project.init_git();
+    let options = ApplyOptions {
+        abort_on_dirty: true,
+        ..Default::default()
+    };
+
+    // A clean manifest is patched, which leaves it dirty
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &options,
+    )
+    .unwrap();
+    let patched = project.read_manifest();
+    assert!(patched.contains("[patch.crates-io]"));
+
+    let err = apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &options,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        cargo_patch_source::PatchError::DirtyManifest { .. }
+    ));
+    assert_eq!(project.read_manifest(), patched);
+}
+
+#[test]
+fn test_remove_with_versions_file_without_metadata() {
+    let fixture = TestFixture::new();
+    let project = fixture
+        .project("lost-metadata")
+        .dep_version("rattler-one", "1.1.0")
+        .dep_version("rattler-two", "2.2.0")
+        .build();
+    project.append_manifest(
+        r#"
+[patch.crates-io]
+rattler-one = { path = "../rattler/crates/rattler-one" }
+rattler-two = { path = "../rattler/crates/rattler-two" }
+serde = { path = "../serde" }
+"#,
+    );
+    project.write_file(
+        "versions.txt",
+        "rattler-one = \"1.0.0\"\nrattler-two = \"2.0.0\"\n",
+    );
+
+    remove_patches_with_options(
+        Some(project.manifest_path().to_path_buf()),
+        &RemoveOptions {
+            versions_from: Some(project.dir().join("versions.txt")),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_snapshot!(project.read_manifest(), @r###"
+    [package]
+    name = "lost-metadata"
+    version = "0.1.0"
+    edition = "2021"
+
+    [dependencies]
+    rattler-one = "1.0.0"
+    rattler-two = "2.0.0"
+
+    [patch.crates-io]
+    serde = { path = "../serde" }
+    "###);
+}
+
+#[test]
+fn test_dedupe_keeps_the_git_patch() {
+    let fixture = TestFixture::new();
+    let project = fixture
+        .project("dedupe-project")
+        .dep_version("rattler-one", "1.0.0")
+        .build();
+    project.append_manifest(
+        r#"
+[package.metadata.cargo-patch-source]
+original-versions = { rattler-one = "1.0.0" }
+managed-patches = ["crates-io", "https://github.com/org/rattler"]
+
+[patch.crates-io]
+rattler-one = { path = "../rattler/crates/rattler-one" }
+
+[patch."https://github.com/org/rattler"]
+rattler-one = { git = "https://github.com/org/rattler", branch = "fix" }
+"#,
+    );
+
+    patch_source_cmd()
+        .arg("dedupe")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+
+    assert_snapshot!(project.read_manifest(), @r###"
+    [package]
+    name = "dedupe-project"
+    version = "0.1.0"
+    edition = "2021"
+
+    [dependencies]
+    rattler-one = "1.0.0"
+
+    [package.metadata.cargo-patch-source]
+    original-versions = { rattler-one = "1.0.0" }
+    managed-patches = ["https://github.com/org/rattler"]
+
+    [patch."https://github.com/org/rattler"]
+    rattler-one = { git = "https://github.com/org/rattler", branch = "fix" }
+    "###);
+}
+
+#[test]
+fn test_find_unused_patches() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let crate_path = |name: &str| {
+        workspace
+            .path()
+            .join("crates")
+            .join(name)
+            .to_string_lossy()
+            .replace('\\', "/")
+    };
+    let project = fixture
+        .project("unused-patch-project")
+        .dep(
+            "rattler-one",
+            DependencySpec::path(crate_path("rattler-one")),
+        )
+        .build();
+    project.append_manifest(&format!(
+        r#"
+[patch.crates-io]
+rattler-one = {{ path = "{}" }}
+rattler-two = {{ path = "{}" }}
+"#,
+        crate_path("rattler-one"),
+        crate_path("rattler-two")
+    ));
+
+    let unused = find_unused_patches(project.manifest_path()).unwrap();
+    assert_eq!(unused, ["rattler-two"]);
+}
+
+#[test]
+fn test_apply_max_crates_rejects_broad_patterns() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let original = project.read_manifest();
+
+    let output = patch_source_cmd()
+        .arg("apply")
+        .arg("--pattern")
+        .arg("*")
+        .arg("--max-crates")
+        .arg("1")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("3 crates would be patched, more than --max-crates 1"));
+    assert_eq!(project.read_manifest(), original);
+}
+
+#[test]
+fn test_workspace_plain_string_dependency_roundtrip() {
+    let fixture = TestFixture::new();
+    let workspace = fixture
+        .workspace("upstream")
+        .member("rattler-one", "1.1.0")
+        .build();
+    let project = fixture.project("target-workspace").build();
+    let original = r#"[workspace]
+members = []
+
+[workspace.dependencies]
+rattler-one = "1.0.0"
+serde = "1.0"
+"#;
+    project.write_manifest(original);
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert_eq!(
+        doc["workspace"]["dependencies"]["rattler-one"].as_str(),
+        Some("1.1.0")
+    );
+    assert_eq!(
+        doc["workspace"]["metadata"]["cargo-patch-source"]["original-versions"]["rattler-one"]
+            .as_str(),
+        Some("1.0.0")
+    );
+
+    remove_patches(Some(project.manifest_path().to_path_buf())).unwrap();
+    assert_eq!(project.read_manifest(), original);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_apply_update_lock_runs_cargo_update() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    // Record `cargo update` calls and forward everything else (cargo metadata) to cargo
+    let log = project.dir().join("cargo-update.log");
+    let wrapper = project.dir().join("cargo-wrapper.sh");
+    std::fs::write(
+        &wrapper,
+        format!(
+            "#!/bin/sh\nif [ \"$1\" = update ]; then echo \"$@\" >> {}; exit 0; fi\nexec \"$REAL_CARGO\" \"$@\"\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&wrapper, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    patch_source_cmd()
+        .env("CARGO", &wrapper)
+        .env("REAL_CARGO", env!("CARGO"))
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--pattern")
+        .arg("rattler-*")
+        .arg("--update-lock")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+
+    assert_eq!(
+        std::fs::read_to_string(&log).unwrap().trim(),
+        format!(
+            "update --manifest-path {} -p rattler-one -p rattler-two",
+            project.manifest_path().display()
+        )
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_apply_update_lock_reports_cargo_failure() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    let wrapper = project.dir().join("cargo-wrapper.sh");
+    std::fs::write(
+        &wrapper,
+        "#!/bin/sh\nif [ \"$1\" = update ]; then echo 'error: no matching package' >&2; exit 101; fi\nexec \"$REAL_CARGO\" \"$@\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&wrapper, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = patch_source_cmd()
+        .env("CARGO", &wrapper)
+        .env("REAL_CARGO", env!("CARGO"))
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--update-lock")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Failed to update Cargo.lock: error: no matching package"));
+
+    // The manifest is still patched
+    assert!(project.read_manifest().contains("[patch.crates-io]"));
+}
+
+#[test]
+fn test_apply_warns_when_manifest_is_a_workspace_member() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture.project("member-workspace").build();
+    project.write_manifest("[workspace]\nmembers = [\"member\"]\nresolver = \"2\"\n");
+    project.write_file(
+        "member/Cargo.toml",
+        r#"[package]
+name = "member"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+rattler-one = "1.0.0"
+"#,
+    );
+    project.write_file("member/src/lib.rs", "");
+    let member_manifest = project.dir().join("member/Cargo.toml");
+
+    let output = patch_source_cmd()
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(&member_manifest)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!(
+        "{} is a member of the workspace at {}; cargo ignores [patch] outside the workspace root",
+        member_manifest.display(),
+        project.dir().display()
+    )));
+
+    // The workspace root itself is not warned about
+    let output = patch_source_cmd()
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("is a member of the workspace"));
+}
+
+#[test]
+fn test_apply_record_checksum_flags_hand_edited_entries() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let apply = || {
+        patch_source_cmd()
+            .arg("apply")
+            .arg("--path")
+            .arg(workspace.path())
+            .arg("--pattern")
+            .arg("rattler-*")
+            .arg("--record-checksum")
+            .arg("--manifest-path")
+            .arg(project.manifest_path())
+            .assert()
+            .success()
+            .get_output()
+            .clone()
+    };
+
+    apply();
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let checksums = &doc["package"]["metadata"]["cargo-patch-source"]["checksums"]["crates-io"];
+    assert!(checksums["rattler-one"].is_str());
+    assert!(checksums["rattler-two"].is_str());
+
+    // Point rattler-one somewhere else by hand
+    let edited = project.read_manifest().replace(
+        &format!("{}/crates/rattler-one", workspace.path().display()),
+        "../my-fork/rattler-one",
+    );
+    project.write_manifest(&edited);
+
+    let output = apply();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(
+        "The [patch.crates-io] entry for rattler-one was edited by hand; keeping it as an unmanaged entry"
+    ));
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert_eq!(
+        doc["patch"]["crates-io"]["rattler-one"]["path"].as_str(),
+        Some("../my-fork/rattler-one")
+    );
+    let metadata = &doc["package"]["metadata"]["cargo-patch-source"];
+    assert!(metadata["original-versions"].get("rattler-one").is_none());
+    assert!(metadata["checksums"]["crates-io"]
+        .get("rattler-one")
+        .is_none());
+    assert!(metadata["checksums"]["crates-io"]["rattler-two"].is_str());
+}
+
+#[test]
+fn test_apply_skips_standard_library_crates() {
+    let fixture = TestFixture::new();
+    let project = fixture
+        .project("sysroot-project")
+        .dep_version("std", "1.0.0")
+        .dep_version("rattler-one", "1.0.0")
+        .build();
+
+    let output = patch_source_cmd()
+        .arg("apply")
+        .arg("--git")
+        .arg("https://github.com/prefix-dev/rattler")
+        .arg("--pattern")
+        .arg("*")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr
+        .contains("Skipping std because standard library crates cannot be patched with [patch]"));
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let patches = doc["patch"]["crates-io"].as_table().unwrap();
+    assert!(patches.contains_key("rattler-one"));
+    assert!(!patches.contains_key("std"));
+}
+
+#[test]
+fn test_apply_from_source_lock() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let options = ApplyOptions {
+        patterns: vec!["rattler-one".to_string()],
+        from_source_lock: true,
+        ..Default::default()
+    };
+
+    let err = apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &options,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        cargo_patch_source::PatchError::SourceLockNotFound { .. }
+    ));
+
+    // The lockfile pins a newer release than the manifest says, e.g. before a version bump
+    std::fs::write(
+        workspace.path().join("Cargo.lock"),
+        r#"version = 3
+
+[[package]]
+name = "rattler-one"
+version = "1.0.3"
+
+[[package]]
+name = "rattler-one"
+version = "0.9.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+    )
+    .unwrap();
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &options,
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert_eq!(doc["dependencies"]["rattler-one"].as_str(), Some("1.0.3"));
+}
+
+#[test]
+fn test_no_color_output_has_no_ansi_codes() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+
+    // FORCE_COLOR would otherwise turn colors on even though stderr is not a terminal
+    let no_color_env = patch_source_cmd()
+        .env("FORCE_COLOR", "1")
+        .env("NO_COLOR", "1")
+        .arg("remove")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .code(3)
+        .get_output()
+        .clone();
+    let no_color_flag = patch_source_cmd()
+        .env("FORCE_COLOR", "1")
+        .arg("--no-color")
+        .arg("remove")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .code(3)
+        .get_output()
+        .clone();
+
+    for output in [no_color_env, no_color_flag] {
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("No patches found to remove"));
+        assert!(!stderr.contains('\x1b'), "{:?}", stderr);
+    }
+}
+
+#[test]
+fn test_malformed_patch_section_is_reported() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    project.append_manifest("\n[patch]\ncrates-io = \"oops\"\n");
+    let manifest_path = project.manifest_path().to_path_buf();
+
+    let err = apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(manifest_path.clone()),
+        Some("rattler-*"),
+    )
+    .unwrap_err();
+    match err {
+        cargo_patch_source::PatchError::MalformedPatchSection { key, .. } => {
+            assert_eq!(key, "patch.crates-io")
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+
+    let err = remove_patches(Some(manifest_path)).unwrap_err();
+    assert!(matches!(
+        err,
+        cargo_patch_source::PatchError::MalformedPatchSection { .. }
+    ));
+    assert!(project.read_manifest().contains("crates-io = \"oops\""));
+}
+
+#[test]
+fn test_apply_filtered_by_license() {
+    let fixture = TestFixture::new();
+    let workspace = fixture
+        .workspace("mock-workspace")
+        .member("rattler-one", "1.1.0")
+        .license("MIT OR Apache-2.0")
+        .member("rattler-two", "2.1.0")
+        .license("GPL-3.0-only")
+        .member("other-crate", "3.1.0")
+        .build();
+    let project = rattler_project(&fixture);
+    let options = ApplyOptions {
+        license: Some("Apache-2.0".to_string()),
+        ..Default::default()
+    };
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &options,
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let patched: Vec<_> = doc["patch"]["crates-io"]
+        .as_table()
+        .unwrap()
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    assert_eq!(patched, ["rattler-one"]);
+    assert_eq!(doc["dependencies"]["rattler-two"].as_str(), Some("2.0.0"));
+}
+
+#[test]
+fn test_apply_workspace_dependencies_only() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture.project("root-project").build();
+    let options = ApplyOptions {
+        workspace_dependencies_only: true,
+        ..Default::default()
+    };
+    let apply = || {
+        apply_patches_with_options(
+            PatchSource::local_path(workspace.path().to_path_buf()),
+            Some(project.manifest_path().to_path_buf()),
+            &options,
+        )
+        .unwrap()
+    };
+
+    project.write_manifest(
+        r#"[package]
+name = "root-project"
+version = "0.1.0"
+edition = "2021"
+
+[workspace]
+
+[workspace.dependencies]
+rattler-one = "1.0.0"
+
+[dependencies]
+rattler-two = "2.0.0"
+"#,
+    );
+    apply();
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let patched: Vec<_> = doc["patch"]["crates-io"]
+        .as_table()
+        .unwrap()
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    assert_eq!(patched, ["rattler-one"]);
+
+    // Without [workspace.dependencies] there is nothing to patch, not a fall back
+    let without_workspace_deps = r#"[package]
+name = "root-project"
+version = "0.1.0"
+edition = "2021"
+
+[workspace]
+
+[dependencies]
+rattler-two = "2.0.0"
+"#;
+    project.write_manifest(without_workspace_deps);
+    let plan = apply();
+    assert_eq!(plan.patched, 0);
+    assert_eq!(project.read_manifest(), without_workspace_deps);
+}
+
+#[test]
+fn test_apply_selects_package_spec_with_version() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    let output = patch_source_cmd()
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .arg("-p")
+        .arg("rattler-one@2.0.0")
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert_eq!(output.status.code(), Some(2));
+
+    patch_source_cmd()
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .arg("-p")
+        .arg("rattler-one@1.0.0")
+        .assert()
+        .success();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let patched: Vec<_> = doc["patch"]["crates-io"]
+        .as_table()
+        .unwrap()
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    assert_eq!(patched, ["rattler-one"]);
+}
+
+#[test]
+fn test_apply_skipping_everything_leaves_no_empty_patch_table() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    // A table left behind after deleting its last entry by hand
+    project.append_manifest("\n[patch.crates-io]\n");
+    let options = ApplyOptions {
+        only_newer: true,
+        ..Default::default()
+    };
+
+    let plan = apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &options,
+    )
+    .unwrap();
+
+    assert_eq!(plan.patched, 0);
+    assert!(!project.read_manifest().contains("[patch"));
+}
+
+#[test]
+fn test_apply_relative_path_is_relative_to_the_manifest() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    // From the CWD, `../mock-workspace` would point at `mock-workspace/mock-workspace`
+    patch_source_cmd()
+        .current_dir(workspace.path().join("crates"))
+        .arg("apply")
+        .arg("--path")
+        .arg("../mock-workspace")
+        .arg("--pattern")
+        .arg("rattler-one")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let entry = doc["patch"]["crates-io"]["rattler-one"].to_string();
+    assert_eq!(
+        normalize_manifest(entry.trim(), Some(&workspace)),
+        r#"{ path = "<workspace>/crates/rattler-one" }"#
+    );
+}
+
+#[test]
+fn test_apply_scans_a_custom_section() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture
+        .project("my-project")
+        .dep_version("rattler-one", "0.9.0")
+        .build();
+    project.append_manifest(
+        r#"
+[package.metadata.tool-deps]
+rattler-two = "1.9.0"
+"#,
+    );
+
+    patch_source_cmd()
+        .args(["apply", "--section", "package.metadata.tool-deps", "--path"])
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+
+    let manifest = normalize_manifest(&project.read_manifest(), Some(&workspace));
+    assert_snapshot!(manifest, @r###"
+[package]
+name = "my-project"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+rattler-one = "1.0.0"
+
+[patch]
+
+[patch.crates-io]
+rattler-one = { path = "<workspace>/crates/rattler-one" }
+rattler-two = { path = "<workspace>/crates/rattler-two" }
+
+[package.metadata.tool-deps]
+rattler-two = "2.0.0"
+
+[package.metadata.cargo-patch-source]
+dependency-sections = ["package.metadata.tool-deps"]
+original-versions = { rattler-one = "0.9.0", rattler-two = "1.9.0" }
+managed-patches = ["crates-io"]
+"###);
+
+    // The recorded section is restored without passing --section again
+    patch_source_cmd()
+        .args(["remove", "--manifest-path"])
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+    assert_snapshot!(project.read_manifest(), @r###"
+[package]
+name = "my-project"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+rattler-one = "0.9.0"
+
+[package.metadata.tool-deps]
+rattler-two = "1.9.0"
+"###);
+}
+
+#[test]
+fn test_apply_reports_matched_source_crates_that_are_not_dependencies() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture
+        .project("my-project")
+        .dep_version("rattler-one", "1.0.0")
+        .build();
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .args(["apply", "--pattern", "rattler-*", "--path"])
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Matched in the source but not a dependency: rattler-two\n"));
+    assert!(!stderr.contains("other-crate"));
+}
+
+#[test]
+fn test_apply_relative_to_workspace_root() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture.project("target-ws").build();
+    project.write_manifest(
+        r#"[workspace]
+members = ["app"]
+"#,
+    );
+    project.write_file(
+        "app/Cargo.toml",
+        r#"[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+rattler-one = "1.0.0"
+"#,
+    );
+    project.write_file("app/src/lib.rs", "");
+    let member_manifest = project.dir().join("app/Cargo.toml");
+
+    let apply = |relative_to: &str| {
+        patch_source_cmd()
+            .args(["apply", "--relative-to", relative_to, "--path"])
+            .arg(workspace.path())
+            .arg("--manifest-path")
+            .arg(&member_manifest)
+            .assert()
+            .success();
+        let manifest = std::fs::read_to_string(&member_manifest).unwrap();
+        let doc: DocumentMut = manifest.parse().unwrap();
+        doc["patch"]["crates-io"]["rattler-one"]["path"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+
+    assert_eq!(apply("workspace"), "../mock-workspace/crates/rattler-one");
+    assert_eq!(apply("manifest"), "../../mock-workspace/crates/rattler-one");
+}
+
+#[test]
+fn test_apply_with_name_map() {
+    let fixture = TestFixture::new();
+    let workspace = fixture
+        .workspace("renamed-upstream")
+        .member("rattler_conda_types", "1.2.0")
+        .build();
+    let project = fixture
+        .project("name-map-project")
+        .dep_version("rattler-conda-types-legacy", "1.0.0")
+        .build();
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &ApplyOptions {
+            name_map: std::collections::HashMap::from([(
+                "rattler_conda_types".to_string(),
+                "rattler-conda-types-legacy".to_string(),
+            )]),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert_eq!(
+        doc["dependencies"]["rattler-conda-types-legacy"].as_str(),
+        Some("1.2.0")
+    );
+    let entry = doc["patch"]["crates-io"]["rattler-conda-types-legacy"].to_string();
+    assert_eq!(
+        normalize_manifest(entry.trim(), Some(&workspace)),
+        r#"{ path = "<workspace>/crates/rattler_conda_types", package = "rattler_conda_types" }"#
+    );
+}
+
+#[test]
+fn test_patch_git_dependencies_without_version() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture
+        .project("git-deps-project")
+        .dep(
+            "rattler-one",
+            DependencySpec::git("https://github.com/prefix-dev/rattler").tag("v1.0.0"),
+        )
+        .dep(
+            "rattler-two",
+            DependencySpec::git("https://github.com/prefix-dev/rattler").tag("v1.0.0"),
+        )
+        .dep(
+            "other-crate",
+            DependencySpec::git("https://github.com/prefix-dev/rattler").tag("v1.0.0"),
+        )
+        .build();
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+
+    let content = project.read_manifest();
+    let normalized = normalize_manifest(&content, Some(&workspace));
+    assert_snapshot!(
+        normalized.as_str(),
+        @r###"
+[package]
+name = "git-deps-project"
+version = "0.1.0"
+edition = "2021"
+
+[package.metadata]
+
+[package.metadata.cargo-patch-source]
+original-versions = { other-crate = "", rattler-one = "", rattler-two = "" }
+managed-patches = ["https://github.com/prefix-dev/rattler"]
+git-ref = { tag = "v1.0.0" }
+
+[dependencies]
+other-crate = { git = "https://github.com/prefix-dev/rattler", tag = "v1.0.0" }
+rattler-one = { git = "https://github.com/prefix-dev/rattler", tag = "v1.0.0" }
+rattler-two = { git = "https://github.com/prefix-dev/rattler", tag = "v1.0.0" }
+
+[patch]
+
+[patch."https://github.com/prefix-dev/rattler"]
+other-crate = { path = "<workspace>/crates/other-crate" }
+rattler-one = { path = "<workspace>/crates/rattler-one" }
+rattler-two = { path = "<workspace>/crates/rattler-two" }
+"###
+    );
+}
+
+#[test]
+fn test_remove_dry_run_reports_plan_without_writing() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+
+    let content_before = project.read_manifest();
+
+    let plan = remove_patches_with_options(
+        Some(project.manifest_path().to_path_buf()),
+        &RemoveOptions {
+            dry_run: true,
+            format: OutputFormat::Json,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(project.read_manifest(), content_before);
+
+    assert_snapshot!(plan.to_json().unwrap(), @r###"
+    {
+      "schema_version": 1,
+      "dry_run": true,
+      "restored_versions": [
+        {
+          "name": "rattler-one",
+          "version": "1.0.0"
+        },
+        {
+          "name": "rattler-two",
+          "version": "2.0.0"
+        }
+      ],
+      "removed_patches": [
+        {
+          "patch_key": "crates-io",
+          "name": "rattler-one"
+        },
+        {
+          "patch_key": "crates-io",
+          "name": "rattler-two"
+        }
+      ]
+    }
+    "###);
+}
+
+#[test]
+fn test_apply_and_remove_dry_run_leave_the_manifest_unchanged() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let before = std::fs::read(project.manifest_path()).unwrap();
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .args(["apply", "--dry-run", "--pattern", "rattler-*", "--path"])
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert_eq!(std::fs::read(project.manifest_path()).unwrap(), before);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Patching rattler-one 1.0.0"));
+    assert!(stderr.contains(
+        "Dry run: would patch 2 crates under [patch.crates-io] (0 skipped), no changes written"
+    ));
+
+    // Errors surface exactly as in a real run
+    patch_source_cmd()
+        .args(["apply", "--dry-run", "--pattern", "missing-*", "--path"])
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .code(2);
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+    let patched = std::fs::read(project.manifest_path()).unwrap();
+    patch_source_cmd()
+        .args(["remove", "--dry-run", "--manifest-path"])
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(project.manifest_path()).unwrap(), patched);
+}
+
+#[test]
+fn test_remove_dry_run_human_output() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+    let content_before = project.read_manifest();
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .arg("remove")
+        .arg("--dry-run")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    assert_eq!(project.read_manifest(), content_before);
+    let stderr = String::from_utf8(output.stderr).unwrap().replace(
+        &project.manifest_path().display().to_string(),
+        "<project>/Cargo.toml",
+    );
+    assert_snapshot!(stderr, @r###"
+      Would restore rattler-one to 1.0.0
+      Would restore rattler-two to 2.0.0
+      Would remove [patch.crates-io] rattler-one
+      Would remove [patch.crates-io] rattler-two
+    Dry run: no changes written to <project>/Cargo.toml
+    "###);
+}
+
+#[test]
+fn test_list_grouped_by_source() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+    project.append_manifest(
+        r#"
+[package.metadata.cargo-patch-source]
+original-versions = { other-crate = "3.0.0", rattler-one = "1.0.0", rattler-two = "2.0.0" }
+managed-patches = ["crates-io", "https://github.com/org/rattler"]
+
+[patch.crates-io]
+rattler-two = { path = "../rattler/crates/rattler-two" }
+rattler-one = { path = "../rattler/crates/rattler-one" }
+
+[patch."https://github.com/org/rattler"]
+other-crate = { git = "https://github.com/fork/rattler", branch = "fix" }
+"#,
+    );
+    let list = |group_by_source: bool| {
+        // The listing is the command's output, so --quiet doesn't hide it
+        let mut cmd = patch_source_cmd();
+        cmd.env_remove("RUST_LOG").args(["--quiet", "list"]);
+        if group_by_source {
+            cmd.arg("--group-by-source");
+        }
+        let output = cmd
+            .arg("--manifest-path")
+            .arg(project.manifest_path())
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_snapshot!(list(false), @r###"
+      other-crate [patch.https://github.com/org/rattler] { git = "https://github.com/fork/rattler", branch = "fix" }
+      rattler-one [patch.crates-io] { path = "../rattler/crates/rattler-one" }
+      rattler-two [patch.crates-io] { path = "../rattler/crates/rattler-two" }
+    "###);
+    assert_snapshot!(list(true), @r###"
+    [patch.crates-io]
+      rattler-one = { path = "../rattler/crates/rattler-one" }
+      rattler-two = { path = "../rattler/crates/rattler-two" }
+    [patch.https://github.com/org/rattler]
+      other-crate = { git = "https://github.com/fork/rattler", branch = "fix" }
+    "###);
+}
+
+#[test]
+fn test_remove_keep_empty_metadata() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let manifest_path = project.manifest_path().to_path_buf();
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(manifest_path.clone()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+    remove_patches_with_options(
+        Some(manifest_path),
+        &RemoveOptions {
+            keep_empty_metadata: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_snapshot!(project.read_manifest(), @r###"
+    [package]
+    name = "target-project"
+    version = "0.1.0"
+    edition = "2021"
+
+    [package.metadata]
+
+    [package.metadata.cargo-patch-source]
+
+    [dependencies]
+    other-crate = "3.0.0"
+    rattler-one = "1.0.0"
+    rattler-two = "2.0.0"
+    "###);
+}
+
+#[test]
+fn test_remove_all_keys_purges_untracked_keys() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+    // `managed-patches` lost the git key, but rattler-two is still ours
+    project.append_manifest(
+        r#"
+[package.metadata.cargo-patch-source]
+original-versions = { rattler-one = "0.9.0", rattler-two = "1.9.0" }
+managed-patches = ["crates-io"]
+
+[patch.crates-io]
+rattler-one = { path = "../rattler/crates/rattler-one" }
+unrelated = { path = "../unrelated" }
+
+[patch."https://github.com/org/two"]
+rattler-two = { git = "https://github.com/fork/two" }
+"#,
+    );
+
+    let plan = remove_patches_with_options(
+        Some(project.manifest_path().to_path_buf()),
+        &RemoveOptions {
+            all_keys: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(plan.removed_patches.len(), 2);
+
+    assert_snapshot!(project.read_manifest(), @r###"
+[package]
+name = "target-project"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+other-crate = "3.0.0"
+rattler-one = "0.9.0"
+rattler-two = "1.9.0"
+
+[patch.crates-io]
+unrelated = { path = "../unrelated" }
+"###);
+}
+
+#[test]
+fn test_remove_all_keys_from_inline_patch_tables() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+    project.append_manifest(
+        r#"
+[package.metadata.cargo-patch-source]
+original-versions = { rattler-one = "0.9.0", rattler-two = "1.9.0" }
+managed-patches = ["crates-io"]
+
+[patch]
+crates-io = { rattler-one = { path = "../rattler/crates/rattler-one" }, unrelated = { path = "../unrelated" } }
+"https://github.com/org/two" = { rattler-two = { git = "https://github.com/fork/two" } }
+"#,
+    );
+
+    let plan = remove_patches_with_options(
+        Some(project.manifest_path().to_path_buf()),
+        &RemoveOptions {
+            all_keys: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(plan.removed_patches.len(), 2);
+
+    assert_snapshot!(project.read_manifest(), @r###"
+[package]
+name = "target-project"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+other-crate = "3.0.0"
+rattler-one = "0.9.0"
+rattler-two = "1.9.0"
+
+[patch]
+crates-io = { unrelated = { path = "../unrelated" } }
+"###);
+}
+
+#[test]
+fn test_remove_by_key_pattern() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+    project.append_manifest(
+        r#"
+[package.metadata.cargo-patch-source]
+original-versions = { other-crate = "3.0.0", rattler-one = "0.9.0", rattler-two = "1.9.0" }
+managed-patches = ["https://github.com/org/one", "https://github.com/org/two", "https://gitlab.com/other/repo"]
+
+[patch."https://github.com/org/one"]
+rattler-one = { git = "https://github.com/fork/one" }
+
+[patch."https://github.com/org/two"]
+rattler-two = { git = "https://github.com/fork/two" }
+
+[patch."https://gitlab.com/other/repo"]
+other-crate = { git = "https://gitlab.com/fork/repo" }
+"#,
+    );
+
+    let plan = remove_patches_with_options(
+        Some(project.manifest_path().to_path_buf()),
+        &RemoveOptions {
+            key_pattern: Some("https://github.com/org/*".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(plan.removed_patches.len(), 2);
+
+    assert_snapshot!(project.read_manifest(), @r###"
+[package]
+name = "target-project"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+other-crate = "3.0.0"
+rattler-one = "0.9.0"
+rattler-two = "1.9.0"
+
+[package.metadata.cargo-patch-source]
+original-versions = { other-crate = "3.0.0" }
+managed-patches = ["https://gitlab.com/other/repo"]
+
+[patch."https://gitlab.com/other/repo"]
+other-crate = { git = "https://gitlab.com/fork/repo" }
+"###);
+
+    remove_patches(Some(project.manifest_path().to_path_buf())).unwrap();
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert!(doc.get("patch").is_none());
+}
+
+#[test]
+fn test_remove_only_some_crates() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+    project.append_manifest(
+        r#"
+[package.metadata.cargo-patch-source]
+original-versions = { other-crate = "2.9.0", rattler-one = "0.9.0", rattler-two = "1.9.0" }
+managed-patches = ["crates-io"]
+
+[patch.crates-io]
+other-crate = { path = "../rattler/crates/other-crate" }
+rattler-one = { path = "../rattler/crates/rattler-one" }
+rattler-two = { path = "../rattler/crates/rattler-two" }
+"#,
+    );
+
+    patch_source_cmd()
+        .arg("remove")
+        .arg("--only")
+        .arg("rattler-one")
+        .arg("--only")
+        .arg("other-crate")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+
+    assert_snapshot!(project.read_manifest(), @r###"
+[package]
+name = "target-project"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+other-crate = "2.9.0"
+rattler-one = "0.9.0"
+rattler-two = "2.0.0"
+
+[package.metadata.cargo-patch-source]
+original-versions = { rattler-two = "1.9.0" }
+managed-patches = ["crates-io"]
+
+[patch.crates-io]
+rattler-two = { path = "../rattler/crates/rattler-two" }
+"###);
+
+    // Naming a crate that is not managed is an error
+    patch_source_cmd()
+        .arg("remove")
+        .arg("--only")
+        .arg("serde")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .code(3);
+
+    remove_patches(Some(project.manifest_path().to_path_buf())).unwrap();
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert!(doc.get("patch").is_none());
+    assert_eq!(doc["dependencies"]["rattler-two"].as_str(), Some("1.9.0"));
+}
+
+#[test]
+fn test_remove_workspace_members_matching_pattern() {
+    let fixture = TestFixture::new();
+    let project = fixture.project("patched-workspace").build();
+    project.write_manifest(
+        "[workspace]\nmembers = [\"rattler-app\", \"rattler-cli\", \"tool\"]\nresolver = \"2\"\n",
+    );
+    let patched_member = |name: &str| {
+        format!(
+            r#"[package]
+name = "{name}"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+rattler-one = "1.1.0"
+
+[package.metadata.cargo-patch-source]
+original-versions = {{ rattler-one = "1.0.0" }}
+managed-patches = ["crates-io"]
+
+[patch.crates-io]
+rattler-one = {{ path = "../../rattler/crates/rattler-one" }}
+"#
+        )
+    };
+    for member in ["rattler-app", "rattler-cli", "tool"] {
+        project.write_file(format!("{member}/Cargo.toml"), &patched_member(member));
+        project.write_file(format!("{member}/src/lib.rs"), "");
+    }
+
+    patch_source_cmd()
+        .arg("remove")
+        .arg("--workspace")
+        .arg("--member-pattern")
+        .arg("rattler-*")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+
+    for member in ["rattler-app", "rattler-cli"] {
+        let manifest =
+            std::fs::read_to_string(project.dir().join(member).join("Cargo.toml")).unwrap();
+        assert!(!manifest.contains("[patch"), "{}", manifest);
+        assert!(manifest.contains("rattler-one = \"1.0.0\""));
+    }
+    assert_eq!(
+        std::fs::read_to_string(project.dir().join("tool/Cargo.toml")).unwrap(),
+        patched_member("tool")
+    );
+
+    // Nothing left to remove among the matching members
+    patch_source_cmd()
+        .arg("remove")
+        .arg("--workspace")
+        .arg("--member-pattern")
+        .arg("rattler-*")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn test_apply_patch_path_is_package_root_for_unusual_layouts() {
+    let fixture = TestFixture::new();
+    let source = fixture.project("odd-layout").build();
+    source.write_manifest("[workspace]\nmembers = [\"packages/rattler-one\"]\nresolver = \"2\"\n");
+    source.write_file(
+        "packages/rattler-one/Cargo.toml",
+        r#"[package]
+name = "rattler-one"
+version = "1.1.0"
+edition = "2021"
+build = "scripts/build.rs"
+autobins = false
+
+[lib]
+path = "code/lib.rs"
+
+[[bin]]
+name = "rattler-one-cli"
+path = "tools/cli/main.rs"
+"#,
+    );
+    source.write_file("packages/rattler-one/code/lib.rs", "");
+    source.write_file("packages/rattler-one/tools/cli/main.rs", "fn main() {}\n");
+    source.write_file("packages/rattler-one/scripts/build.rs", "fn main() {}\n");
+    let project = rattler_project(&fixture);
+
+    apply_patches(
+        PatchSource::local_path(source.dir().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-one"),
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let patch_path = doc["patch"]["crates-io"]["rattler-one"]["path"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(
+        std::fs::canonicalize(patch_path).unwrap(),
+        std::fs::canonicalize(source.dir().join("packages/rattler-one")).unwrap()
+    );
+}
+
+#[test]
+fn test_apply_skips_path_dependencies() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture
+        .project("path-deps-project")
+        .dep_version("rattler-one", "1.0.0")
+        .dep("rattler-two", DependencySpec::path("../vendor/rattler-two"))
+        .build();
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+
+    let content = project.read_manifest();
+    let normalized = normalize_manifest(&content, Some(&workspace));
+    assert_snapshot!(
+        normalized.as_str(),
+        @r###"
+[package]
+name = "path-deps-project"
+version = "0.1.0"
+edition = "2021"
+
+[package.metadata]
+
+[package.metadata.cargo-patch-source]
+original-versions = { rattler-one = "1.0.0" }
+managed-patches = ["crates-io"]
+
+[dependencies]
+rattler-one = "1.0.0"
+rattler-two = { path = "../vendor/rattler-two" }
+
+[patch]
+
+[patch.crates-io]
+rattler-one = { path = "<workspace>/crates/rattler-one" }
+"###
+    );
+}
+
+#[test]
+fn test_apply_registry_from_dep_uses_index_url() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture
+        .project("alt-registry-project")
+        .dep(
+            "rattler-one",
+            DependencySpec::version("1.0.0").registry("my-registry"),
+        )
+        .dep(
+            "rattler-two",
+            DependencySpec::version("2.0.0").registry("my-registry"),
+        )
+        .build();
+    project.write_file(
+        ".cargo/config.toml",
+        r#"
+[registries.my-registry]
+index = "sparse+https://registry.example.com/index/"
+"#,
+    );
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &ApplyOptions {
+            registry_from_dep: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let content = project.read_manifest();
+    let normalized = normalize_manifest(&content, Some(&workspace));
+    assert_snapshot!(
+        normalized.as_str(),
+        @r###"
+[package]
+name = "alt-registry-project"
+version = "0.1.0"
+edition = "2021"
+
+[package.metadata]
+
+[package.metadata.cargo-patch-source]
+original-versions = { rattler-one = "1.0.0", rattler-two = "2.0.0" }
+managed-patches = ["sparse+https://registry.example.com/index/"]
+
+[dependencies]
+rattler-one = { version = "1.0.0", registry = "my-registry" }
+rattler-two = { version = "2.0.0", registry = "my-registry" }
+
+[patch]
+
+[patch."sparse+https://registry.example.com/index/"]
+rattler-one = { path = "<workspace>/crates/rattler-one" }
+rattler-two = { path = "<workspace>/crates/rattler-two" }
+"###
+    );
+}
+
+#[test]
+fn test_apply_patches_renamed_dependencies_once() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture
+        .project("renamed-project")
+        .dep(
+            "rattler-current",
+            DependencySpec::version("1.0.0").package("rattler-one"),
+        )
+        .dep(
+            "rattler-legacy",
+            DependencySpec::version("0.9.0").package("rattler-one"),
+        )
+        .build();
+    let original = project.read_manifest();
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+
+    let content = project.read_manifest();
+    let normalized = normalize_manifest(&content, Some(&workspace));
+    assert_snapshot!(
+        normalized.as_str(),
+        @r###"
+[package]
+name = "renamed-project"
+version = "0.1.0"
+edition = "2021"
+
+[package.metadata]
+
+[package.metadata.cargo-patch-source]
+original-versions = { rattler-current = "1.0.0", rattler-legacy = "0.9.0" }
+managed-patches = ["crates-io"]
+
+[dependencies]
+rattler-current = { package = "rattler-one", version = "1.0.0" }
+rattler-legacy = { package = "rattler-one", version = "1.0.0" }
+
+[patch]
+
+[patch.crates-io]
+rattler-one = { path = "<workspace>/crates/rattler-one" }
+"###
+    );
+
+    remove_patches(Some(project.manifest_path().to_path_buf())).unwrap();
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert_eq!(
+        doc["dependencies"].to_string(),
+        original.parse::<DocumentMut>().unwrap()["dependencies"].to_string()
+    );
+    assert!(doc.get("patch").is_none());
+}
+
+#[test]
+fn test_apply_uses_registry_index_as_patch_key() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture
+        .project("registry-index-project")
+        .dep(
+            "rattler-one",
+            DependencySpec::version("1.0.0")
+                .registry_index("https://registry.example.com/git-index"),
+        )
+        .dep(
+            "rattler-two",
+            DependencySpec::version("2.0.0")
+                .registry_index("https://registry.example.com/git-index"),
+        )
+        .build();
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+
+    let content = project.read_manifest();
+    let normalized = normalize_manifest(&content, Some(&workspace));
+    assert_snapshot!(
+        normalized.as_str(),
+        @r###"
+[package]
+name = "registry-index-project"
+version = "0.1.0"
+edition = "2021"
+
+[package.metadata]
+
+[package.metadata.cargo-patch-source]
+original-versions = { rattler-one = "1.0.0", rattler-two = "2.0.0" }
+managed-patches = ["https://registry.example.com/git-index"]
+
+[dependencies]
+rattler-one = { version = "1.0.0", registry-index = "https://registry.example.com/git-index" }
+rattler-two = { version = "2.0.0", registry-index = "https://registry.example.com/git-index" }
+
+[patch]
+
+[patch."https://registry.example.com/git-index"]
+rattler-one = { path = "<workspace>/crates/rattler-one" }
+rattler-two = { path = "<workspace>/crates/rattler-two" }
+"###
+    );
+}
+
+#[test]
+fn test_apply_with_prefix() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    patch_source_cmd()
+        .args(["apply", "--prefix", "rattler", "--path"])
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let patched: Vec<_> = doc["patch"]["crates-io"]
+        .as_table()
+        .unwrap()
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    assert_eq!(patched, ["rattler-one", "rattler-two"]);
+
+    // Without a wildcard the pattern only matches the exact name
+    patch_source_cmd()
+        .args(["apply", "--pattern", "rattler", "--path"])
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_apply_recursive_discovers_nested_workspaces() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    fixture
+        .workspace("mock-workspace/nested")
+        .member("inner-crate", "0.5.0")
+        .build();
+    let project = fixture
+        .project("target-project")
+        .dep_version("rattler-one", "1.0.0")
+        .dep_version("inner-crate", "0.5.0")
+        .build();
+    let patched = |project: &Project| -> Vec<String> {
+        let doc: DocumentMut = project.read_manifest().parse().unwrap();
+        doc["patch"]["crates-io"]
+            .as_table()
+            .unwrap()
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect()
+    };
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+    assert_eq!(patched(&project), ["rattler-one"]);
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &ApplyOptions {
+            recursive: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(patched(&project), ["inner-crate", "rattler-one"]);
+    assert!(project
+        .read_manifest()
+        .contains("mock-workspace/nested/crates/inner-crate"));
+}
+
+#[test]
+fn test_apply_from_registry_cache_crate() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+    let crate_dir = "cargo-home/registry/src/index.crates.io-1949cf8c6b5b557f/rattler-one-1.0.0";
+    project.write_file(
+        format!("{}/Cargo.toml", crate_dir),
+        "[package]\nname = \"rattler-one\"\nversion = \"1.0.0\"\nedition = \"2021\"\n",
+    );
+    project.write_file(format!("{}/src/lib.rs", crate_dir), "");
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .arg("-q")
+        .arg("apply")
+        .arg("--path")
+        .arg(project.dir().join(crate_dir))
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("in cargo's registry cache; edits there are volatile"));
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let patches = doc["patch"]["crates-io"].as_table().unwrap();
+    assert_eq!(patches.len(), 1);
+    assert!(patches["rattler-one"]["path"]
+        .as_str()
+        .unwrap()
+        .ends_with("rattler-one-1.0.0"));
+}
+
+#[test]
+fn test_apply_writes_output_diff_file() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let diff_path = project.dir().join("patch.diff");
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &ApplyOptions {
+            patterns: vec!["rattler-one".to_string()],
+            output_diff_file: Some(diff_path.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let diff = std::fs::read_to_string(&diff_path).unwrap();
+    let diff = normalize_manifest(&diff, Some(&workspace));
+    assert!(diff.starts_with(&format!("--- a/{}\n", project.manifest_path().display())));
+    assert!(diff.contains("\n+[patch.crates-io]\n"));
+    assert!(diff.contains("\n+rattler-one = { path = \"<workspace>/crates/rattler-one\" }\n"));
+    assert!(diff.contains("\n+managed-patches = [\"crates-io\"]\n"));
+    assert!(!diff.contains("+rattler-two"));
+}
+
+#[test]
+fn test_apply_warns_about_out_of_tree_patch_paths() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .arg("-q")
+        .arg("apply")
+        .arg("--pattern")
+        .arg("rattler-one")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr)
+        .unwrap()
+        .replace(&project.dir().to_string_lossy().to_string(), "<project>");
+    assert_snapshot!(
+        stderr,
+        @"warning: Patch paths for rattler-one point outside <project>; they won't work for other contributors"
+    );
+
+    // --strict turns the warning into an error and leaves the manifest alone
+    let err = apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &ApplyOptions {
+            strict: true,
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        cargo_patch_source::PatchError::PatchPathsOutsideProject { .. }
+    ));
+}
+
+#[test]
+fn test_apply_manifest_path_globs() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let projects: Vec<_> = ["glob-a", "glob-b"]
+        .into_iter()
+        .map(|name| {
+            fixture
+                .project(name)
+                .dep_version("rattler-one", "1.0.0")
+                .build()
+        })
+        .collect();
+    let root = projects[0].dir().parent().unwrap();
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(root.join("missing-*/Cargo.toml"))
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!(
+        "No manifests matched glob {}",
+        root.join("missing-*/Cargo.toml").display()
+    )));
+
+    patch_source_cmd()
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(root.join("glob-?/Cargo.toml"))
+        .assert()
+        .success();
+    for project in &projects {
+        assert!(project.read_manifest().contains("[patch.crates-io]"));
+    }
+}
+
+#[test]
+fn test_fail_on_warning_accepts_relative_patch_paths() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    // Relative paths work for other contributors, so nothing is out of tree
+    patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .args([
+            "--fail-on-warning",
+            "apply",
+            "--relative-to",
+            "manifest",
+            "--path",
+        ])
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+    assert!(project.read_manifest().contains("[patch.crates-io]"));
+}
+
+#[test]
+fn test_fail_on_warning_exits_nonzero_after_the_run() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture
+        .project("my-project")
+        .dep_version("rattler-one", "1.0.0")
+        .build();
+
+    // The sibling workspace triggers the "outside the project" warning
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .args(["--fail-on-warning", "apply", "--path"])
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .code(6)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("point outside"));
+    assert!(stderr.contains("1 warnings were emitted"));
+
+    // The run itself still completed
+    assert!(project.read_manifest().contains("[patch.crates-io]"));
+
+    // Commands that don't warn are unaffected
+    patch_source_cmd()
+        .args(["--fail-on-warning", "list", "--manifest-path"])
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_apply_to_several_manifests_concurrently() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let projects: Vec<_> = ["project-a", "project-b", "project-c"]
+        .into_iter()
+        .map(|name| {
+            fixture
+                .project(name)
+                .dep_version("rattler-one", "1.0.0")
+                .build()
+        })
+        .collect();
+
+    let mut cmd = patch_source_cmd();
+    cmd.env_remove("RUST_LOG")
+        .args(["apply", "--concurrency", "3", "--path"])
+        .arg(workspace.path());
+    for project in &projects {
+        cmd.arg("--manifest-path").arg(project.manifest_path());
+    }
+    let output = cmd.assert().success().get_output().clone();
+
+    // Output is grouped per manifest in the order given, however the work was scheduled
+    let mut stderr =
+        normalize_manifest(&String::from_utf8(output.stderr).unwrap(), Some(&workspace));
+    for (project, name) in projects.iter().zip(["<a>", "<b>", "<c>"]) {
+        stderr = stderr.replace(&project.dir().to_string_lossy().to_string(), name);
+    }
+    let stderr = normalize_elapsed(&stderr);
+    assert_snapshot!(stderr, @r###"
+    warning: Patch paths for rattler-one point outside <a>; they won't work for other contributors
+      Patching rattler-one 1.0.0 -> <workspace>/crates/rattler-one
+    Patched 1 crate under [patch.crates-io] (0 skipped) in <elapsed>.
+    warning: Patch paths for rattler-one point outside <b>; they won't work for other contributors
+      Patching rattler-one 1.0.0 -> <workspace>/crates/rattler-one
+    Patched 1 crate under [patch.crates-io] (0 skipped) in <elapsed>.
+    warning: Patch paths for rattler-one point outside <c>; they won't work for other contributors
+      Patching rattler-one 1.0.0 -> <workspace>/crates/rattler-one
+    Patched 1 crate under [patch.crates-io] (0 skipped) in <elapsed>.
+    "###);
+
+    for project in &projects {
+        let doc: DocumentMut = project.read_manifest().parse().unwrap();
+        assert!(doc["patch"]["crates-io"].get("rattler-one").is_some());
+    }
+}
+
+#[test]
+fn test_apply_json_lines_prints_one_report_per_manifest() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    // The first manifest has more to patch, so it tends to finish last
+    let first = rattler_project(&fixture);
+    let second = fixture
+        .project("project-b")
+        .dep_version("rattler-two", "2.0.0")
+        .build();
+
+    let output = patch_source_cmd()
+        .args([
+            "apply",
+            "--format",
+            "json-lines",
+            "--concurrency",
+            "2",
+            "--path",
+        ])
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(first.manifest_path())
+        .arg("--manifest-path")
+        .arg(second.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let lines: Vec<_> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect();
+    let patched: Vec<_> = lines
+        .iter()
+        .map(|report| {
+            assert_eq!(report["schema_version"], 1);
+            report["patched"].as_u64().unwrap()
+        })
+        .collect();
+    // One line per manifest, in the order the manifests were given
+    assert_eq!(patched, [3, 1]);
+    assert_eq!(lines[1]["patches"][0]["name"], "rattler-two");
+}
+
+#[test]
+fn test_apply_select_from_crate_list() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    project.write_file(
+        "crates.txt",
+        "# crates to patch\nrattler-two\n\nother-crate # trailing comment\nnot-a-dependency\n",
+    );
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &ApplyOptions {
+            select_from: Some(project.dir().join("crates.txt")),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let mut patched: Vec<_> = doc["patch"]["crates-io"]
+        .as_table()
+        .unwrap()
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    patched.sort();
+    assert_snapshot!(format!("{:?}", patched), @r###"["other-crate", "rattler-two"]"###);
+}
+
+#[test]
+fn test_exit_code_for_no_matching_crates() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    patch_source_cmd()
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--pattern")
+        .arg("nonexistent-*")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_pattern_matching_only_excluded_source_crates() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let excluded = workspace.path().join("experimental/rattler-three");
+    std::fs::create_dir_all(excluded.join("src")).unwrap();
+    std::fs::write(
+        excluded.join("Cargo.toml"),
+        "[package]\nname = \"rattler-three\"\nversion = \"3.0.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    std::fs::write(excluded.join("src/lib.rs"), "").unwrap();
+    let manifest = workspace.read_manifest().replace(
+        "[workspace]\n",
+        "[workspace]\nexclude = [\"experimental\"]\n",
+    );
+    std::fs::write(workspace.manifest_path(), manifest).unwrap();
+
+    let err = apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-th*"),
+    )
+    .unwrap_err();
+    assert_snapshot!(
+        format!("{:?}", err),
+        @r###"NoMatchingCratesExcluded { pattern: "rattler-th*", crates: ["rattler-three"] }"###
+    );
+
+    // Crates that are excluded but don't match stay out of the error
+    let err = apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        Some("nonexistent-*"),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        cargo_patch_source::PatchError::NoMatchingCrates { .. }
+    ));
+}
+
+#[test]
+fn test_cargo_manifest_dir_sets_default_manifest() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    patch_source_cmd()
+        .current_dir(workspace.path())
+        .env("CARGO_MANIFEST_DIR", project.dir())
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--pattern")
+        .arg("rattler-one")
+        .assert()
+        .success();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert!(doc["patch"]["crates-io"].get("rattler-one").is_some());
+    assert!(!workspace.read_manifest().contains("[patch"));
+}
+
+#[test]
+fn test_root_sets_default_manifest_directory() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    // Run from the workspace so the current directory's Cargo.toml would be the wrong one
+    patch_source_cmd()
+        .current_dir(workspace.path())
+        .arg("--root")
+        .arg(project.dir())
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--pattern")
+        .arg("rattler-one")
+        .assert()
+        .success();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert!(doc["patch"]["crates-io"].get("rattler-one").is_some());
+
+    patch_source_cmd()
+        .current_dir(workspace.path())
+        .arg("remove")
+        .arg("--root")
+        .arg(project.dir())
+        .assert()
+        .success();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert!(doc.get("patch").is_none());
+}
+
+#[test]
+fn test_doctor_on_healthy_project() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+
+    let output = patch_source_cmd()
+        .arg("doctor")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .arg("--format")
+        .arg("json")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let report: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    let summary: Vec<_> = report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|check| format!("{} {}", check["passed"], check["name"].as_str().unwrap()))
+        .collect();
+    assert_snapshot!(summary.join("\n"), @r###"
+    true manifest parses
+    true metadata matches patch table
+    true patch sources resolve
+    true patched versions satisfy requirements
+    true no unused patches
+    true no crate patched under several keys
+    "###);
+
+    // Dropping a patched dependency leaves its patch unused
+    let manifest = project
+        .read_manifest()
+        .replace("rattler-two = \"2.0.0\"\n", "");
+    project.write_manifest(&manifest);
+    patch_source_cmd()
+        .arg("doctor")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_duplicate_patches_across_keys_are_reported() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    project.append_manifest(
+        r#"
+[patch.crates-io]
+other-crate = { path = "/custom/other-crate" }
+
+[patch."https://github.com/org/other"]
+other-crate = { git = "https://github.com/org/other" }
+"#,
+    );
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .arg("-q")
+        .arg("apply")
+        .arg("--pattern")
+        .arg("other-crate")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert_snapshot!(String::from_utf8(output.stderr).unwrap(), @r###"
+    warning: Skipping other-crate because a patch entry already exists
+    warning: No crates to patch after skipping existing patch entries
+    warning: other-crate is patched under several keys (crates-io, https://github.com/org/other); the patches may conflict
+    "###);
+
+    let output = patch_source_cmd()
+        .arg("doctor")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .arg("--format")
+        .arg("json")
+        .assert()
+        .code(1)
+        .get_output()
+        .stdout
+        .clone();
+    let report: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    let check = report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|check| check["name"] == "no crate patched under several keys")
+        .unwrap();
+    assert_eq!(
+        check["problems"],
+        serde_json::json!([
+            "other-crate is patched under [patch.crates-io], [patch.https://github.com/org/other]"
+        ])
+    );
+}
+
+#[test]
+fn test_validate_source() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .args(["validate", "--pattern", "rattler-*", "--path"])
+        .arg(workspace.path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    // The crates are the output, the summary is progress
+    assert_snapshot!(String::from_utf8(output.stdout).unwrap(), @r###"
+      rattler-one 1.0.0
+      rattler-two 2.0.0
+    "###);
+    let stderr = normalize_manifest(&String::from_utf8(output.stderr).unwrap(), Some(&workspace));
+    assert_snapshot!(stderr, @"<workspace> provides 2 matching crates");
+
+    // A pattern matching nothing in the source fails like apply does
+    patch_source_cmd()
+        .args(["validate", "--pattern", "missing-*", "--path"])
+        .arg(workspace.path())
+        .assert()
+        .code(2);
+
+    // A directory without a workspace manifest is not a usable source
+    patch_source_cmd()
+        .args(["validate", "--path"])
+        .arg(workspace.path().join("crates"))
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_exit_code_for_no_patches_found() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+
+    patch_source_cmd()
+        .arg("remove")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn test_exit_code_for_parse_error() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+    project.write_manifest("[package\nname = ");
+
+    patch_source_cmd()
+        .arg("remove")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .code(5);
+}
+
+#[test]
+fn test_init_creates_config_and_refuses_overwrite() {
+    let fixture = TestFixture::new();
+    let project = rattler_project(&fixture);
+    let config_path = config_path_for(project.manifest_path());
+
+    let created = init_config(project.manifest_path(), false).unwrap();
+    assert_eq!(created, config_path);
+    let template = std::fs::read_to_string(&config_path).unwrap();
+    assert!(template.contains("[presets.local]"));
+
+    std::fs::write(&config_path, "# my presets\n").unwrap();
+
+    let err = init_config(project.manifest_path(), false).unwrap_err();
+    assert!(matches!(
+        err,
+        cargo_patch_source::PatchError::ConfigFileExists { .. }
+    ));
+    assert_eq!(
+        std::fs::read_to_string(&config_path).unwrap(),
+        "# my presets\n"
+    );
+
+    init_config(project.manifest_path(), true).unwrap();
+    assert_eq!(std::fs::read_to_string(&config_path).unwrap(), template);
+}
+
+#[test]
+fn test_apply_with_preset() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    project.write_file(
+        ".cargo-patch-source.toml",
+        &format!(
+            "[presets.local]\npath = {:?}\npattern = \"rattler-one\"\n",
+            workspace.path().display().to_string()
+        ),
+    );
+
+    patch_source_cmd()
+        .arg("apply")
+        .arg("--preset")
+        .arg("local")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let patched: Vec<_> = doc["patch"]["crates-io"]
+        .as_table()
+        .unwrap()
+        .iter()
+        .map(|(k, _)| k.to_string())
+        .collect();
+    assert_eq!(patched, vec!["rattler-one"]);
+}
+
+#[test]
+fn test_apply_print_effective_config() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let before = project.read_manifest();
+    project.write_file(
+        ".cargo-patch-source.toml",
+        &format!(
+            "[presets.local]\npath = {:?}\npattern = \"rattler-one\"\n",
+            workspace.path().display().to_string()
+        ),
+    );
+
+    let effective_config = |pattern: Option<&str>| {
+        let mut cmd = patch_source_cmd();
+        cmd.env_remove("RUST_LOG")
+            // --root wins over the directory cargo would set for build scripts
+            .env("CARGO_MANIFEST_DIR", workspace.path())
+            .arg("--root")
+            .arg(project.dir())
+            .arg("apply")
+            .arg("--preset")
+            .arg("local")
+            .arg("--print-effective-config");
+        if let Some(pattern) = pattern {
+            cmd.arg("--pattern").arg(pattern);
+        }
+        let output = cmd.assert().success().get_output().clone();
+        normalize_manifest(&String::from_utf8_lossy(&output.stdout), Some(&workspace))
+    };
+
+    // The preset fills in what the command line leaves out
+    let from_preset = effective_config(None);
+    assert!(from_preset.contains("source: path: <workspace>\n"));
+    assert!(from_preset.contains("patterns: rattler-one\n"));
+    assert!(from_preset.contains(&format!(
+        "manifest: {}\n",
+        project.manifest_path().display()
+    )));
+    assert!(from_preset.contains("merge-strategy: skip\n"));
+    assert!(from_preset.contains("patch key: crates-io\n"));
+
+    // Command line values take precedence over the preset
+    let overridden = effective_config(Some("rattler-two"));
+    assert!(overridden.contains("source: path: <workspace>\n"));
+    assert!(overridden.contains("patterns: rattler-two\n"));
+
+    assert_eq!(project.read_manifest(), before);
+}
+
+#[test]
+fn test_effective_config_patch_key_follows_the_dependencies() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture
+        .project("registry-index-project")
+        .dep(
+            "rattler-one",
+            DependencySpec::version("1.0.0")
+                .registry_index("https://registry.example.com/git-index"),
+        )
+        .build();
+    let manifests = [project.manifest_path().to_path_buf()];
+
+    // A git source is still written under the registry its dependencies come from
+    let sources = [
+        PatchSource::Git {
+            url: "https://github.com/fork/rattler".to_string(),
+            reference: None,
+        },
+        PatchSource::local_path(workspace.path().to_path_buf()),
+    ];
+    for source in &sources {
+        let config = format_effective_config(source, &manifests, &ApplyOptions::default()).unwrap();
+        assert!(
+            config.contains("patch key: https://registry.example.com/git-index\n"),
+            "{}",
+            config
+        );
+    }
+}
+
+#[test]
+fn test_strict_fails_on_version_mismatches() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = fixture
+        .project("outdated-project")
+        .dep_version("rattler-one", "0.9")
+        .dep_version("rattler-two", "1.5")
+        .dep_version("other-crate", "3.0.0")
+        .build();
+    let content_before = project.read_manifest();
+
+    let options = ApplyOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let err = apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &options,
+    )
+    .unwrap_err();
+
+    assert_snapshot!(
+        err.to_string(),
+        @"Source versions do not satisfy the dependency requirements of: rattler-one, rattler-two"
+    );
+    assert_eq!(project.read_manifest(), content_before);
+
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &ApplyOptions::default(),
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert_eq!(doc["dependencies"]["rattler-one"].as_str(), Some("1.0.0"));
+    assert_eq!(doc["dependencies"]["rattler-two"].as_str(), Some("2.0.0"));
+}
+
+#[test]
+fn test_apply_git_without_pattern_clones_to_discover_crates() {
+    let fixture = TestFixture::new();
+    let workspace = fixture
+        .workspace("git-source")
+        .member("rattler-one", "1.0.0")
+        .member("rattler-two", "2.0.0")
+        .build();
+    workspace.init_git();
+    let project = rattler_project(&fixture);
+
+    apply_patches(
+        PatchSource::git(workspace.file_url(), None),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let mut patched: Vec<_> = doc["patch"]["crates-io"]
+        .as_table()
+        .unwrap()
+        .iter()
+        .map(|(k, _)| k.to_string())
+        .collect();
+    patched.sort();
+    assert_eq!(patched, ["rattler-one", "rattler-two"]);
+}
+
+#[test]
+fn test_apply_file_url_uses_the_local_checkout() {
+    let fixture = TestFixture::new();
+    // Not a git repository, so this only works without cloning
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    apply_patches(
+        PatchSource::git(workspace.file_url(), None),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let entry = doc["patch"]["crates-io"]["rattler-one"].to_string();
+    assert_eq!(
+        normalize_manifest(entry.trim(), Some(&workspace)),
+        r#"{ path = "<workspace>/crates/rattler-one" }"#
+    );
+}
+
+#[test]
+fn test_apply_records_the_source_revision() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let head = workspace.init_git();
+    let project = rattler_project(&fixture);
+    let manifest_path = project.manifest_path().to_path_buf();
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(manifest_path.clone()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert_eq!(
+        doc["package"]["metadata"]["cargo-patch-source"]["source-revision"].as_str(),
+        Some(head.as_str())
+    );
+
+    remove_patches(Some(manifest_path)).unwrap();
+    assert!(!project.read_manifest().contains("source-revision"));
+}
+
+#[test]
+fn test_apply_git_verify_source_crates() {
+    let fixture = TestFixture::new();
+    let workspace = fixture
+        .workspace("git-source")
+        .member("rattler-one", "1.0.0")
+        .build();
+    workspace.init_git();
+    let project = rattler_project(&fixture);
+
+    let options = ApplyOptions {
+        patterns: vec!["rattler-*".to_string()],
+        verify_source_crates: true,
+        ..Default::default()
+    };
+    let err = apply_patches_with_options(
+        PatchSource::git(workspace.file_url(), None),
+        Some(project.manifest_path().to_path_buf()),
+        &options,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        cargo_patch_source::PatchError::SourceCratesNotFound { ref crates, .. } if crates == &["rattler-two"]
+    ));
+
+    let options = ApplyOptions {
+        patterns: vec!["rattler-one".to_string()],
+        ..options
+    };
+    apply_patches_with_options(
+        PatchSource::git(workspace.file_url(), None),
+        Some(project.manifest_path().to_path_buf()),
+        &options,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_apply_git_rev_outside_shallow_history() {
+    let fixture = TestFixture::new();
+    let workspace = fixture
+        .workspace("git-source")
+        .member("rattler-one", "1.0.0")
+        .build();
+    let first_commit = workspace.init_git();
+    workspace.commit_all("second");
+    let project = rattler_project(&fixture);
+
+    let source = PatchSource::git(
+        workspace.file_url(),
+        Some(GitReference::Rev(first_commit.clone())),
+    );
+    let err = apply_patches(
+        source.clone(),
+        Some(project.manifest_path().to_path_buf()),
+        None,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        cargo_patch_source::PatchError::GitRevNotInShallowHistory { depth: 1, .. }
+    ));
+
+    let options = ApplyOptions {
+        git_clone: GitCloneOptions::new(1, true),
+        ..Default::default()
+    };
+    apply_patches_with_options(
+        source,
+        Some(project.manifest_path().to_path_buf()),
+        &options,
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let entry = doc["patch"]["crates-io"]["rattler-one"].to_string();
+    assert!(entry.contains(&first_commit));
+}
+
+fn archive_workspace_tar_gz(workspace: &Workspace, archive_path: &std::path::Path) {
+    let file = std::fs::File::create(archive_path).unwrap();
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    builder
+        .append_dir_all("mock-workspace", workspace.path())
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+fn archive_workspace_zip(workspace: &Workspace, archive_path: &std::path::Path) {
+    let file = std::fs::File::create(archive_path).unwrap();
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default();
+    let mut pending = vec![workspace.path().to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let relative = path.strip_prefix(workspace.path()).unwrap();
+            let name = relative.display().to_string().replace('\\', "/");
+            if path.is_dir() {
+                zip.add_directory(name, options).unwrap();
+                pending.push(path);
+            } else {
+                zip.start_file(name, options).unwrap();
+                std::io::Write::write_all(&mut zip, &std::fs::read(&path).unwrap()).unwrap();
+            }
+        }
+    }
+    zip.finish().unwrap();
+}
+
+fn patch_paths_relative_to(project: &Project) -> Vec<String> {
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    let project_dir = project.dir().display().to_string().replace('\\', "/");
+    let mut entries: Vec<_> = doc["patch"]["crates-io"]
+        .as_table()
+        .unwrap()
+        .iter()
+        .map(|(name, value)| {
+            let path = value["path"].as_str().unwrap();
+            format!("{} = {}", name, path.replace(&project_dir, "<project>"))
+        })
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn test_apply_from_tar_gz_archive() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let archive_path = workspace.path().with_file_name("rattler-src.tar.gz");
+    archive_workspace_tar_gz(&workspace, &archive_path);
+
+    apply_patches(
+        PatchSource::archive(archive_path),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+
+    let entries = patch_paths_relative_to(&project);
+    assert_snapshot!(entries.join("\n"), @r###"
+    rattler-one = <project>/target/patch-source/rattler-src/mock-workspace/crates/rattler-one
+    rattler-two = <project>/target/patch-source/rattler-src/mock-workspace/crates/rattler-two
+    "###);
+    assert!(project
+        .dir()
+        .join("target/patch-source/rattler-src/mock-workspace/crates/rattler-one/Cargo.toml")
+        .exists());
+
+    // No scratch directories are left behind
+    let leftovers: Vec<_> = std::fs::read_dir(project.dir().join("target/patch-source"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(leftovers, ["rattler-src"]);
+}
+
+#[test]
+fn test_apply_from_archive_extracts_into_the_configured_target_dir() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    project.write_file(
+        ".cargo/config.toml",
+        "[build]\ntarget-dir = \"build-out\"\n",
+    );
+    let archive_path = workspace.path().with_file_name("rattler-src.zip");
+    archive_workspace_zip(&workspace, &archive_path);
+
+    apply_patches(
+        PatchSource::archive(archive_path),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-one"),
+    )
+    .unwrap();
+
+    let entries = patch_paths_relative_to(&project);
+    assert_snapshot!(entries.join("\n"), @"rattler-one = <project>/build-out/patch-source/rattler-src/crates/rattler-one");
+    assert!(!project.dir().join("target").exists());
+}
+
+#[test]
+fn test_apply_dry_run_from_archive_writes_nothing() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let original = project.read_manifest();
+    let archive_path = workspace.path().with_file_name("rattler-src.tar.gz");
+    archive_workspace_tar_gz(&workspace, &archive_path);
+
+    let plan = apply_patches_with_options(
+        PatchSource::archive(archive_path),
+        Some(project.manifest_path().to_path_buf()),
+        &ApplyOptions {
+            patterns: vec!["rattler-*".to_string()],
+            dry_run: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(plan.patched, 2);
+    assert_eq!(project.read_manifest(), original);
+    assert!(!project.dir().join("target/patch-source").exists());
+}
+
+#[test]
+fn test_apply_from_zip_archive() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+    let archive_path = workspace.path().with_file_name("rattler-src.zip");
+    archive_workspace_zip(&workspace, &archive_path);
+
+    apply_patches(
+        PatchSource::archive(archive_path),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-one"),
+    )
+    .unwrap();
+
+    let entries = patch_paths_relative_to(&project);
+    assert_snapshot!(entries.join("\n"), @"rattler-one = <project>/target/patch-source/rattler-src/crates/rattler-one");
+}
+
+#[test]
+fn test_apply_offline_and_frozen() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    // Generate the lockfile up front, --frozen refuses to create it
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-one"),
+    )
+    .unwrap();
+
+    let options = ApplyOptions {
+        patterns: vec!["rattler-*".to_string()],
+        metadata: MetadataOptions {
+            offline: true,
+            frozen: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    apply_patches_with_options(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        &options,
+    )
+    .unwrap();
+
+    let doc: DocumentMut = project.read_manifest().parse().unwrap();
+    assert_eq!(doc["patch"]["crates-io"].as_table().unwrap().len(), 2);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_apply_forwards_cargo_config_to_metadata() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    // Record `cargo metadata` calls before handing them to cargo
+    let log = project.dir().join("cargo-metadata.log");
+    let wrapper = project.dir().join("cargo-wrapper.sh");
+    std::fs::write(
+        &wrapper,
+        format!(
+            "#!/bin/sh\nif [ \"$1\" = metadata ]; then echo \"$@\" >> {}; fi\nexec \"$REAL_CARGO\" \"$@\"\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&wrapper, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    patch_source_cmd()
+        .env("CARGO", &wrapper)
+        .env("REAL_CARGO", env!("CARGO"))
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--cargo-config")
+        .arg("net.git-fetch-with-cli=true")
+        .arg("--cargo-config")
+        .arg("net.retry=5")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success();
+
+    let log = std::fs::read_to_string(&log).unwrap();
+    assert!(!log.is_empty());
+    for call in log.lines() {
+        assert!(
+            call.contains("--config net.git-fetch-with-cli=true --config net.retry=5"),
+            "{}",
+            call
+        );
+    }
+    assert!(project.read_manifest().contains("[patch.crates-io]"));
+}
+
+/// Writer handed to a test subscriber so log output can be inspected
+#[derive(Clone, Default)]
+struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl CapturedLogs {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[test]
+fn test_apply_emits_tracing_events() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    project.append_manifest(
+        r#"
+[patch.crates-io]
+rattler-two = { path = "/custom/user/path" }
+"#,
+    );
+
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_writer(move || writer.clone())
+        .event_format(cargo_patch_source::logging::CliFormatter)
+        .finish();
+
+    tracing::subscriber::with_default(subscriber, || {
+        apply_patches(
+            PatchSource::local_path(workspace.path().to_path_buf()),
+            Some(project.manifest_path().to_path_buf()),
+            Some("rattler-*"),
+        )
+        .unwrap();
+    });
+
+    let logs = normalize_elapsed(&normalize_manifest(&logs.contents(), Some(&workspace)));
+    let manifest = project.manifest_path().to_string_lossy().to_string();
+    let project_dir = project.dir().to_string_lossy().to_string();
+    let logs = logs
+        .replace(&manifest, "<project>/Cargo.toml")
+        .replace(&project_dir, "<project>");
+    assert_snapshot!(logs, @r###"
+    warning: Skipping rattler-two because a patch entry already exists
+    warning: Patch paths for rattler-one point outside <project>; they won't work for other contributors
+      Patching rattler-one 1.0.0 -> <workspace>/crates/rattler-one
+    Patched 1 crate under [patch.crates-io] (1 skipped) in <elapsed>.
+    "###);
+}
+
+#[test]
+fn test_apply_json_report_includes_elapsed_time_and_counts() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .arg("apply")
+        .arg("--format")
+        .arg("json")
+        .arg("--pattern")
+        .arg("rattler-*")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["schema_version"], 1);
+    assert!(report["elapsed_ms"].is_u64());
+    assert_eq!(report["patched"], 2);
+    assert_eq!(report["skipped"], 0);
+    assert_eq!(report["patches"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_remove_json_report_is_the_only_stdout() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    apply_patches(
+        PatchSource::local_path(workspace.path().to_path_buf()),
+        Some(project.manifest_path().to_path_buf()),
+        Some("rattler-*"),
+    )
+    .unwrap();
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .arg("remove")
+        .arg("--format")
+        .arg("json")
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Progress lines go to stderr, so stdout parses as a single document
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["schema_version"], 1);
+    assert_eq!(report["dry_run"], false);
+    assert_eq!(report["removed_patches"].as_array().unwrap().len(), 2);
+    assert_eq!(report["restored_versions"].as_array().unwrap().len(), 2);
+    assert!(!project.read_manifest().contains("[patch"));
+}
+
+#[test]
+fn test_summary_only_prints_just_the_summary() {
+    let fixture = TestFixture::new();
+    let workspace = rattler_workspace(&fixture);
+    let project = rattler_project(&fixture);
+
+    let output = patch_source_cmd()
+        .env_remove("RUST_LOG")
+        .arg("--summary-only")
+        .arg("apply")
+        .arg("--path")
+        .arg(workspace.path())
+        .arg("--manifest-path")
+        .arg(project.manifest_path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    assert!(output.stdout.is_empty());
+    let stderr = normalize_elapsed(&String::from_utf8(output.stderr).unwrap())
+        .replace(&project.dir().to_string_lossy().to_string(), "<project>");
+    assert_snapshot!(stderr, @r###"
+    warning: Patch paths for other-crate, rattler-one, rattler-two point outside <project>; they won't work for other contributors
+    Patched 3 crates under [patch.crates-io] (0 skipped) in <elapsed>.
+    "###);
 }
@@ -1,6 +1,7 @@
 use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use tempfile::TempDir;
 use toml_edit::{self, Item, Table};
 
@@ -12,6 +13,9 @@ pub struct DependencySpec {
     branch: Option<String>,
     tag: Option<String>,
     rev: Option<String>,
+    registry: Option<String>,
+    registry_index: Option<String>,
+    package: Option<String>,
 }
 
 impl DependencySpec {
@@ -23,6 +27,9 @@ impl DependencySpec {
             branch: None,
             tag: None,
             rev: None,
+            registry: None,
+            registry_index: None,
+            package: None,
         }
     }
 
@@ -34,6 +41,23 @@ impl DependencySpec {
             branch: None,
             tag: None,
             rev: None,
+            registry: None,
+            registry_index: None,
+            package: None,
+        }
+    }
+
+    pub fn path(path: impl Into<String>) -> Self {
+        Self {
+            version: None,
+            path: Some(path.into()),
+            git: None,
+            branch: None,
+            tag: None,
+            rev: None,
+            registry: None,
+            registry_index: None,
+            package: None,
         }
     }
 
@@ -42,12 +66,30 @@ impl DependencySpec {
         self
     }
 
+    pub fn registry(mut self, registry: impl Into<String>) -> Self {
+        self.registry = Some(registry.into());
+        self
+    }
+
+    pub fn registry_index(mut self, index: impl Into<String>) -> Self {
+        self.registry_index = Some(index.into());
+        self
+    }
+
+    pub fn package(mut self, package: impl Into<String>) -> Self {
+        self.package = Some(package.into());
+        self
+    }
+
     fn to_item(&self) -> Item {
         let complex = self.path.is_some()
             || self.git.is_some()
             || self.branch.is_some()
             || self.tag.is_some()
             || self.rev.is_some()
+            || self.registry.is_some()
+            || self.registry_index.is_some()
+            || self.package.is_some()
             || self
                 .version
                 .as_ref()
@@ -60,6 +102,9 @@ impl DependencySpec {
         }
 
         let mut table = toml_edit::InlineTable::new();
+        if let Some(package) = &self.package {
+            table.insert("package", package.as_str().into());
+        }
         if let Some(version) = &self.version {
             table.insert("version", version.as_str().into());
         }
@@ -78,6 +123,12 @@ impl DependencySpec {
         if let Some(rev) = &self.rev {
             table.insert("rev", rev.as_str().into());
         }
+        if let Some(registry) = &self.registry {
+            table.insert("registry", registry.as_str().into());
+        }
+        if let Some(index) = &self.registry_index {
+            table.insert("registry-index", index.as_str().into());
+        }
 
         Item::Value(toml_edit::Value::InlineTable(table))
     }
@@ -118,6 +169,7 @@ struct MemberSpec {
     name: String,
     version: String,
     edition: String,
+    license: Option<String>,
 }
 
 impl<'a> WorkspaceBuilder<'a> {
@@ -141,10 +193,18 @@ impl<'a> WorkspaceBuilder<'a> {
             name: name_str,
             version: version_str,
             edition: "2021".to_string(),
+            license: None,
         });
         self
     }
 
+    /// Set the license of the member added last
+    pub fn license(mut self, license: impl Into<String>) -> Self {
+        let member = self.members.last_mut().expect("add a member first");
+        member.license = Some(license.into());
+        self
+    }
+
     pub fn build(self) -> Workspace {
         let workspace_path = self.fixture.root().join(&self.name);
         fs::create_dir(&workspace_path).expect("create workspace root");
@@ -187,10 +247,13 @@ impl<'a> WorkspaceBuilder<'a> {
             let crate_dir = crates_dir.join(&member.name);
             fs::create_dir(&crate_dir).expect("create crate dir");
 
-            let manifest = format!(
+            let mut manifest = format!(
                 "[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"{}\"\n",
                 member.name, member.version, member.edition
             );
+            if let Some(license) = &member.license {
+                manifest.push_str(&format!("license = \"{}\"\n", license));
+            }
             fs::write(crate_dir.join("Cargo.toml"), manifest).expect("write crate manifest");
 
             let src_dir = crate_dir.join("src");
@@ -222,6 +285,55 @@ impl Workspace {
     pub fn read_manifest(&self) -> String {
         fs::read_to_string(&self.manifest_path).expect("read workspace manifest")
     }
+
+    /// Turn the workspace into a git repository with everything committed, returning HEAD
+    pub fn init_git(&self) -> String {
+        git(&self.root, &["init", "--quiet", "--initial-branch", "main"]);
+        self.commit_all("initial")
+    }
+
+    /// Commit all files in the workspace, returning the new HEAD
+    pub fn commit_all(&self, message: &str) -> String {
+        git(&self.root, &["add", "--all"]);
+        git(
+            &self.root,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "--quiet",
+                "--allow-empty",
+                "-m",
+                message,
+            ],
+        );
+        git(&self.root, &["rev-parse", "HEAD"])
+    }
+
+    /// A `file://` URL for the workspace, which git clones like a remote
+    pub fn file_url(&self) -> String {
+        format!(
+            "file://{}",
+            self.root.display().to_string().replace('\\', "/")
+        )
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .expect("run git");
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
 }
 
 pub struct ProjectBuilder<'a> {
@@ -297,6 +409,18 @@ impl Project {
         &self.manifest_path
     }
 
+    pub fn dir(&self) -> &Path {
+        self.manifest_path.parent().expect("manifest has a parent")
+    }
+
+    pub fn write_file(&self, relative: impl AsRef<Path>, contents: &str) {
+        let path = self.dir().join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("create parent dirs");
+        }
+        fs::write(path, contents).expect("write file");
+    }
+
     pub fn read_manifest(&self) -> String {
         fs::read_to_string(&self.manifest_path).expect("read manifest")
     }
@@ -310,4 +434,24 @@ impl Project {
         existing.push_str(contents);
         self.write_manifest(&existing);
     }
+
+    /// Turn the project into a git repository with everything committed
+    pub fn init_git(&self) {
+        let dir = self.dir();
+        git(dir, &["init", "--quiet", "--initial-branch", "main"]);
+        git(dir, &["add", "--all"]);
+        git(
+            dir,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "--quiet",
+                "-m",
+                "initial",
+            ],
+        );
+    }
 }